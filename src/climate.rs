@@ -0,0 +1,245 @@
+use std::f32::consts::{PI, TAU};
+
+use crate::{
+    constants,
+    geometry::{self, Line, Vertex},
+    grid::Grid,
+    terrain, utils,
+};
+
+pub const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September", "October",
+    "November", "December",
+];
+
+#[derive(Copy, Clone, Debug)]
+pub struct ClimateSettings {
+    /// Degrees, how far the sun moves north and south over the year.
+    pub(crate) axial_tilt: f32,
+    /// Mean sea level temperatures in °C when the sun is overhead and at the poles.
+    pub(crate) equator_temperature: f32,
+    pub(crate) pole_temperature: f32,
+    /// °C lost per kilometre of elevation.
+    pub(crate) lapse_rate: f32,
+    /// Sea water freezes below this, in °C.
+    pub(crate) freezing_point: f32,
+    /// Added to every temperature, to try out warmer and colder worlds.
+    pub(crate) temperature_offset: f32,
+}
+
+impl Default for ClimateSettings {
+    fn default() -> Self {
+        ClimateSettings {
+            axial_tilt: 23.44,
+            equator_temperature: 28.0,
+            pole_temperature: -30.0,
+            lapse_rate: 6.5,
+            freezing_point: -1.8,
+            temperature_offset: 0.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClimateView {
+    Temperature,
+    Precipitation,
+}
+
+/// Twelve months of temperature, precipitation and sea ice for every cell.
+/// Temperatures are stored as whole °C, precipitation in steps of `PRECIPITATION_STEP` mm
+/// and sea ice as one bit per cell, which keeps a year at about 25 bytes per cell.
+pub struct Climate {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    temperature: Vec<i8>,
+    precipitation: Vec<u8>,
+    sea_ice: Vec<u64>,
+}
+
+impl Climate {
+    fn cells(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+
+    /// Mean temperature in °C.
+    pub fn temperature(&self, month: usize, x: u32, y: u32) -> f32 {
+        self.temperature[month * self.cells() + (y * self.width + x) as usize] as f32
+    }
+
+    /// Precipitation in mm over the month.
+    pub fn precipitation(&self, month: usize, x: u32, y: u32) -> f32 {
+        self.precipitation[month * self.cells() + (y * self.width + x) as usize] as f32 * constants::PRECIPITATION_STEP
+    }
+
+    pub fn sea_ice(&self, month: usize, x: u32, y: u32) -> bool {
+        let bit = month * self.cells() + (y * self.width + x) as usize;
+        self.sea_ice[bit / 64] & (1 << (bit % 64)) != 0
+    }
+
+    /// Share of the sea covered by ice in a month.
+    pub fn sea_ice_fraction(&self, month: usize, mask: &Grid<bool>) -> f32 {
+        let mut sea = 0;
+        let mut ice = 0;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !mask.get(x, y) {
+                    sea += 1;
+                    if self.sea_ice(month, x, y) {
+                        ice += 1;
+                    }
+                }
+            }
+        }
+        if sea == 0 {
+            return 0.0;
+        }
+        ice as f32 / sea as f32
+    }
+
+    /// Mean over the year of one variable, precipitation as mm per month.
+    pub fn annual_mean(&self, view: ClimateView) -> Grid<f32> {
+        let mut grid = Grid::new(self.width, self.height, 0.0);
+        for month in 0..MONTHS.len() {
+            let month_grid = self.month_grid(month, view);
+            for i in 0..grid.len() {
+                grid.data[i] += month_grid.data[i] / MONTHS.len() as f32;
+            }
+        }
+        grid
+    }
+
+    /// One month of one variable as a raster.
+    pub fn month_grid(&self, month: usize, view: ClimateView) -> Grid<f32> {
+        let mut grid = Grid::new(self.width, self.height, 0.0);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let value = match view {
+                    ClimateView::Temperature => self.temperature(month, x, y),
+                    ClimateView::Precipitation => self.precipitation(month, x, y),
+                };
+                grid.set(x, y, value);
+            }
+        }
+        grid
+    }
+}
+
+/// Latitude of the sun at midday in the middle of a month, in radians.
+pub fn solar_declination(month: usize, axial_tilt: f32) -> f32 {
+    // the northern summer solstice falls about two thirds into June
+    let day = month as f32 * 30.44 + 15.0;
+    axial_tilt.to_radians() * (TAU * (day - 80.0) / 365.25).sin()
+}
+
+/// Works out the yearly cycle from latitude, elevation and distance to the sea.
+/// The base image is treated as spanning the whole globe, north at the top.
+pub fn simulate_climate(mask: &Grid<bool>, heightmap: Option<&Grid<f32>>, settings: &ClimateSettings) -> Climate {
+    let width = mask.width;
+    let height = mask.height;
+    let cells = mask.len();
+    let distance = terrain::distance_to_coast(mask);
+    let heightmap = heightmap.filter(|h| h.width == width && h.height == height);
+    let moisture_reach = constants::MOISTURE_REACH * width.max(height) as f32;
+
+    let mut temperature = vec![0i8; cells * 12];
+    let mut precipitation = vec![0u8; cells * 12];
+    let mut sea_ice = vec![0u64; (cells * 12).div_ceil(64)];
+
+    for (month, _) in MONTHS.iter().enumerate() {
+        let declination = solar_declination(month, settings.axial_tilt);
+        for y in 0..height {
+            let latitude = utils::row_latitude(y as f32 + 0.5, height).to_radians();
+            let annual = band_temperature(latitude, 0.0, settings);
+            let seasonal = band_temperature(latitude, declination, settings);
+            let rain_band = rain_band(latitude, declination);
+
+            for x in 0..width {
+                let i = mask.index(x, y);
+                let land = mask.data[i];
+
+                // the sea evens out the seasons
+                let swing = if land { 1.0 } else { constants::OCEAN_SEASONALITY };
+                let mut t = annual + (seasonal - annual) * swing + settings.temperature_offset;
+                if let Some(heightmap) = heightmap {
+                    t -= settings.lapse_rate * heightmap.data[i].max(0.0) / 1000.0;
+                }
+
+                let moisture = if land { (-distance.data[i] / moisture_reach).exp() } else { 1.0 };
+                let p = constants::MAX_PRECIPITATION * rain_band * (0.3 + 0.7 * moisture);
+
+                let cell = month * cells + i;
+                temperature[cell] = t.round().clamp(i8::MIN as f32, i8::MAX as f32) as i8;
+                precipitation[cell] = (p / constants::PRECIPITATION_STEP).round().clamp(0.0, 255.0) as u8;
+                if !land && t < settings.freezing_point {
+                    sea_ice[cell / 64] |= 1 << (cell % 64);
+                }
+            }
+        }
+    }
+
+    Climate { width, height, temperature, precipitation, sea_ice }
+}
+
+// sea level temperature at a latitude with the sun overhead at `declination`
+fn band_temperature(latitude: f32, declination: f32, settings: &ClimateSettings) -> f32 {
+    let from_sun = ((latitude - declination).abs() / PI * 2.0).min(1.0);
+    // flattened so the mid-latitudes stay temperate and the cold is packed near the poles
+    let warmth = (from_sun * PI / 2.0).cos().max(0.0).sqrt();
+    settings.pole_temperature + (settings.equator_temperature - settings.pole_temperature) * warmth
+}
+
+// 0..1 share of the wettest rainfall: the tropical rain belt follows the sun,
+// the subtropics are dry and the mid-latitude storm tracks wet again
+fn rain_band(latitude: f32, declination: f32) -> f32 {
+    let lat = (latitude - declination * 0.5).to_degrees().abs();
+    let tropics = (-(lat / 12.0).powi(2)).exp();
+    let storms = 0.6 * (-((lat - 55.0) / 15.0).powi(2)).exp();
+    let polar = 0.1;
+    (tropics + storms + polar).min(1.0)
+}
+
+/// Ice that never melts: glaciers on land too cold to thaw even in the warmest month,
+/// and pack ice on sea that stays frozen all year, with sea that freezes over in winter.
+pub struct IceCover {
+    pub(crate) glacier_cells: usize,
+    pub(crate) glacier_lines: Vec<Line>,
+    pub(crate) glacier_fill: Vec<Vertex>,
+    pub(crate) pack_ice_cells: usize,
+    pub(crate) pack_ice_lines: Vec<Line>,
+    pub(crate) pack_ice_fill: Vec<Vertex>,
+    pub(crate) seasonal_ice_fill: Vec<Vertex>,
+}
+
+pub fn ice_cover(climate: &Climate, mask: &Grid<bool>) -> IceCover {
+    let width = climate.width;
+    let height = climate.height;
+    let mut glacier = vec![false; climate.cells()];
+    let mut pack_ice = vec![false; climate.cells()];
+    let mut seasonal_ice = vec![false; climate.cells()];
+    for y in 0..height {
+        for x in 0..width {
+            let i = mask.index(x, y);
+            if mask.data[i] {
+                let warmest = (0..MONTHS.len()).map(|m| climate.temperature(m, x, y)).fold(f32::MIN, f32::max);
+                glacier[i] = warmest < constants::GLACIER_TEMPERATURE;
+            } else {
+                let frozen = (0..MONTHS.len()).filter(|m| climate.sea_ice(*m, x, y)).count();
+                pack_ice[i] = frozen == MONTHS.len();
+                seasonal_ice[i] = frozen > 0 && frozen < MONTHS.len();
+            }
+        }
+    }
+
+    let glacier_cells: Vec<usize> = (0..glacier.len()).filter(|i| glacier[*i]).collect();
+    let pack_ice_cells: Vec<usize> = (0..pack_ice.len()).filter(|i| pack_ice[*i]).collect();
+    IceCover {
+        glacier_cells: glacier_cells.len(),
+        glacier_lines: geometry::outline_cells(width, height, &glacier_cells, |i| glacier[i]),
+        glacier_fill: geometry::fill_cells(width, height, |i| glacier[i]),
+        pack_ice_cells: pack_ice_cells.len(),
+        pack_ice_lines: geometry::outline_cells(width, height, &pack_ice_cells, |i| pack_ice[i]),
+        pack_ice_fill: geometry::fill_cells(width, height, |i| pack_ice[i]),
+        seasonal_ice_fill: geometry::fill_cells(width, height, |i| seasonal_ice[i]),
+    }
+}
@@ -0,0 +1,216 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{climate::ClimateView, grid::Grid, info::WorldInfo, terrain};
+
+// colour stops, evenly spaced from the low end of the range to the high end
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+/// Sea in the lower half and land in the upper, so that sea level is in the middle.
+const TERRAIN: [[u8; 3]; 8] = [
+    [10, 30, 110],
+    [40, 90, 180],
+    [120, 180, 230],
+    [60, 140, 70],
+    [170, 190, 100],
+    [160, 120, 70],
+    [130, 110, 100],
+    [250, 250, 250],
+];
+const DIVERGING: [[u8; 3]; 7] = [
+    [33, 102, 172],
+    [103, 169, 207],
+    [209, 229, 240],
+    [247, 247, 247],
+    [253, 219, 199],
+    [239, 138, 98],
+    [178, 24, 43],
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Palette {
+    Viridis,
+    /// Blues below the middle of the range and land colours above it, so sea level
+    /// wants to be there.
+    Terrain,
+    /// Blue through white to red, for values either side of the middle of the range.
+    Diverging,
+}
+
+impl Palette {
+    pub fn name(&self) -> &str {
+        match self {
+            Palette::Viridis => "Viridis",
+            Palette::Terrain => "Terrain",
+            Palette::Diverging => "Diverging",
+        }
+    }
+
+    /// The colour `t` of the way along the palette, clamped to `0.0..=1.0`.
+    pub fn color(&self, t: f32) -> [u8; 3] {
+        let stops: &[[u8; 3]] = match self {
+            Palette::Viridis => &VIRIDIS,
+            Palette::Terrain => &TERRAIN,
+            Palette::Diverging => &DIVERGING,
+        };
+        let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (position as usize).min(stops.len() - 2);
+        let f = position - i as f32;
+        let mut color = [0; 3];
+        for c in 0..3 {
+            color[c] = (stops[i][c] as f32 * (1.0 - f) + stops[i + 1][c] as f32 * f).round() as u8;
+        }
+        color
+    }
+}
+
+/// A raster of the world that can be shown as a colour map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScalarField {
+    Elevation,
+    Temperature,
+    Precipitation,
+    /// Positive on land and negative at sea.
+    DistanceToCoast,
+}
+
+impl ScalarField {
+    pub fn name(&self) -> &str {
+        match self {
+            ScalarField::Elevation => "Elevation",
+            ScalarField::Temperature => "Temperature",
+            ScalarField::Precipitation => "Precipitation",
+            ScalarField::DistanceToCoast => "Distance to Coast",
+        }
+    }
+
+    pub fn unit(&self) -> &str {
+        match self {
+            ScalarField::Elevation => "m",
+            ScalarField::Temperature => "°C",
+            ScalarField::Precipitation => "mm",
+            ScalarField::DistanceToCoast => "px",
+        }
+    }
+
+    /// The palette that suits the field best.
+    pub fn palette(&self) -> Palette {
+        match self {
+            ScalarField::Elevation => Palette::Terrain,
+            ScalarField::Temperature | ScalarField::DistanceToCoast => Palette::Diverging,
+            ScalarField::Precipitation => Palette::Viridis,
+        }
+    }
+
+    /// Whether the world has what the field is made from yet.
+    pub fn is_available(&self, world_info: &WorldInfo) -> bool {
+        match self {
+            ScalarField::Elevation => world_info.heightmap.is_some(),
+            ScalarField::Temperature | ScalarField::Precipitation => world_info.climate.is_some(),
+            ScalarField::DistanceToCoast => world_info.land_mask.is_some(),
+        }
+    }
+
+    /// Whether the field changes from month to month.
+    pub fn is_seasonal(&self) -> bool {
+        matches!(self, ScalarField::Temperature | ScalarField::Precipitation)
+    }
+
+    /// The field for one month, or the mean over the year when `month` is `None`, if the
+    /// world has what it's made from.
+    pub fn grid(&self, world_info: &WorldInfo, month: Option<usize>) -> Option<Grid<f32>> {
+        let climate = |view| {
+            let climate = world_info.climate.as_ref()?;
+            Some(match month {
+                Some(month) => climate.month_grid(month, view),
+                None => climate.annual_mean(view),
+            })
+        };
+        match self {
+            ScalarField::Elevation => world_info.heightmap.clone(),
+            ScalarField::Temperature => climate(ClimateView::Temperature),
+            ScalarField::Precipitation => climate(ClimateView::Precipitation),
+            ScalarField::DistanceToCoast => {
+                let mask = world_info.land_mask.as_ref()?;
+                let mut distance = terrain::distance_to_coast(mask);
+                for (d, land) in distance.data.iter_mut().zip(&mask.data) {
+                    if !land {
+                        *d = -*d;
+                    }
+                }
+                Some(distance)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ColorMapSettings {
+    pub(crate) field: ScalarField,
+    pub(crate) palette: Palette,
+    /// `None` for the mean over the year, for the fields that change with the seasons.
+    pub(crate) month: Option<usize>,
+    /// Stretch the palette over the field's own minimum and maximum rather than `min..max`.
+    pub(crate) auto_range: bool,
+    pub(crate) min: f32,
+    pub(crate) max: f32,
+}
+
+impl Default for ColorMapSettings {
+    fn default() -> Self {
+        ColorMapSettings {
+            field: ScalarField::Elevation,
+            palette: Palette::Terrain,
+            month: None,
+            auto_range: true,
+            min: 0.0,
+            max: 1.0,
+        }
+    }
+}
+
+/// What the colours of a colour map overlay stand for, for its legend.
+#[derive(Clone, Debug)]
+pub struct Legend {
+    pub(crate) title: String,
+    pub(crate) unit: String,
+    pub(crate) palette: Palette,
+    pub(crate) min: f32,
+    pub(crate) max: f32,
+    /// Where the overlay is in `WorldInfo.texture_list`.
+    pub(crate) overlay: usize,
+}
+
+/// The value range the settings ask for on a field. A fitted diverging or terrain palette
+/// is centred on zero when the values cross it, so that white stays at freezing and the
+/// coast colour at sea level.
+pub fn value_range(grid: &Grid<f32>, settings: &ColorMapSettings) -> (f32, f32) {
+    if !settings.auto_range {
+        return (settings.min, settings.max);
+    }
+    let (min, max) = grid.min_max();
+    let centred = matches!(settings.palette, Palette::Diverging | Palette::Terrain);
+    if centred && min < 0.0 && max > 0.0 {
+        let extent = max.max(-min);
+        return (-extent, extent);
+    }
+    (min, max)
+}
+
+/// Every cell in the colour of its value, values outside `min..max` clamped to the ends.
+pub fn colorize(grid: &Grid<f32>, palette: Palette, min: f32, max: f32) -> DynamicImage {
+    let span = if max > min { max - min } else { 1.0 };
+    let image = RgbaImage::from_fn(grid.width, grid.height, |x, y| {
+        let [r, g, b] = palette.color((grid.get(x, y) - min) / span);
+        Rgba([r, g, b, 255])
+    });
+    DynamicImage::ImageRgba8(image)
+}
@@ -1,4 +1,103 @@
 use crate::geometry::{Line, Vertex};
 
 pub(crate) const PRECISION: f32 = 0.0001;
-pub(crate) const UPSCALE: f32 = 1.0;
\ No newline at end of file
+pub(crate) const UPSCALE: f32 = 1.0;
+pub(crate) const NEIGHBOURS_4: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+// heightmap generation
+pub(crate) const NOISE_FREQUENCY: f32 = 8.0;
+pub(crate) const RIDGE_WIDTH: f32 = 0.05;
+pub(crate) const MIN_LAND_ELEVATION: f32 = 1.0;
+
+pub(crate) const NEIGHBOURS_8: [(i32, i32); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+
+// erosion
+pub(crate) const EROSION_BATCH: u32 = 2000;
+pub(crate) const DROPLET_LIFETIME: u32 = 64;
+
+// rivers, widths in world units
+pub(crate) const RIVER_MAX_WIDTH: f32 = 0.02;
+pub(crate) const RIVER_MIN_WIDTH: f32 = 0.002;
+
+// depression filling, in metres
+pub(crate) const FILL_EPSILON: f32 = 0.01;
+pub(crate) const LAKE_DEPTH_TOLERANCE: f32 = 0.5;
+
+// tectonics, sizes relative to the larger side of the world
+pub(crate) const PLATE_WARP_FREQUENCY: f32 = 4.0;
+pub(crate) const PLATE_WARP: f32 = 0.15;
+pub(crate) const BOUNDARY_WIDTH: f32 = 0.03;
+pub(crate) const TRANSFORM_THRESHOLD: f32 = 0.2;
+pub(crate) const CONTINENTAL_CRUST: f32 = 300.0;
+pub(crate) const OCEANIC_CRUST: f32 = -3000.0;
+pub(crate) const CRUST_NOISE: f32 = 600.0;
+
+// procedural land masks
+pub(crate) const WORLDGEN_FREQUENCY: f32 = 4.0;
+
+// simulation thread
+pub(crate) const SCHEDULER_IDLE_MS: u64 = 5;
+
+// climate
+pub(crate) const PRECIPITATION_STEP: f32 = 4.0;
+pub(crate) const MAX_PRECIPITATION: f32 = 300.0;
+pub(crate) const MOISTURE_REACH: f32 = 0.05;
+pub(crate) const OCEAN_SEASONALITY: f32 = 0.4;
+pub(crate) const GLACIER_TEMPERATURE: f32 = 0.0;
+
+// settlements
+pub(crate) const IDEAL_TEMPERATURE: f32 = 15.0;
+pub(crate) const TEMPERATURE_TOLERANCE: f32 = 12.0;
+pub(crate) const IDEAL_PRECIPITATION: f32 = 80.0;
+
+// navigation
+pub(crate) const EARTH_RADIUS_KM: f32 = 6371.0;
+pub(crate) const KM_PER_NAUTICAL_MILE: f32 = 1.852;
+
+// names
+pub(crate) const NAME_ORDER: usize = 3;
+pub(crate) const NAME_ATTEMPTS: u32 = 50;
+pub(crate) const MIN_NAME_LENGTH: usize = 4;
+pub(crate) const MAX_NAME_LENGTH: usize = 12;
+pub(crate) const MIN_NAMED_AREA: usize = 20;
+/// How far a thing can have moved, in world units, and still get its saved name back.
+pub(crate) const NAME_MATCH_DISTANCE: f32 = 0.05;
+
+// relief
+/// Narrowest a cell gets towards the poles, as a share of its width at the equator.
+pub(crate) const MIN_CELL_WIDTH: f32 = 0.05;
+/// Opacity of the hillshade on ground facing straight into the sun.
+pub(crate) const HIGHLIGHT_STRENGTH: f32 = 0.5;
+
+// graticule, in degrees
+/// Length of the pieces meridians and parallels are cut into.
+pub(crate) const GRATICULE_STEP: f32 = 1.0;
+/// Gap between graticule labels and the edge of the map, in points.
+pub(crate) const GRATICULE_LABEL_PADDING: f32 = 4.0;
+/// Least room between the graticule labels along an edge, in points.
+pub(crate) const GRATICULE_LABEL_GAP: f32 = 50.0;
+
+// projections, in degrees
+/// Latitude the Mercator projection is cut off at, north and south.
+pub(crate) const MERCATOR_LIMIT: f32 = 85.0;
+/// How far past the equator the polar stereographic projection reaches.
+pub(crate) const STEREOGRAPHIC_LIMIT: f32 = 10.0;
+/// Longest piece a line is cut into before it is projected, so that it can bend.
+pub(crate) const PROJECTION_STEP: f32 = 1.0;
+
+// map export
+/// Most pixels an exported map can have, past which its scale is brought down.
+pub(crate) const MAX_EXPORT_PIXELS: u64 = 64_000_000;
+
+// line rendering, in screen pixels
+pub(crate) const LINE_MITER_LIMIT: f32 = 4.0;
+
+// textures, in pixels
+pub(crate) const TEXTURE_TILE_SIZE: u32 = 1024;
+/// Longest side of the copies of the world's images made in a projection.
+pub(crate) const PROJECTED_MAX_SIZE: u32 = 8192;
+/// Pixels of a projected copy worked out in one tick of the simulation thread.
+pub(crate) const REPROJECT_PIXELS_PER_TICK: u32 = 1 << 20;
+pub(crate) const GUI_TEXTURE_SIZE: u32 = 2048;
+/// Tiles kept on the GPU after they go out of view.
+pub(crate) const MAX_CACHED_TILES: usize = 96;
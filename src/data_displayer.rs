@@ -1,115 +1,681 @@
 use std::ops::Deref;
+use std::collections::HashMap;
 
 use egui::epaint::TextureManager;
-use glium::{texture::SrgbTexture2d, Display, Frame, Program, Surface};
+use glium::{texture::SrgbTexture2d, uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerWrapFunction}, Display, Frame, Program, Surface, VertexBuffer};
 
 use crate::{
-    geometry::{Shape, Vertex, Line},
+    geometry::{self, Vertex, Line},
+    layers::{Layer, LayerKind},
+    texture_manager::TextureData,
+    lines::{line_vertices, tapered_line_vertices, LineVertex},
     info::{self, WorldInfo, InputInfo},
     utils, constants,
+    tectonics::BoundaryKind,
+    settlements::SettlementKind,
+    roads::RoadClass,
+    graticule,
+    projection::{ProjectionSettings, Projector},
 };
 
+/// Vertex buffers for every geometry layer of the map, so that panning and zooming draw
+/// from the GPU instead of re-tessellating every frame. Colours and opacity are uniforms,
+/// only a change to the world or to a layer's line width needs a rebuild.
+pub struct RenderCache {
+    world_version: Option<u64>,
+    simulations_version: Option<u64>,
+    /// The images the tiles were cut from, see `WorldInfo::raster_key`.
+    raster_key: Option<(u64, Option<(ProjectionSettings, u64)>)>,
+    meshes: HashMap<LayerKind, Vec<Mesh>>,
+    /// Texture tiles on the GPU, by image, pyramid level and position.
+    tiles: HashMap<(TileImage, usize, u32, u32), Tile>,
+    /// Counts the frames drawn, to tell which tiles have been out of view longest.
+    frame: u64,
+    scanline: Option<Scanline>,
+}
+
+/// The scanline fill, with the world version, row and line scale it was built for.
+struct Scanline {
+    key: (u64, f32, f32),
+    buffer: Option<VertexBuffer<Vertex>>,
+}
+
+/// Which of the world's images a tile was cut from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum TileImage {
+    Base,
+    Hillshade,
+    /// An image of `WorldInfo.texture_list`, by its index.
+    Overlay(usize),
+}
+
+struct Tile {
+    texture: SrgbTexture2d,
+    quad: VertexBuffer<Vertex>,
+    last_drawn: u64,
+}
+
+struct Mesh {
+    geometry: Geometry,
+    color: [f32; 4],
+}
+
+enum Geometry {
+    Triangles(VertexBuffer<Vertex>),
+    /// Lines and their width in pixels, before the layer's style scales it.
+    Lines(VertexBuffer<LineVertex>, f32),
+}
+
+impl RenderCache {
+    pub fn new() -> Self {
+        RenderCache {
+            world_version: None,
+            simulations_version: None,
+            raster_key: None,
+            meshes: HashMap::new(),
+            tiles: HashMap::new(),
+            frame: 0,
+            scanline: None,
+        }
+    }
+
+    fn update(&mut self, dis: &Display, world_info: &WorldInfo) {
+        let raster_key = world_info.raster_key();
+        if self.raster_key != Some(raster_key) {
+            self.raster_key = Some(raster_key);
+            self.tiles.clear();
+        }
+        let world_changed = self.world_version != Some(world_info.render_version);
+        if world_changed {
+            self.world_version = Some(world_info.render_version);
+            for layer in &world_info.layers.layers {
+                if layer.kind != LayerKind::Currents {
+                    self.meshes.insert(layer.kind, upload(dis, layer_primitives(world_info, layer.kind, layer.style.line_scale)));
+                }
+            }
+        }
+        if world_changed || self.simulations_version != Some(world_info.simulations_version) {
+            self.simulations_version = Some(world_info.simulations_version);
+            let scale = world_info.layers.get(LayerKind::Currents).map_or(1.0, |l| l.style.line_scale);
+            self.meshes.insert(LayerKind::Currents, upload(dis, layer_primitives(world_info, LayerKind::Currents, scale)));
+        }
+    }
+}
+
+impl Default for RenderCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a layer draws, in world coordinates, before it is uploaded or rasterised.
+pub enum Primitive {
+    Triangles(Vec<Vertex>, [f32; 4]),
+    /// Lines `width` pixels wide, or for tapering lines that times the width at each point.
+    Lines {
+        lines: Vec<Line>,
+        widths: Option<Vec<f32>>,
+        width: f32,
+        color: [f32; 4],
+    },
+}
+
+fn push_mesh(primitives: &mut Vec<Primitive>, triangles: &[Vertex], color: [f32; 4]) {
+    if !triangles.is_empty() {
+        primitives.push(Primitive::Triangles(triangles.to_vec(), color));
+    }
+}
+
+fn push_lines(primitives: &mut Vec<Primitive>, lines: &[Line], width: f32, color: [f32; 4]) {
+    if !lines.is_empty() {
+        primitives.push(Primitive::Lines { lines: lines.to_vec(), widths: None, width, color });
+    }
+}
+
+fn upload(dis: &Display, primitives: Vec<Primitive>) -> Vec<Mesh> {
+    primitives
+        .into_iter()
+        .map(|primitive| match primitive {
+            Primitive::Triangles(triangles, color) => Mesh { geometry: Geometry::Triangles(VertexBuffer::new(dis, &triangles).unwrap()), color },
+            Primitive::Lines { lines, widths, width, color } => {
+                let vertices = match widths {
+                    Some(widths) => tapered_line_vertices(&lines, &widths),
+                    None => line_vertices(&lines),
+                };
+                Mesh { geometry: Geometry::Lines(VertexBuffer::new(dis, &vertices).unwrap(), width), color }
+            }
+        })
+        .collect()
+}
+
+/// The geometry of a layer, with marker sizes multiplied by `scale`. Line widths are
+/// in pixels, so that lines look the same at every zoom. It is in the map's projection.
+pub fn layer_primitives(world_info: &WorldInfo, kind: LayerKind, scale: f32) -> Vec<Primitive> {
+    let primitives = equirectangular_primitives(world_info, kind, scale);
+    let projector = world_info.projector();
+    if projector.is_identity() {
+        return primitives;
+    }
+    // long lines are cut up to bend with the projection
+    let step = (world_info.bottom_right.0 - world_info.top_left.0) / 360.0 * constants::PROJECTION_STEP;
+    reproject(primitives, &projector, step)
+}
+
+fn equirectangular_primitives(world_info: &WorldInfo, kind: LayerKind, scale: f32) -> Vec<Primitive> {
+    let mut primitives = Vec::new();
+    match kind {
+        LayerKind::LandFill => {
+            if let Some(mask) = &world_info.land_mask {
+                let triangles = geometry::fill_cells(mask.width, mask.height, |i| mask.data[i]);
+                push_mesh(&mut primitives, &triangles, [0.55, 0.6, 0.35, 1.0]);
+            }
+        }
+        LayerKind::Coastlines => {
+            push_lines(&mut primitives, &world_info.lines, 2.0, [0.15, 0.15, 0.1, 1.0]);
+        }
+        LayerKind::Contours => {
+            let color = [0.45, 0.3, 0.15, 0.8];
+            push_lines(&mut primitives, &world_info.contours.lines, 1.0, color);
+            push_lines(&mut primitives, &world_info.contours.index_lines, 2.0, color);
+        }
+        LayerKind::Tectonics => {
+            if let Some(tectonics) = &world_info.tectonics {
+                let kinds = [BoundaryKind::Collision, BoundaryKind::Subduction, BoundaryKind::IslandArc, BoundaryKind::Rift];
+                for kind in kinds {
+                    let boundaries: Vec<Line> = tectonics.boundaries.iter().filter(|b| b.kind == kind).map(|b| b.line).collect();
+                    push_lines(&mut primitives, &boundaries, 2.0, kind.color());
+                }
+            }
+        }
+        LayerKind::Ice => {
+            if let Some(ice) = world_info.ice.as_ref().filter(|_| world_info.show_ice) {
+                push_mesh(&mut primitives, &ice.seasonal_ice_fill, [0.85, 0.92, 1.0, 0.35]);
+                push_mesh(&mut primitives, &ice.pack_ice_fill, [0.85, 0.92, 1.0, 0.8]);
+                push_mesh(&mut primitives, &ice.glacier_fill, [1.0, 1.0, 1.0, 0.9]);
+                push_lines(&mut primitives, &ice.pack_ice_lines, 1.0, [0.5, 0.7, 0.9, 1.0]);
+                push_lines(&mut primitives, &ice.glacier_lines, 1.0, [0.6, 0.75, 0.9, 1.0]);
+            }
+        }
+        LayerKind::Kingdoms => {
+            for kingdom in &world_info.kingdoms {
+                let [r, g, b, _] = kingdom.color;
+                push_mesh(&mut primitives, &kingdom.fill, [r, g, b, 0.3]);
+                push_lines(&mut primitives, &kingdom.lines, 1.5, kingdom.color);
+            }
+        }
+        LayerKind::Lakes => {
+            for lake in &world_info.lakes {
+                push_lines(&mut primitives, &lake.lines, 2.0, [0.1, 0.3, 0.9, 1.0]);
+            }
+        }
+        LayerKind::Rivers => {
+            for river in &world_info.rivers {
+                // one pixel for the smallest stream, the rest in proportion
+                let widths: Vec<f32> = river.widths.iter().map(|w| w / constants::RIVER_MIN_WIDTH).collect();
+                primitives.push(Primitive::Lines { lines: river.lines.clone(), widths: Some(widths), width: 1.0, color: [0.1, 0.3, 0.9, 1.0] });
+            }
+        }
+        LayerKind::Currents => {
+            for status in &world_info.simulations {
+                push_lines(&mut primitives, &status.frame.lines, 1.5, [0.0, 0.8, 0.8, 1.0]);
+            }
+        }
+        LayerKind::DebugLines => {
+            push_lines(&mut primitives, &world_info.debug_lines, 1.0, [1.0, 0.0, 1.0, 1.0]);
+        }
+        LayerKind::Roads => {
+            for class in [RoadClass::Track, RoadClass::Road, RoadClass::Highway] {
+                let (width, color) = match class {
+                    RoadClass::Highway => (3.0, [0.85, 0.55, 0.1, 1.0]),
+                    RoadClass::Road => (2.0, [0.45, 0.3, 0.2, 1.0]),
+                    RoadClass::Track => (1.0, [0.45, 0.4, 0.35, 1.0]),
+                };
+                let roads: Vec<Line> = world_info.roads.edges.iter().filter(|e| e.class == class).flat_map(|e| e.lines.iter().copied()).collect();
+                push_lines(&mut primitives, &roads, width, color);
+            }
+            let bridges: Vec<Vertex> = world_info.roads.edges.iter().flat_map(|e| e.bridges.iter().copied()).collect();
+            push_mesh(&mut primitives, &square_markers(&bridges, 0.004 * scale), [0.3, 0.3, 0.3, 1.0]);
+        }
+        LayerKind::Settlements => {
+            for kind in [SettlementKind::Village, SettlementKind::Town, SettlementKind::City] {
+                let (size, color) = match kind {
+                    SettlementKind::City => (0.012, [0.6, 0.0, 0.0, 1.0]),
+                    SettlementKind::Town => (0.008, [0.3, 0.1, 0.1, 1.0]),
+                    SettlementKind::Village => (0.004, [0.2, 0.2, 0.2, 1.0]),
+                };
+                let points: Vec<Vertex> = world_info.settlements.iter().filter(|s| s.kind == kind).map(|s| s.position).collect();
+                push_mesh(&mut primitives, &square_markers(&points, size * scale), color);
+            }
+        }
+        LayerKind::SeaRoute => {
+            if let Some(route) = &world_info.sea_route {
+                push_lines(&mut primitives, &route.lines, 2.0, [0.8, 0.1, 0.6, 1.0]);
+            }
+        }
+        LayerKind::Capitals => {
+            // a small cross on every capital
+            let mut crosses = Vec::new();
+            for capital in &world_info.capitals {
+                for (dx, dy) in [(0.01, 0.01), (0.01, -0.01)] {
+                    let mut start = *capital;
+                    let mut end = *capital;
+                    start.add_to(-dx * scale, -dy * scale);
+                    end.add_to(dx * scale, dy * scale);
+                    crosses.push(Line::new(start, end));
+                }
+            }
+            push_lines(&mut primitives, &crosses, 2.0, [0.1, 0.1, 0.1, 1.0]);
+        }
+        LayerKind::Ridges => {
+            push_lines(&mut primitives, &world_info.ridge_lines, 4.0, [0.5, 0.3, 0.1, 1.0]);
+        }
+        LayerKind::Graticule => {
+            if world_info.world_texture.is_some() {
+                let tilt = world_info.climate_settings.axial_tilt;
+                let graticule = graticule::graticule(&world_info.graticule_settings, tilt, world_info.top_left, world_info.bottom_right);
+                push_lines(&mut primitives, &graticule.lines, 1.0, [0.2, 0.2, 0.2, 0.5]);
+                push_lines(&mut primitives, &graticule.special_lines, 1.5, [0.6, 0.15, 0.1, 0.7]);
+            }
+        }
+        // drawn every frame rather than cached
+        LayerKind::BaseTexture | LayerKind::Hillshade | LayerKind::Overlays | LayerKind::Scanline | LayerKind::Labels => {}
+    }
+    primitives
+}
+
+/// Moves primitives from the base image's frame into a projection, cut where the map's
+/// edges and the limit of what it shows run through them.
+fn reproject(primitives: Vec<Primitive>, projector: &Projector, step: f32) -> Vec<Primitive> {
+    primitives
+        .into_iter()
+        .map(|primitive| match primitive {
+            Primitive::Triangles(triangles, color) => {
+                let mut projected = Vec::with_capacity(triangles.len());
+                for triangle in triangles.chunks_exact(3) {
+                    projected.extend(projector.project_triangle([triangle[0], triangle[1], triangle[2]], step));
+                }
+                Primitive::Triangles(projected, color)
+            }
+            Primitive::Lines { lines, widths, width, color } => {
+                let mut projected = Vec::with_capacity(lines.len());
+                let mut projected_widths = Vec::new();
+                let mut last_width = 1.0;
+                for (i, line) in lines.iter().enumerate() {
+                    let (start_width, end_width) = match &widths {
+                        Some(w) => {
+                            let start = w.get(i).copied().unwrap_or(1.0);
+                            (start, w.get(i + 1).copied().unwrap_or(start))
+                        }
+                        None => (1.0, 1.0),
+                    };
+                    for (piece, from, to) in projector.project_line(*line, step) {
+                        projected.push(piece);
+                        projected_widths.push(start_width + (end_width - start_width) * from);
+                        last_width = start_width + (end_width - start_width) * to;
+                    }
+                }
+                projected_widths.push(last_width);
+                Primitive::Lines { lines: projected, widths: widths.map(|_| projected_widths), width, color }
+            }
+        })
+        .collect()
+}
+
 pub fn draw_things(
     dis: &Display,
     mut target: Frame,
     pro: &Program,
+    line_pro: &Program,
     vertex_info: &info::VertexShaderInfo,
     world_info: &WorldInfo,
+    cache: &mut RenderCache,
 ) -> Frame {
-    //let texture = glium::texture::SrgbTexture2d::new(dis, img).unwrap();
-    //world_info.world_texture;
-    if !world_info.world_texture.is_none() {
-        let shape = Shape::new_rectangle(
-            world_info
-                .world_texture
-                .as_ref()
-                .unwrap()
-                .gui_texture
-                .aspect_ratio(),
-        );
-
-        let shape = shape.vertices;
-        //let shape = &world_info.triangles;
-
-        let vertex_buffer = glium::VertexBuffer::new(dis, &shape).unwrap();
-        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
-
-        let texture = &world_info.world_texture.as_ref().unwrap().vertex_texture;
-
-        let uniforms = uniform! {tex: texture, aspect: vertex_info.aspect, zoom: vertex_info.zoom, offset: vertex_info.offset, useTexture: true};
-
-        // &glium::uniforms::EmptyUniforms
-
-        let params = glium::DrawParameters {
-            blend: glium::Blend::alpha_blending(),
-            //point_size: Some(1.0),
-            //polygon_mode: glium::PolygonMode::Fill,
-            ..Default::default()
-        };
+    cache.update(dis, world_info);
 
-        target
-            .draw(&vertex_buffer, &indices, &pro, &uniforms, &params)
-            .unwrap();
-    }
-    if !world_info.lines.is_empty() {
-        let fill = [world_info.bottom_right.0, vertex_info.mouse_pos.position[1]];
-        let end = Vertex{position: fill, tex_coords: fill};
-        let fill = [world_info.top_left.0, vertex_info.mouse_pos.position[1]];
-        let start = Vertex{position: fill, tex_coords: fill};
-        let horiz_line = Line{start: start, end: end};
-        let mut lines = Vec::new();
-        let mut intersections = Vec::new();
-
-        //lines.push(horiz_line);
-        for l in &world_info.lines {
-            if l.get_intersection(horiz_line).is_some() {
-                //lines.push(l.clone());
-                intersections.push(l.get_intersection(horiz_line).unwrap());
-            }
+    for layer in &world_info.layers.layers {
+        if !layer.visible || layer.opacity <= 0.0 {
+            continue;
         }
-        intersections.sort_by(|a, b| a.get_x().total_cmp(&b.get_x()));
-        //println!("Length before dedup {}", intersections.len());
-        intersections.dedup_by(|a, b| a.get_x()==b.get_x());
-        //println!("Length after dedup {}", intersections.len());
-        for i in 0..intersections.len() {
-            if i < intersections.len()-1 {
-                // if intersections[i].position[0] - intersections[i+1].position[0] < constants::PRECISION {
-                //     intersections.remove(i+1);
-                // }
+        match layer.kind {
+            LayerKind::BaseTexture => {
+                if let Some(texture) = world_info.base_raster() {
+                    target = draw_tiled(dis, target, pro, vertex_info, cache, TileImage::Base, texture, layer.opacity);
+                }
             }
-        }
-        for i in 0..intersections.len() {
-            if i%2 == 0 && i < intersections.len()-1{
-                lines.push(Line::new(intersections[i], intersections[i+1]));
+            LayerKind::Hillshade => {
+                if let Some(texture) = world_info.hillshade_raster() {
+                    target = draw_tiled(dis, target, pro, vertex_info, cache, TileImage::Hillshade, texture, layer.opacity);
+                }
+            }
+            LayerKind::Overlays => {
+                for (i, texture) in world_info.overlay_rasters().iter().enumerate() {
+                    target = draw_tiled(dis, target, pro, vertex_info, cache, TileImage::Overlay(i), texture, layer.opacity);
+                }
+            }
+            // works along the rows of the base image, so only on the unprojected map
+            LayerKind::Scanline => {
+                if !world_info.lines.is_empty() && world_info.projector().is_identity() {
+                    target = scanline_fill(dis, target, pro, vertex_info, world_info, layer, cache);
+                }
+            }
+            // text is painted by egui, see `draw_labels`
+            LayerKind::Labels => {}
+            kind => {
+                for mesh in cache.meshes.get(&kind).into_iter().flatten() {
+                    let color = layer.tint(mesh.color);
+                    target = match &mesh.geometry {
+                        Geometry::Triangles(buffer) => draw_buffer(target, pro, vertex_info, buffer, color),
+                        Geometry::Lines(buffer, width) => draw_lines(target, line_pro, vertex_info, buffer, width * layer.style.line_scale, layer, color),
+                    };
+                }
             }
         }
-        
-        let triangles = utils::vertices_from_lines(0.01, &lines);
-        target = draw_triangles(dis, target, pro, vertex_info, &triangles);
     }
 
+    cache.evict_tiles();
+    cache.frame += 1;
+
     return target;
 }
 
-fn draw_triangles(
+impl RenderCache {
+    /// Frees the tiles that have been out of view the longest, once there are too many.
+    fn evict_tiles(&mut self) {
+        if self.tiles.len() <= constants::MAX_CACHED_TILES {
+            return;
+        }
+        let mut ages: Vec<u64> = self.tiles.values().map(|t| t.last_drawn).collect();
+        ages.sort_unstable_by(|a, b| b.cmp(a));
+        let oldest_kept = ages[constants::MAX_CACHED_TILES - 1];
+        self.tiles.retain(|_, t| t.last_drawn >= oldest_kept);
+    }
+}
+
+/// Draws the tiles of a texture that are on screen, from the pyramid level that best
+/// matches the zoom, uploading any that aren't on the GPU yet.
+#[allow(clippy::too_many_arguments)]
+fn draw_tiled(
     dis: &Display,
     mut target: Frame,
     pro: &Program,
     vertex_info: &info::VertexShaderInfo,
-    triangles: &Vec<Vertex>
+    cache: &mut RenderCache,
+    image: TileImage,
+    texture: &TextureData,
+    opacity: f32,
+) -> Frame {
+    let (w, _) = target.get_dimensions();
+    // screen pixels covered by a pixel of the full image, along x
+    let screen_pixels = vertex_info.zoom * w as f32 / texture.height() as f32;
+    let level = texture.level_for(screen_pixels);
+
+    // what the screen shows, from clip space back to world, then to image pixels
+    let aspect = texture.aspect_ratio();
+    let to_image = |cx: f32, cy: f32| {
+        let x = cx / vertex_info.zoom - vertex_info.offset[0];
+        let y = (cy / vertex_info.zoom - vertex_info.offset[1]) / vertex_info.aspect;
+        ((x / aspect + 1.0) / 2.0 * texture.width() as f32, (1.0 - y) / 2.0 * texture.height() as f32)
+    };
+    let (left, top) = to_image(-1.0, 1.0);
+    let (right, bottom) = to_image(1.0, -1.0);
+
+    for (tx, ty) in texture.tiles_in(level, (left, top), (right, bottom)) {
+        let tile = cache.tiles.entry((image, level, tx, ty)).or_insert_with(|| {
+            let (tile_texture, quad) = texture.upload_tile(dis, level, tx, ty);
+            Tile { texture: tile_texture, quad: VertexBuffer::new(dis, &quad).unwrap(), last_drawn: 0 }
+        });
+        tile.last_drawn = cache.frame;
+        target = draw_texture(target, pro, vertex_info, &tile.quad, &tile.texture, opacity);
+    }
+    target
+}
+
+/// Fills the land along the row of the world under the mouse, from the coastlines alone.
+/// The fill is only rebuilt when the mouse moves to another row or the world changes.
+fn scanline_fill(
+    dis: &Display,
+    target: Frame,
+    pro: &Program,
+    vertex_info: &info::VertexShaderInfo,
+    world_info: &WorldInfo,
+    layer: &Layer,
+    cache: &mut RenderCache,
 ) -> Frame {
+    let key = (world_info.render_version, vertex_info.mouse_pos.position[1], layer.style.line_scale);
+    if cache.scanline.as_ref().map(|s| s.key) != Some(key) {
+        let triangles = scanline_triangles(vertex_info, world_info, layer.style.line_scale);
+        let buffer = (!triangles.is_empty()).then(|| VertexBuffer::new(dis, &triangles).unwrap());
+        cache.scanline = Some(Scanline { key, buffer });
+    }
+    match cache.scanline.as_ref().and_then(|s| s.buffer.as_ref()) {
+        Some(buffer) => draw_buffer(target, pro, vertex_info, buffer, layer.tint([1.0, 0.0, 0.0, 1.0])),
+        None => target,
+    }
+}
+
+fn scanline_triangles(vertex_info: &info::VertexShaderInfo, world_info: &WorldInfo, line_scale: f32) -> Vec<Vertex> {
+    let fill = [world_info.bottom_right.0, vertex_info.mouse_pos.position[1]];
+    let end = Vertex{position: fill, tex_coords: fill};
+    let fill = [world_info.top_left.0, vertex_info.mouse_pos.position[1]];
+    let start = Vertex{position: fill, tex_coords: fill};
+    let horiz_line = Line{start: start, end: end};
+    let mut lines = Vec::new();
+    let mut intersections = Vec::new();
 
+    //lines.push(horiz_line);
+    for l in &world_info.lines {
+        if l.get_intersection(horiz_line).is_some() {
+            //lines.push(l.clone());
+            intersections.push(l.get_intersection(horiz_line).unwrap());
+        }
+    }
+    intersections.sort_by(|a, b| a.get_x().total_cmp(&b.get_x()));
+    //println!("Length before dedup {}", intersections.len());
+    intersections.dedup_by(|a, b| a.get_x()==b.get_x());
+    //println!("Length after dedup {}", intersections.len());
+    for i in 0..intersections.len() {
+        if i < intersections.len()-1 {
+            // if intersections[i].position[0] - intersections[i+1].position[0] < constants::PRECISION {
+            //     intersections.remove(i+1);
+            // }
+        }
+    }
+    for i in 0..intersections.len() {
+        if i%2 == 0 && i < intersections.len()-1{
+            lines.push(Line::new(intersections[i], intersections[i+1]));
+        }
+    }
     
-    let shape = triangles;
+    utils::vertices_from_lines(0.01 * line_scale, &lines)
+}
+
+/// A name written on the map, centred on its position.
+pub struct Label {
+    pub(crate) position: Vertex,
+    pub(crate) text: String,
+    /// Font size in points, before the layer's style scales it.
+    pub(crate) size: f32,
+    pub(crate) color: [f32; 4],
+}
+
+/// Names of the seas, islands, kingdoms and larger settlements, where the projection
+/// puts them.
+pub fn labels(world_info: &WorldInfo) -> Vec<Label> {
+    let mut labels = Vec::new();
+    let projector = world_info.projector();
+    let mut label = |position: Vertex, text: &str, size: f32, color: [f32; 4]| {
+        if let Some(position) = projector.project(position) {
+            labels.push(Label { position, text: text.to_string(), size, color });
+        }
+    };
+
+    for sea in &world_info.seas {
+        label(sea.centre, &sea.name, 16.0, [0.1, 0.2, 0.5, 0.8]);
+    }
+    for island in &world_info.islands {
+        label(island.centre, &island.name, 16.0, [0.2, 0.15, 0.05, 0.8]);
+    }
+    for kingdom in &world_info.kingdoms {
+        let mut position = kingdom.capital;
+        position.add_to(0.0, 0.03);
+        label(position, &kingdom.name, 18.0, [0.1, 0.1, 0.1, 1.0]);
+    }
+    for settlement in world_info.settlements.iter().filter(|s| s.kind != SettlementKind::Village) {
+        let mut position = settlement.position;
+        position.add_to(0.0, -0.02);
+        let size = if settlement.kind == SettlementKind::City { 14.0 } else { 11.0 };
+        label(position, &settlement.name, size, [0.1, 0.1, 0.1, 1.0]);
+    }
+    labels
+}
+
+/// The labels layer, painted on the part of the screen that isn't covered by panels.
+pub fn draw_labels(egui_ctx: &egui::Context, vertex_info: &info::VertexShaderInfo, world_info: &WorldInfo) {
+    let Some(layer) = world_info.layers.visible(LayerKind::Labels) else {
+        return;
+    };
+    let screen = egui_ctx.input().screen_rect();
+    let painter = egui_ctx.layer_painter(egui::LayerId::background()).with_clip_rect(egui_ctx.available_rect());
+    for label in labels(world_info) {
+        let [r, g, b, a] = layer.tint(label.color);
+        let color = egui::Rgba::from_rgba_unmultiplied(r, g, b, a);
+        let font = egui::FontId::proportional(label.size * layer.style.line_scale);
+        painter.text(to_screen(screen, vertex_info, label.position), egui::Align2::CENTER_CENTER, label.text, font, color.into());
+    }
+}
+
+/// Degrees along the edges of the part of the map that isn't covered by panels.
+pub fn draw_graticule_labels(egui_ctx: &egui::Context, vertex_info: &info::VertexShaderInfo, world_info: &WorldInfo) {
+    let Some(layer) = world_info.layers.visible(LayerKind::Graticule) else {
+        return;
+    };
+    // the meridians and parallels only meet the edges of the view squarely unprojected
+    if world_info.world_texture.is_none() || !world_info.projector().is_identity() {
+        return;
+    }
+    let screen = egui_ctx.input().screen_rect();
+    let view = egui_ctx.available_rect();
+    let painter = egui_ctx.layer_painter(egui::LayerId::background()).with_clip_rect(view);
+    let top_left = from_screen(screen, vertex_info, view.left_top());
+    let bottom_right = from_screen(screen, vertex_info, view.right_bottom());
+    let labels = graticule::edge_labels(
+        &world_info.graticule_settings,
+        world_info.climate_settings.axial_tilt,
+        world_info.top_left,
+        world_info.bottom_right,
+        (top_left.position[0], top_left.position[1]),
+        (bottom_right.position[0], bottom_right.position[1]),
+        (bottom_right.position[0] - top_left.position[0]) / view.width() * constants::GRATICULE_LABEL_GAP,
+    );
+    let [r, g, b, a] = layer.tint([0.1, 0.1, 0.1, 0.9]);
+    let color = egui::Rgba::from_rgba_unmultiplied(r, g, b, a);
+    for label in labels {
+        let position = to_screen(screen, vertex_info, label.position) + graticule::label_padding(label.align);
+        painter.text(position, label.align, label.text, egui::FontId::proportional(12.0), color.into());
+    }
+}
+
+/// Where a world point is drawn, in points: the same transform as the vertex shader,
+/// then from clip space to the screen.
+fn to_screen(screen: egui::Rect, vertex_info: &info::VertexShaderInfo, v: Vertex) -> egui::Pos2 {
+    let x = (v.position[0] + vertex_info.offset[0]) * vertex_info.zoom;
+    let y = (v.position[1] * vertex_info.aspect + vertex_info.offset[1]) * vertex_info.zoom;
+    egui::pos2(screen.min.x + (x + 1.0) / 2.0 * screen.width(), screen.min.y + (1.0 - y) / 2.0 * screen.height())
+}
+
+/// The world point drawn at a point on the screen, the inverse of `to_screen`.
+fn from_screen(screen: egui::Rect, vertex_info: &info::VertexShaderInfo, pos: egui::Pos2) -> Vertex {
+    let cx = (pos.x - screen.min.x) / screen.width() * 2.0 - 1.0;
+    let cy = 1.0 - (pos.y - screen.min.y) / screen.height() * 2.0;
+    let x = cx / vertex_info.zoom - vertex_info.offset[0];
+    let y = (cy / vertex_info.zoom - vertex_info.offset[1]) / vertex_info.aspect;
+    Vertex { position: [x, y], tex_coords: [x, y] }
+}
 
-    let vertex_buffer = glium::VertexBuffer::new(dis, &shape).unwrap();
+/// Two triangles making a square around each point, `size` being half the side.
+fn square_markers(points: &[Vertex], size: f32) -> Vec<Vertex> {
+    let mut triangles = Vec::new();
+    for point in points {
+        let [x, y] = point.position;
+        let corner = |dx: f32, dy: f32| {
+            let p = [x + dx * size, y + dy * size];
+            Vertex{position: p, tex_coords: p}
+        };
+        triangles.extend([corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)]);
+    }
+    triangles
+}
+
+fn draw_texture(
+    mut target: Frame,
+    pro: &Program,
+    vertex_info: &info::VertexShaderInfo,
+    quad: &VertexBuffer<Vertex>,
+    texture: &SrgbTexture2d,
+    opacity: f32,
+) -> Frame {
+    let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+
+    let sampler = texture
+        .sampled()
+        .wrap_function(SamplerWrapFunction::Clamp)
+        .minify_filter(MinifySamplerFilter::LinearMipmapLinear)
+        .magnify_filter(MagnifySamplerFilter::Linear);
+    let uniforms = uniform! {tex: sampler, aspect: vertex_info.aspect, zoom: vertex_info.zoom, offset: vertex_info.offset, useTexture: true, opacity: opacity};
+
+    let params = glium::DrawParameters {
+        blend: glium::Blend::alpha_blending(),
+        ..Default::default()
+    };
+
+    target
+        .draw(quad, indices, pro, &uniforms, &params)
+        .unwrap();
+
+    target
+}
+
+/// Lines `width` pixels wide, whatever the zoom, with the layer's joins and caps.
+fn draw_lines(
+    mut target: Frame,
+    line_pro: &Program,
+    vertex_info: &info::VertexShaderInfo,
+    vertex_buffer: &VertexBuffer<LineVertex>,
+    width: f32,
+    layer: &Layer,
+    color: [f32; 4],
+) -> Frame {
     let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+    let (w, h) = target.get_dimensions();
+
+    let uniforms = uniform! {
+        aspect: vertex_info.aspect, zoom: vertex_info.zoom, offset: vertex_info.offset,
+        viewport: [w as f32, h as f32], lineWidth: width, miterLimit: constants::LINE_MITER_LIMIT,
+        join: layer.style.join.uniform(), cap: layer.style.cap.uniform(), lineColor: color,
+    };
+
+    let params = glium::DrawParameters {
+        blend: glium::Blend::alpha_blending(),
+        ..Default::default()
+    };
+
+    target
+        .draw(vertex_buffer, indices, line_pro, &uniforms, &params)
+        .unwrap();
 
-    //let texture = &world_info.world_texture.as_ref().unwrap().vertex_texture;
+    target
+}
 
-    let uniforms = uniform! {aspect: vertex_info.aspect, zoom: vertex_info.zoom, offset: vertex_info.offset, useTexture: false};
+fn draw_buffer(
+    mut target: Frame,
+    pro: &Program,
+    vertex_info: &info::VertexShaderInfo,
+    vertex_buffer: &VertexBuffer<Vertex>,
+    color: [f32; 4],
+) -> Frame {
+    let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
 
-    // &glium::uniforms::EmptyUniforms
+    let uniforms = uniform! {aspect: vertex_info.aspect, zoom: vertex_info.zoom, offset: vertex_info.offset, useTexture: false, lineColor: color};
 
     let params = glium::DrawParameters {
         blend: glium::Blend::alpha_blending(),
@@ -119,8 +685,8 @@ fn draw_triangles(
     };
 
     target
-        .draw(&vertex_buffer, &indices, &pro, &uniforms, &params)
+        .draw(vertex_buffer, indices, pro, &uniforms, &params)
         .unwrap();
-    
+
     target
 }
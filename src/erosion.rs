@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    constants,
+    grid::Grid,
+    seed,
+    scheduler::{Simulation, SimulationFrame},
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct ErosionSettings {
+    pub(crate) droplets: u32,
+    pub(crate) thermal_iterations: u32,
+    pub(crate) inertia: f32,
+    pub(crate) capacity: f32,
+    pub(crate) erode_speed: f32,
+    pub(crate) deposit_speed: f32,
+    pub(crate) evaporate_speed: f32,
+    pub(crate) talus: f32,
+    pub(crate) thermal_rate: f32,
+}
+
+impl Default for ErosionSettings {
+    fn default() -> Self {
+        ErosionSettings {
+            droplets: 50000,
+            thermal_iterations: 20,
+            inertia: 0.05,
+            capacity: 4.0,
+            erode_speed: 0.3,
+            deposit_speed: 0.3,
+            evaporate_speed: 0.01,
+            talus: 40.0,
+            thermal_rate: 0.5,
+        }
+    }
+}
+
+/// An erosion run that is advanced a batch at a time so the gui can report progress.
+/// Hydraulic droplets are simulated first, then the thermal passes.
+pub struct ErosionJob {
+    settings: ErosionSettings,
+    rng: ChaCha8Rng,
+    droplets_done: u32,
+    thermal_done: u32,
+}
+
+impl ErosionJob {
+    pub fn new(settings: ErosionSettings, seed: u64) -> Self {
+        ErosionJob {
+            settings,
+            rng: seed::from_seed(seed),
+            droplets_done: 0,
+            thermal_done: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.droplets_done >= self.settings.droplets && self.thermal_done >= self.settings.thermal_iterations
+    }
+
+    /// Fraction of the work done, `0.0..=1.0`. A thermal pass counts as much as a batch of droplets.
+    pub fn progress(&self) -> f32 {
+        let total = self.settings.droplets + self.settings.thermal_iterations * constants::EROSION_BATCH;
+        if total == 0 {
+            return 1.0;
+        }
+        let done = self.droplets_done + self.thermal_done * constants::EROSION_BATCH;
+        done as f32 / total as f32
+    }
+
+    /// Runs one batch of droplets, or one thermal pass once every droplet is done.
+    pub fn step(&mut self, heightmap: &mut Grid<f32>, mask: &Grid<bool>) {
+        if heightmap.width < 2 || heightmap.height < 2 {
+            // too small for a droplet to run anywhere, and for the range droplets start in
+            self.droplets_done = self.settings.droplets;
+        }
+        if self.droplets_done < self.settings.droplets {
+            // heights are in metres, droplets work in units of the highest peak
+            let (_, max) = heightmap.min_max();
+            let unit = max.max(1.0);
+
+            let batch = constants::EROSION_BATCH.min(self.settings.droplets - self.droplets_done);
+            for _ in 0..batch {
+                let x = self.rng.gen_range(0.0..(heightmap.width - 1) as f32);
+                let y = self.rng.gen_range(0.0..(heightmap.height - 1) as f32);
+                hydraulic_droplet(heightmap, mask, &self.settings, unit, x, y);
+            }
+            self.droplets_done += batch;
+        } else if self.thermal_done < self.settings.thermal_iterations {
+            thermal_pass(heightmap, mask, &self.settings);
+            self.thermal_done += 1;
+        }
+    }
+}
+
+/// Erosion of a copy of the world heightmap, run by the scheduler one batch per tick.
+/// The heightmap is only handed out once the erosion is finished, so it is never copied.
+pub struct ErosionSimulation {
+    job: ErosionJob,
+    heightmap: Arc<Grid<f32>>,
+    mask: Grid<bool>,
+}
+
+impl ErosionSimulation {
+    pub fn new(job: ErosionJob, heightmap: Grid<f32>, mask: Grid<bool>) -> Self {
+        ErosionSimulation {
+            job,
+            heightmap: Arc::new(heightmap),
+            mask,
+        }
+    }
+}
+
+impl Simulation for ErosionSimulation {
+    fn name(&self) -> &str {
+        "Erosion"
+    }
+
+    fn tick(&mut self) {
+        self.job.step(Arc::make_mut(&mut self.heightmap), &self.mask);
+    }
+
+    fn is_finished(&self) -> bool {
+        self.job.is_finished()
+    }
+
+    fn progress(&self) -> Option<f32> {
+        Some(self.job.progress())
+    }
+
+    fn frame(&self) -> SimulationFrame {
+        SimulationFrame {
+            lines: Arc::new(Vec::new()),
+            heightmap: self.job.is_finished().then(|| Arc::clone(&self.heightmap)),
+        }
+    }
+}
+
+/// Simulates a single water droplet that picks up and drops sediment while running downhill.
+/// Droplets only live on land and stop as soon as they reach the sea.
+/// `unit` is the height in metres that counts as 1.0 for the droplet's capacity.
+pub fn hydraulic_droplet(heightmap: &mut Grid<f32>, mask: &Grid<bool>, settings: &ErosionSettings, unit: f32, x: f32, y: f32) {
+    if heightmap.width < 2 || heightmap.height < 2 || !mask.get(x as u32, y as u32) {
+        return;
+    }
+
+    let mut pos = (x, y);
+    let mut dir = (0.0, 0.0);
+    let mut speed = 1.0;
+    let mut water = 1.0;
+    let mut sediment = 0.0;
+
+    for _ in 0..constants::DROPLET_LIFETIME {
+        let cell = (pos.0 as u32, pos.1 as u32);
+        let frac = (pos.0 - cell.0 as f32, pos.1 - cell.1 as f32);
+        let (height, gradient) = height_and_gradient(heightmap, pos.0, pos.1);
+
+        dir.0 = dir.0 * settings.inertia - gradient.0 * (1.0 - settings.inertia);
+        dir.1 = dir.1 * settings.inertia - gradient.1 * (1.0 - settings.inertia);
+        let len = (dir.0 * dir.0 + dir.1 * dir.1).sqrt();
+        if len == 0.0 {
+            break;
+        }
+        dir = (dir.0 / len, dir.1 / len);
+        pos = (pos.0 + dir.0, pos.1 + dir.1);
+
+        if pos.0 < 0.0 || pos.1 < 0.0 || pos.0 >= (heightmap.width - 1) as f32 || pos.1 >= (heightmap.height - 1) as f32 {
+            break;
+        }
+        let reached_sea = !mask.get(pos.0 as u32, pos.1 as u32);
+
+        let (new_height, _) = height_and_gradient(heightmap, pos.0, pos.1);
+        let delta = (new_height - height) / unit;
+
+        let capacity = (-delta).max(0.01) * speed * water * settings.capacity;
+        if reached_sea || sediment > capacity || delta > 0.0 {
+            let amount = if delta > 0.0 {
+                delta.min(sediment)
+            } else {
+                (sediment - capacity) * settings.deposit_speed
+            };
+            let amount = if reached_sea { sediment } else { amount };
+            sediment -= amount;
+            deposit(heightmap, mask, cell, frac, amount * unit);
+        } else {
+            let amount = ((capacity - sediment) * settings.erode_speed).min(-delta);
+            sediment += amount;
+            deposit(heightmap, mask, cell, frac, -amount * unit);
+        }
+
+        if reached_sea {
+            break;
+        }
+
+        speed = (speed * speed + delta.abs()).sqrt();
+        water *= 1.0 - settings.evaporate_speed;
+    }
+}
+
+fn height_and_gradient(heightmap: &Grid<f32>, x: f32, y: f32) -> (f32, (f32, f32)) {
+    let cx = (x as u32).min(heightmap.width - 2);
+    let cy = (y as u32).min(heightmap.height - 2);
+    let fx = x - cx as f32;
+    let fy = y - cy as f32;
+
+    let nw = heightmap.get(cx, cy);
+    let ne = heightmap.get(cx + 1, cy);
+    let sw = heightmap.get(cx, cy + 1);
+    let se = heightmap.get(cx + 1, cy + 1);
+
+    let gx = (ne - nw) * (1.0 - fy) + (se - sw) * fy;
+    let gy = (sw - nw) * (1.0 - fx) + (se - ne) * fx;
+    let h = nw * (1.0 - fx) * (1.0 - fy) + ne * fx * (1.0 - fy) + sw * (1.0 - fx) * fy + se * fx * fy;
+    (h, (gx, gy))
+}
+
+// spreads `amount` metres bilinearly over the four corners of a cell
+fn deposit(heightmap: &mut Grid<f32>, mask: &Grid<bool>, cell: (u32, u32), frac: (f32, f32), amount: f32) {
+    let (fx, fy) = frac;
+    let corners = [
+        (cell.0, cell.1, (1.0 - fx) * (1.0 - fy)),
+        (cell.0 + 1, cell.1, fx * (1.0 - fy)),
+        (cell.0, cell.1 + 1, (1.0 - fx) * fy),
+        (cell.0 + 1, cell.1 + 1, fx * fy),
+    ];
+    for (x, y, weight) in corners {
+        if x < heightmap.width && y < heightmap.height {
+            let h = heightmap.get(x, y) + amount * weight;
+            heightmap.set(x, y, keep_coast(h, mask.get(x, y)));
+        }
+    }
+}
+
+/// Moves material from cells steeper than the talus threshold to their lower neighbours.
+pub fn thermal_pass(heightmap: &mut Grid<f32>, mask: &Grid<bool>, settings: &ErosionSettings) {
+    let width = heightmap.width as i32;
+    let height = heightmap.height as i32;
+    let mut change = Grid::new(heightmap.width, heightmap.height, 0.0);
+
+    for y in 0..height {
+        for x in 0..width {
+            let h = heightmap.get(x as u32, y as u32);
+            let mut total = 0.0;
+            let mut max_diff: f32 = 0.0;
+            for (dx, dy) in constants::NEIGHBOURS_8 {
+                if heightmap.in_bounds(x + dx, y + dy) {
+                    let diff = h - heightmap.get((x + dx) as u32, (y + dy) as u32);
+                    if diff > settings.talus {
+                        total += diff;
+                        max_diff = max_diff.max(diff);
+                    }
+                }
+            }
+            if total == 0.0 {
+                continue;
+            }
+
+            let moved = settings.thermal_rate * (max_diff - settings.talus) / 2.0;
+            change.data[heightmap.index(x as u32, y as u32)] -= moved;
+            for (dx, dy) in constants::NEIGHBOURS_8 {
+                if heightmap.in_bounds(x + dx, y + dy) {
+                    let diff = h - heightmap.get((x + dx) as u32, (y + dy) as u32);
+                    if diff > settings.talus {
+                        change.data[heightmap.index((x + dx) as u32, (y + dy) as u32)] += moved * diff / total;
+                    }
+                }
+            }
+        }
+    }
+
+    for i in 0..heightmap.len() {
+        heightmap.data[i] = keep_coast(heightmap.data[i] + change.data[i], mask.data[i]);
+    }
+}
+
+// erosion must never move the coastline from the land mask
+fn keep_coast(h: f32, land: bool) -> f32 {
+    if land {
+        h.max(constants::MIN_LAND_ELEVATION)
+    } else {
+        h.min(-constants::MIN_LAND_ELEVATION)
+    }
+}
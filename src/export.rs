@@ -0,0 +1,515 @@
+use egui::epaint::{
+    text::{FontDefinitions, Fonts},
+    Color32, FontId, FontImage, ImageData,
+};
+use egui::{pos2, Align2, Rect};
+use image::{Rgba, RgbaImage};
+use std::{collections::VecDeque, sync::Arc};
+
+use crate::{
+    data_displayer::{self, Primitive},
+    graticule, constants,
+    geometry::{Line, Vertex},
+    info::{VertexShaderInfo, WorldInfo},
+    layers::{Layer, LayerKind},
+    projection::{self, Projector},
+    scheduler::{Simulation, SimulationFrame},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExportRegion {
+    World,
+    View,
+}
+
+impl ExportRegion {
+    pub fn name(&self) -> &str {
+        match self {
+            ExportRegion::World => "Whole World",
+            ExportRegion::View => "Current View",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportSettings {
+    pub(crate) region: ExportRegion,
+    /// Pixels of the export for every pixel of the base image, or of the window when
+    /// exporting the view. Lines and text grow with it.
+    pub(crate) scale: f32,
+    /// Layers shown on the map that are left out of the export.
+    pub(crate) excluded: Vec<LayerKind>,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        ExportSettings {
+            region: ExportRegion::World,
+            scale: 1.0,
+            excluded: Vec::new(),
+        }
+    }
+}
+
+/// The part of the world to export and the size of the image it's drawn to.
+#[derive(Copy, Clone, Debug)]
+pub struct ExportArea {
+    pub(crate) top_left: (f32, f32),
+    pub(crate) bottom_right: (f32, f32),
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// Multiplies line widths and font sizes, which are given in screen pixels.
+    pub(crate) scale: f32,
+    /// Whether the scale asked for was brought down to keep under `MAX_EXPORT_PIXELS`.
+    pub(crate) capped: bool,
+}
+
+impl ExportArea {
+    /// The whole world at the resolution of its base image times `scale`, in the map's
+    /// projection.
+    pub fn world(world_info: &WorldInfo, scale: f32) -> Option<Self> {
+        let texture = world_info.world_texture.as_ref()?;
+        let projector = world_info.projector();
+        let (top_left, bottom_right) = projector.bounds();
+        let height = texture.height() as f32;
+        Some(Self::sized(top_left, bottom_right, [height * projector.aspect_ratio(), height], scale))
+    }
+
+    /// What the window shows, `screen` being its size in pixels.
+    pub fn view(vertex_info: &VertexShaderInfo, screen: [f32; 2], scale: f32) -> Self {
+        // clip space corners back through the vertex shader's transform
+        let zoom = vertex_info.zoom;
+        let [x, y] = vertex_info.offset;
+        Self::sized(
+            (-1.0 / zoom - x, (1.0 / zoom - y) / vertex_info.aspect),
+            (1.0 / zoom - x, (-1.0 / zoom - y) / vertex_info.aspect),
+            screen,
+            scale,
+        )
+    }
+
+    /// `size` times `scale` pixels, or as near to it as `MAX_EXPORT_PIXELS` allows.
+    fn sized(top_left: (f32, f32), bottom_right: (f32, f32), size: [f32; 2], scale: f32) -> Self {
+        let pixels = (size[0] * scale).max(1.0) * (size[1] * scale).max(1.0);
+        let capped = pixels > constants::MAX_EXPORT_PIXELS as f32;
+        let scale = if capped { scale * (constants::MAX_EXPORT_PIXELS as f32 / pixels).sqrt() } else { scale };
+        ExportArea {
+            top_left,
+            bottom_right,
+            width: (size[0] * scale).floor().max(1.0) as u32,
+            height: (size[1] * scale).floor().max(1.0) as u32,
+            scale,
+            capped,
+        }
+    }
+
+    fn to_pixel(self, world: [f32; 2]) -> (f32, f32) {
+        let (left, top) = self.top_left;
+        let (right, bottom) = self.bottom_right;
+        (
+            (world[0] - left) / (right - left) * self.width as f32,
+            (top - world[1]) / (top - bottom) * self.height as f32,
+        )
+    }
+
+    fn to_world(self, px: f32, py: f32) -> [f32; 2] {
+        let (left, top) = self.top_left;
+        let (right, bottom) = self.bottom_right;
+        [
+            left + px / self.width as f32 * (right - left),
+            top - py / self.height as f32 * (top - bottom),
+        ]
+    }
+}
+
+/// Draws the map on the CPU, so it can be any size and needs no window, and saves it to
+/// `path`: each layer of the stack in order, skipping the hidden ones and those in
+/// `excluded`. Lines get round joins and caps whatever their style. What to draw is taken
+/// from the world straight away, the drawing is done a layer a tick by the scheduler.
+pub struct ExportJob {
+    canvas: Canvas,
+    items: VecDeque<Item>,
+    total: usize,
+    path: String,
+    finished: bool,
+}
+
+/// One layer's worth of drawing, with its style already applied.
+enum Item {
+    Texture(Arc<Vec<RgbaImage>>, f32),
+    Primitives(Vec<Primitive>),
+    Texts(Vec<Text>),
+}
+
+impl ExportJob {
+    pub fn new(world_info: &WorldInfo, area: &ExportArea, excluded: &[LayerKind], path: String) -> Self {
+        let mut items = VecDeque::new();
+        for layer in &world_info.layers.layers {
+            if !layer.visible || layer.opacity <= 0.0 || excluded.contains(&layer.kind) {
+                continue;
+            }
+            match layer.kind {
+                // the originals, read through the projection
+                LayerKind::BaseTexture => {
+                    if let Some(texture) = &world_info.world_texture {
+                        items.push_back(Item::Texture(Arc::clone(&texture.levels), layer.opacity));
+                    }
+                }
+                LayerKind::Hillshade => {
+                    if let Some(texture) = &world_info.hillshade {
+                        items.push_back(Item::Texture(Arc::clone(&texture.levels), layer.opacity));
+                    }
+                }
+                LayerKind::Overlays => {
+                    for texture in &world_info.texture_list {
+                        items.push_back(Item::Texture(Arc::clone(&texture.levels), layer.opacity));
+                    }
+                }
+                // follows the mouse, so has nothing to show in a picture
+                LayerKind::Scanline => {}
+                LayerKind::Labels => {
+                    let texts = data_displayer::labels(world_info).into_iter().map(|label| Text {
+                        position: area.to_pixel(label.position.position),
+                        text: label.text,
+                        size: label.size * layer.style.line_scale * area.scale,
+                        color: layer.tint(label.color),
+                        align: Align2::CENTER_CENTER,
+                    });
+                    items.push_back(Item::Texts(texts.collect()));
+                }
+                kind => {
+                    items.push_back(Item::Primitives(styled_primitives(world_info, layer, area)));
+                    if kind == LayerKind::Graticule && world_info.world_texture.is_some() && world_info.projector().is_identity() {
+                        items.push_back(Item::Texts(graticule_labels(world_info, layer, area)));
+                    }
+                }
+            }
+        }
+        ExportJob {
+            canvas: Canvas { image: RgbaImage::new(area.width, area.height), area: *area, projector: world_info.projector() },
+            total: items.len(),
+            items,
+            path,
+            finished: false,
+        }
+    }
+}
+
+impl Simulation for ExportJob {
+    fn name(&self) -> &str {
+        "Map Export"
+    }
+
+    fn tick(&mut self) {
+        match self.items.pop_front() {
+            Some(Item::Texture(levels, opacity)) => self.canvas.draw_texture(&levels, opacity),
+            Some(Item::Primitives(primitives)) => self.canvas.draw_primitives(primitives),
+            Some(Item::Texts(texts)) => self.canvas.draw_text(texts),
+            None => {
+                if let Err(e) = self.canvas.image.save(&self.path) {
+                    println!("could not export the map to {}: {}", self.path, e);
+                }
+                self.finished = true;
+            }
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn progress(&self) -> Option<f32> {
+        // saving counts as a step of its own
+        Some((self.total - self.items.len()) as f32 / (self.total + 1) as f32)
+    }
+
+    fn frame(&self) -> SimulationFrame {
+        SimulationFrame::default()
+    }
+
+    fn is_background(&self) -> bool {
+        true
+    }
+}
+
+/// What a layer draws, in the colours and at the widths it is drawn with.
+fn styled_primitives(world_info: &WorldInfo, layer: &Layer, area: &ExportArea) -> Vec<Primitive> {
+    data_displayer::layer_primitives(world_info, layer.kind, layer.style.line_scale)
+        .into_iter()
+        .map(|primitive| match primitive {
+            Primitive::Triangles(triangles, color) => Primitive::Triangles(triangles, layer.tint(color)),
+            Primitive::Lines { lines, widths, width, color } => Primitive::Lines {
+                lines,
+                widths,
+                width: width * layer.style.line_scale * area.scale,
+                color: layer.tint(color),
+            },
+        })
+        .collect()
+}
+
+/// Degrees along the edges of the export, as they are along the edges of the view.
+fn graticule_labels(world_info: &WorldInfo, layer: &Layer, area: &ExportArea) -> Vec<Text> {
+    let labels = graticule::edge_labels(
+        &world_info.graticule_settings,
+        world_info.climate_settings.axial_tilt,
+        world_info.top_left,
+        world_info.bottom_right,
+        area.top_left,
+        area.bottom_right,
+        (area.bottom_right.0 - area.top_left.0) / area.width as f32 * constants::GRATICULE_LABEL_GAP * area.scale,
+    );
+    labels
+        .into_iter()
+        .map(|label| {
+            let (x, y) = area.to_pixel(label.position.position);
+            let padding = graticule::label_padding(label.align) * area.scale;
+            Text {
+                position: (x + padding.x, y + padding.y),
+                text: label.text,
+                size: 12.0 * area.scale,
+                color: layer.tint([0.1, 0.1, 0.1, 0.9]),
+                align: label.align,
+            }
+        })
+        .collect()
+}
+
+/// Text to draw, `align` saying which part of it is at `position`.
+struct Text {
+    position: (f32, f32),
+    text: String,
+    size: f32,
+    color: [f32; 4],
+    align: Align2,
+}
+
+struct Canvas {
+    image: RgbaImage,
+    area: ExportArea,
+    projector: Projector,
+}
+
+impl Canvas {
+    /// Lays `color` over a pixel, `coverage` being how much of the pixel it covers.
+    fn blend(&mut self, x: u32, y: u32, color: [f32; 4], coverage: f32) {
+        let source = color[3] * coverage;
+        if source <= 0.0 {
+            return;
+        }
+        let pixel = self.image.get_pixel_mut(x, y);
+        let below = pixel.0[3] as f32 / 255.0 * (1.0 - source);
+        let alpha = source + below;
+        let mut out = [0u8; 4];
+        for c in 0..3 {
+            let value = (color[c] * source + pixel.0[c] as f32 / 255.0 * below) / alpha;
+            out[c] = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+        out[3] = (alpha * 255.0).round().clamp(0.0, 255.0) as u8;
+        *pixel = Rgba(out);
+    }
+
+    /// Pixels overlapping a rectangle given in pixel coordinates, clamped to the image.
+    fn pixel_range(&self, min: (f32, f32), max: (f32, f32)) -> (std::ops::Range<u32>, std::ops::Range<u32>) {
+        let clamp = |v: f32, size: u32| v.max(0.0).min(size as f32) as u32;
+        (
+            clamp(min.0.floor(), self.area.width)..clamp(max.0.ceil(), self.area.width),
+            clamp(min.1.floor(), self.area.height)..clamp(max.1.ceil(), self.area.height),
+        )
+    }
+
+    /// Samples the pyramid of an equirectangular image through the projection, at the
+    /// level closest to the export's resolution.
+    fn draw_texture(&mut self, levels: &[RgbaImage], opacity: f32) {
+        let (left, _) = self.area.top_left;
+        let (right, _) = self.area.bottom_right;
+        let pixel = (right - left) / self.area.width as f32;
+        for y in 0..self.area.height {
+            for x in 0..self.area.width {
+                let point = self.area.to_world(x as f32 + 0.5, y as f32 + 0.5);
+                if let Some(color) = projection::sample_projected(levels, &self.projector, point, pixel) {
+                    self.blend(x, y, color, opacity);
+                }
+            }
+        }
+    }
+
+    /// Fills every pixel whose centre is inside one of the triangles.
+    fn fill_triangles(&mut self, triangles: &[Vertex], color: [f32; 4]) {
+        for triangle in triangles.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| self.area.to_pixel(triangle[i].position));
+            let min = (a.0.min(b.0).min(c.0), a.1.min(b.1).min(c.1));
+            let max = (a.0.max(b.0).max(c.0), a.1.max(b.1).max(c.1));
+            let edge = |p: (f32, f32), q: (f32, f32), r: (f32, f32)| (q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0);
+            let area = edge(a, b, c);
+            if area == 0.0 {
+                continue;
+            }
+            let (xs, ys) = self.pixel_range(min, max);
+            for y in ys {
+                for x in xs.clone() {
+                    let p = (x as f32 + 0.5, y as f32 + 0.5);
+                    // same sign as the whole triangle on all three edges, whichever way it winds
+                    let inside = [edge(a, b, p), edge(b, c, p), edge(c, a, p)].iter().all(|e| e * area >= 0.0);
+                    if inside {
+                        self.blend(x, y, color, 1.0);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Anti-aliased lines `width` pixels wide. Coverage is gathered for the whole set of
+    /// lines before blending, so that where segments meet isn't drawn twice.
+    fn draw_lines(&mut self, lines: &[Line], widths: Option<&[f32]>, width: f32, color: [f32; 4]) {
+        if lines.is_empty() {
+            return;
+        }
+        let mut segments = Vec::with_capacity(lines.len());
+        let mut min = (f32::MAX, f32::MAX);
+        let mut max = (f32::MIN, f32::MIN);
+        for (i, line) in lines.iter().enumerate() {
+            let factor = widths.map_or(1.0, |w| {
+                let start = w.get(i).copied().unwrap_or(1.0);
+                (start + w.get(i + 1).copied().unwrap_or(start)) / 2.0
+            });
+            let half = width * factor / 2.0;
+            let a = self.area.to_pixel(line.start.position);
+            let b = self.area.to_pixel(line.end.position);
+            min = (min.0.min(a.0.min(b.0) - half - 1.0), min.1.min(a.1.min(b.1) - half - 1.0));
+            max = (max.0.max(a.0.max(b.0) + half + 1.0), max.1.max(a.1.max(b.1) + half + 1.0));
+            segments.push((a, b, half));
+        }
+
+        let (xs, ys) = self.pixel_range(min, max);
+        if xs.is_empty() || ys.is_empty() {
+            return;
+        }
+        let columns = xs.len();
+        let mut coverage = vec![0u8; columns * ys.len()];
+        for (a, b, half) in segments {
+            let (sx, sy) = self.pixel_range(
+                (a.0.min(b.0) - half - 1.0, a.1.min(b.1) - half - 1.0),
+                (a.0.max(b.0) + half + 1.0, a.1.max(b.1) + half + 1.0),
+            );
+            for y in sy {
+                for x in sx.clone() {
+                    let distance = distance_to_segment((x as f32 + 0.5, y as f32 + 0.5), a, b);
+                    let value = (half + 0.5 - distance).clamp(0.0, 1.0);
+                    let cell = &mut coverage[(y - ys.start) as usize * columns + (x - xs.start) as usize];
+                    *cell = (*cell).max((value * 255.0) as u8);
+                }
+            }
+        }
+        for (y, row) in ys.clone().zip(coverage.chunks(columns)) {
+            for (x, value) in xs.clone().zip(row) {
+                if *value > 0 {
+                    self.blend(x, y, color, *value as f32 / 255.0);
+                }
+            }
+        }
+    }
+
+    fn draw_primitives(&mut self, primitives: Vec<Primitive>) {
+        for primitive in primitives {
+            match primitive {
+                Primitive::Triangles(triangles, color) => self.fill_triangles(&triangles, color),
+                Primitive::Lines { lines, widths, width, color } => self.draw_lines(&lines, widths.as_deref(), width, color),
+            }
+        }
+    }
+
+    /// Text laid out with egui's fonts but rasterised here from the glyph atlas.
+    fn draw_text(&mut self, texts: Vec<Text>) {
+        let fonts = Fonts::new(1.0, 8192, FontDefinitions::default());
+        let mut placed = Vec::new();
+        for text in texts {
+            let galley = fonts.layout_no_wrap(text.text, FontId::proportional(text.size), Color32::WHITE);
+            let rect = text.align.anchor_rect(Rect::from_min_size(pos2(text.position.0, text.position.1), galley.size()));
+            placed.push((galley, (rect.min.x, rect.min.y), text.color));
+        }
+        let Some(ImageData::Font(atlas)) = fonts.font_image_delta().map(|d| d.image) else {
+            return;
+        };
+
+        for (galley, corner, color) in placed {
+            for glyph in galley.rows.iter().flat_map(|r| &r.glyphs) {
+                let uv = glyph.uv_rect;
+                if uv.is_nothing() {
+                    continue;
+                }
+                let left = corner.0 + glyph.pos.x + uv.offset.x;
+                let top = corner.1 + glyph.pos.y + uv.offset.y;
+                let (xs, ys) = self.pixel_range((left, top), (left + uv.size.x, top + uv.size.y));
+                for y in ys {
+                    for x in xs.clone() {
+                        // where in the glyph's texels this pixel's centre falls
+                        let tx = uv.min[0] as f32 + (x as f32 + 0.5 - left) / uv.size.x * (uv.max[0] - uv.min[0]) as f32;
+                        let ty = uv.min[1] as f32 + (y as f32 + 0.5 - top) / uv.size.y * (uv.max[1] - uv.min[1]) as f32;
+                        self.blend(x, y, color, coverage_at(&atlas, tx, ty));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn distance_to_segment(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = dx * dx + dy * dy;
+    let t = if length > 0.0 { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+    let (cx, cy) = (a.0 + dx * t, a.1 + dy * t);
+    ((p.0 - cx).powi(2) + (p.1 - cy).powi(2)).sqrt()
+}
+
+fn coverage_at(atlas: &FontImage, x: f32, y: f32) -> f32 {
+    let x = (x.max(0.0) as usize).min(atlas.size[0] - 1);
+    let y = (y.max(0.0) as usize).min(atlas.size[1] - 1);
+    atlas.pixels[y * atlas.size[0] + x]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui;
+    use image::DynamicImage;
+
+    const LAND: Rgba<u8> = Rgba([200, 100, 50, 255]);
+
+    /// A world whose left half is land and right half sea, twice as wide as it is tall.
+    fn half_land_world() -> WorldInfo {
+        let image = RgbaImage::from_fn(64, 32, |x, _| if x < 32 { LAND } else { Rgba([0, 0, 0, 0]) });
+        let mut world_info = WorldInfo::new(None);
+        gui::create_world(&egui::Context::default(), &mut world_info, DynamicImage::ImageRgba8(image));
+        world_info
+    }
+
+    #[test]
+    fn exports_the_base_texture() {
+        let world_info = half_land_world();
+        let area = ExportArea::world(&world_info, 2.0).unwrap();
+        let excluded: Vec<LayerKind> = world_info.layers.layers.iter().map(|l| l.kind).filter(|k| *k != LayerKind::BaseTexture).collect();
+        let path = std::env::temp_dir().join(format!("world_manager_export_{}.png", std::process::id()));
+        let mut job = ExportJob::new(&world_info, &area, &excluded, path.to_string_lossy().into_owned());
+        while !job.is_finished() {
+            job.tick();
+        }
+
+        let image = image::open(&path).unwrap().into_rgba8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.dimensions(), (128, 64));
+        assert_eq!(*image.get_pixel(10, 10), LAND);
+        assert_eq!(*image.get_pixel(50, 60), LAND);
+        assert_eq!(image.get_pixel(80, 10)[3], 0);
+        assert_eq!(image.get_pixel(120, 60)[3], 0);
+    }
+
+    #[test]
+    fn caps_the_size() {
+        let world_info = half_land_world();
+        let area = ExportArea::world(&world_info, 10_000.0).unwrap();
+        assert!(area.capped);
+        assert!(area.width as u64 * area.height as u64 <= constants::MAX_EXPORT_PIXELS);
+        assert!(!ExportArea::world(&world_info, 1.0).unwrap().capped);
+    }
+}
@@ -1,5 +1,5 @@
-use std::cmp::Ordering;
-use std::collections::VecDeque;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet, VecDeque};
 use std::f32::consts::PI;
 
 use egui::{Pos2, Vec2};
@@ -7,6 +7,8 @@ use image::{DynamicImage, GenericImage, GenericImageView, Rgba};
 use rand::Rng;
 
 use crate::constants;
+use crate::grid::Grid;
+use crate::seed;
 use crate::utils;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -183,43 +185,11 @@ impl Line {
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Shape {
-    pub(crate) vertices: Vec<Vertex>,
-}
-
-impl Shape {
-    pub fn new_rectangle(aspect_ratio: f32) -> Self {
-        let mut vertices = Vec::new();
-        let mut sign_x = -1.0;
-        let mut sign_y = 1.0;
-        for numbers in 1..6 {
-            vertices.push(Vertex {
-                position: [sign_x * aspect_ratio, sign_y],
-                tex_coords: [sign_x / 2.0 + 0.5, sign_y / 2.0 + 0.5],
-            });
-            if numbers == 3 {
-                vertices.push(Vertex {
-                    position: [sign_x * aspect_ratio, sign_y],
-                    tex_coords: [sign_x / 2.0 + 0.5, sign_y / 2.0 + 0.5],
-                });
-            }
-            if numbers % 2 == 1 {
-                sign_y *= -1.0;
-            } else {
-                sign_x *= -1.0;
-            }
-        }
-
-        Shape { vertices }
-    }
-}
-
 struct PixelIsland {
     pixel_coordinates: Vec<(u32, u32)>,
 }
 
-pub fn generate_mesh_from_image(dyn_tex: &mut DynamicImage) -> Vec<Line> {
+pub fn generate_mesh_from_image(dyn_tex: &mut DynamicImage, world_seed: u64) -> Vec<Line> {
     let mut start_x: i32 = -1;
     let mut start_y: i32 = -1;
     for x in 0..dyn_tex.width() {
@@ -258,7 +228,7 @@ pub fn generate_mesh_from_image(dyn_tex: &mut DynamicImage) -> Vec<Line> {
             0: [255, 255, 255, 255],
         };
 
-        let mut rng = rand::thread_rng();
+        let mut rng = seed::rng(world_seed, "island-colours");
 
         for x in 0..dyn_tex.width() {
             for y in 0..dyn_tex.height() {
@@ -539,6 +509,8 @@ pub fn fill(
     let mut cells: VecDeque<(u32, u32)> = VecDeque::new();
 
     let mut pixels = Vec::new();
+    // membership for `pixels`, searching the vec made big islands quadratic
+    let mut edge_pixels = HashSet::new();
 
     cells.push_back((x, y));
     //pixels.push((x, y));
@@ -575,11 +547,11 @@ pub fn fill(
 
                 if new_y < height && new_x < width {
                     cells.push_back((new_x, new_y));
-                    if img.get_pixel(new_x, new_y).0[3] == 0 && !pixels.contains(&(x, y)) {
+                    if img.get_pixel(new_x, new_y).0[3] == 0 && edge_pixels.insert((x, y)) {
                         pixels.push((x, y));
                     }
                 } else {
-                    if !pixels.contains(&(x, y)) {
+                    if edge_pixels.insert((x, y)) {
                         pixels.push((x, y));
                     }
                 }
@@ -589,3 +561,250 @@ pub fn fill(
 
     (pixels, top_right, bottom_left)
 }
+
+/// Outline of a set of cells as the pixel edges between the set and everything around it.
+pub fn outline_cells(width: u32, height: u32, cells: &[usize], inside: impl Fn(usize) -> bool) -> Vec<Line> {
+    let mut lines = Vec::new();
+    for i in cells {
+        let x = (*i as u32 % width) as i32;
+        let y = (*i as u32 / width) as i32;
+        // neighbour offset and the corners of the edge shared with it
+        let edges = [
+            ((-1, 0), (x, y), (x, y + 1)),
+            ((1, 0), (x + 1, y), (x + 1, y + 1)),
+            ((0, -1), (x, y), (x + 1, y)),
+            ((0, 1), (x, y + 1), (x + 1, y + 1)),
+        ];
+        for ((dx, dy), a, b) in edges {
+            let nx = x + dx;
+            let ny = y + dy;
+            let outside = nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32
+                || !inside((ny as u32 * width + nx as u32) as usize);
+            if outside {
+                let start = utils::pixel_to_world(a.0 as f32, a.1 as f32, width, height);
+                let end = utils::pixel_to_world(b.0 as f32, b.1 as f32, width, height);
+                lines.push(Line::new(start, end));
+            }
+        }
+    }
+    lines
+}
+
+#[derive(Copy, Clone, PartialEq)]
+struct FloodCell {
+    cost: f32,
+    index: usize,
+}
+
+impl Eq for FloodCell {}
+
+impl Ord for FloodCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cost.total_cmp(&other.cost).then(self.index.cmp(&other.index))
+    }
+}
+
+impl PartialOrd for FloodCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Floods out from every source at once, always growing whichever front is cheapest so far,
+/// and gives each cell to the source that reached it first (Dijkstra's algorithm from many
+/// sources). It shares nothing with `fill`, which recolours an image to trace its islands.
+/// `step_cost(from, to)` is the cost of moving between neighbouring cells, infinite where
+/// the flood can't go. Returns the source each cell belongs to and what it cost to get there.
+pub fn cost_fill(
+    width: u32,
+    height: u32,
+    sources: &[usize],
+    step_cost: impl Fn(usize, usize) -> f32,
+) -> (Grid<Option<u16>>, Grid<f32>) {
+    let mut owner = Grid::new(width, height, None);
+    let mut cost = Grid::new(width, height, f32::INFINITY);
+    let mut open = BinaryHeap::new();
+
+    for (id, source) in sources.iter().enumerate() {
+        if cost.data[*source] > 0.0 {
+            cost.data[*source] = 0.0;
+            owner.data[*source] = Some(id as u16);
+            open.push(Reverse(FloodCell { cost: 0.0, index: *source }));
+        }
+    }
+
+    while let Some(Reverse(cell)) = open.pop() {
+        if cell.cost > cost.data[cell.index] {
+            continue;
+        }
+        let (x, y) = owner.coords(cell.index);
+        for (dx, dy) in constants::NEIGHBOURS_8 {
+            let nx = x as i32 + dx;
+            let ny = y as i32 + dy;
+            if !owner.in_bounds(nx, ny) {
+                continue;
+            }
+            let next = owner.index(nx as u32, ny as u32);
+            let distance = if dx != 0 && dy != 0 { std::f32::consts::SQRT_2 } else { 1.0 };
+            let next_cost = cell.cost + distance * step_cost(cell.index, next);
+            if next_cost < cost.data[next] {
+                cost.data[next] = next_cost;
+                owner.data[next] = owner.data[cell.index];
+                open.push(Reverse(FloodCell { cost: next_cost, index: next }));
+            }
+        }
+    }
+
+    (owner, cost)
+}
+
+/// Buffers for A* over a whole grid, kept between searches and only reset where they were used.
+pub struct PathSearch {
+    cost: Vec<f32>,
+    came_from: Vec<usize>,
+    touched: Vec<usize>,
+}
+
+impl PathSearch {
+    pub fn new(cells: usize) -> Self {
+        PathSearch {
+            cost: vec![f32::INFINITY; cells],
+            came_from: vec![usize::MAX; cells],
+            touched: Vec::new(),
+        }
+    }
+
+    fn visit(&mut self, cell: usize, cost: f32, from: usize) {
+        if self.cost[cell] == f32::INFINITY {
+            self.touched.push(cell);
+        }
+        self.cost[cell] = cost;
+        self.came_from[cell] = from;
+    }
+
+    /// Cheapest path of cells from `start` to `goal`, `None` when the goal can't be reached.
+    /// `heuristic_scale` is the least a step can cost per cell, which keeps the straight line
+    /// estimate from overshooting.
+    pub fn a_star(
+        &mut self,
+        grid: &Grid<bool>,
+        start: usize,
+        goal: usize,
+        passable: impl Fn(usize) -> bool,
+        step_cost: impl Fn(usize, usize) -> f32,
+        heuristic_scale: f32,
+    ) -> Option<Vec<usize>> {
+        for cell in self.touched.drain(..) {
+            self.cost[cell] = f32::INFINITY;
+            self.came_from[cell] = usize::MAX;
+        }
+        if !passable(start) || !passable(goal) {
+            return None;
+        }
+        let (gx, gy) = grid.coords(goal);
+        let heuristic = |i: usize| {
+            let (x, y) = grid.coords(i);
+            let dx = x as f32 - gx as f32;
+            let dy = y as f32 - gy as f32;
+            (dx * dx + dy * dy).sqrt() * heuristic_scale
+        };
+
+        let mut open = BinaryHeap::new();
+        self.visit(start, 0.0, usize::MAX);
+        open.push(Reverse(FloodCell { cost: heuristic(start), index: start }));
+
+        while let Some(Reverse(cell)) = open.pop() {
+            if cell.index == goal {
+                let mut path = vec![goal];
+                let mut current = goal;
+                while self.came_from[current] != usize::MAX {
+                    current = self.came_from[current];
+                    path.push(current);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let here = self.cost[cell.index];
+            if cell.cost > here + heuristic(cell.index) {
+                continue;
+            }
+
+            let (x, y) = grid.coords(cell.index);
+            for (dx, dy) in constants::NEIGHBOURS_8 {
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+                if !grid.in_bounds(nx, ny) {
+                    continue;
+                }
+                let next = grid.index(nx as u32, ny as u32);
+                if !passable(next) {
+                    continue;
+                }
+                let step = if dx != 0 && dy != 0 { std::f32::consts::SQRT_2 } else { 1.0 };
+                let next_cost = here + step * step_cost(cell.index, next);
+                if next_cost < self.cost[next] {
+                    self.visit(next, next_cost, cell.index);
+                    open.push(Reverse(FloodCell { cost: next_cost + heuristic(next), index: next }));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Triangles covering a set of cells, one strip for every run of them along a row. A strip
+/// has corners along its top and bottom wherever a run of the next row ends, so that no
+/// corner is on the side of another strip and they stay closed up when bent by a projection.
+pub fn fill_cells(width: u32, height: u32, inside: impl Fn(usize) -> bool) -> Vec<Vertex> {
+    let runs: Vec<Vec<(u32, u32)>> = (0..height)
+        .map(|y| {
+            let mut runs = Vec::new();
+            let mut x = 0;
+            while x < width {
+                if !inside((y * width + x) as usize) {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < width && inside((y * width + x) as usize) {
+                    x += 1;
+                }
+                runs.push((start, x));
+            }
+            runs
+        })
+        .collect();
+
+    let mut triangles = Vec::new();
+    for y in 0..height {
+        for &(start, end) in &runs[y as usize] {
+            // where the runs of a neighbouring row end along this one
+            let side = |row: Option<&Vec<(u32, u32)>>| {
+                let mut xs = vec![start];
+                for &(s, e) in row.into_iter().flatten() {
+                    xs.extend([s, e].into_iter().filter(|x| *x > start && *x < end));
+                }
+                xs.push(end);
+                xs.sort_unstable();
+                xs
+            };
+            let top = side(y.checked_sub(1).map(|above| &runs[above as usize]));
+            let bottom = side(runs.get(y as usize + 1));
+            let point = |x: u32, row: u32| utils::pixel_to_world(x as f32, row as f32, width, height);
+
+            // along both sides at once, always stepping the one that is further behind
+            let (mut i, mut j) = (0, 0);
+            while i + 1 < top.len() || j + 1 < bottom.len() {
+                let (t, b) = (point(top[i], y), point(bottom[j], y + 1));
+                if j + 1 == bottom.len() || (i + 1 < top.len() && top[i + 1] <= bottom[j + 1]) {
+                    triangles.extend([t, point(top[i + 1], y), b]);
+                    i += 1;
+                } else {
+                    triangles.extend([t, point(bottom[j + 1], y + 1), b]);
+                    j += 1;
+                }
+            }
+        }
+    }
+    triangles
+}
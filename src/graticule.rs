@@ -0,0 +1,155 @@
+use egui::Align2;
+
+use crate::{constants, geometry::{Line, Vertex}, utils};
+
+#[derive(Clone, Debug)]
+pub struct GraticuleSettings {
+    /// Degrees between meridians and between parallels.
+    pub(crate) spacing: f32,
+    /// The equator, tropics and polar circles, at the climate's axial tilt.
+    pub(crate) special_parallels: bool,
+    /// Degrees along the edges of the view.
+    pub(crate) labels: bool,
+}
+
+impl Default for GraticuleSettings {
+    fn default() -> Self {
+        GraticuleSettings {
+            spacing: 15.0,
+            special_parallels: true,
+            labels: true,
+        }
+    }
+}
+
+/// The lines of the graticule, the named parallels kept apart to be drawn differently.
+#[derive(Clone, Debug, Default)]
+pub struct Graticule {
+    pub(crate) lines: Vec<Line>,
+    pub(crate) special_lines: Vec<Line>,
+}
+
+/// A degree label at the edge of what is shown, `align` saying which side of `position`
+/// the text goes so that it stays inside.
+pub struct EdgeLabel {
+    pub(crate) position: Vertex,
+    pub(crate) text: String,
+    pub(crate) align: Align2,
+}
+
+/// How far to move a label in from the edge it is aligned to, in points.
+pub fn label_padding(align: Align2) -> egui::Vec2 {
+    let padding = constants::GRATICULE_LABEL_PADDING;
+    egui::vec2(-align.x().to_sign() * padding, -align.y().to_sign() * padding)
+}
+
+/// The equator, the tropics and the polar circles, which move with the tilt.
+pub fn special_parallels(axial_tilt: f32) -> [(&'static str, f32); 5] {
+    [
+        ("Arctic Circle", 90.0 - axial_tilt),
+        ("Tropic of Cancer", axial_tilt),
+        ("Equator", 0.0),
+        ("Tropic of Capricorn", -axial_tilt),
+        ("Antarctic Circle", axial_tilt - 90.0),
+    ]
+}
+
+/// Meridians and parallels every `spacing` degrees over the world, with each line cut into
+/// short pieces so that it can follow a projection.
+pub fn graticule(settings: &GraticuleSettings, axial_tilt: f32, top_left: (f32, f32), bottom_right: (f32, f32)) -> Graticule {
+    let spacing = settings.spacing.max(1.0);
+    let mut graticule = Graticule::default();
+    let point = |lon: f32, lat: f32| utils::lon_lat_to_world(lon, lat, top_left, bottom_right);
+
+    let steps = |from: f32, to: f32| {
+        let count = ((to - from) / constants::GRATICULE_STEP).ceil().max(1.0) as usize;
+        (0..=count).map(move |i| from + (to - from) * i as f32 / count as f32)
+    };
+    let parallel = |lat: f32| {
+        let points: Vec<Vertex> = steps(-180.0, 180.0).map(|lon| point(lon, lat)).collect();
+        points.windows(2).map(|p| Line::new(p[0], p[1])).collect::<Vec<Line>>()
+    };
+
+    for i in 0..=(360.0 / spacing) as i32 {
+        let lon = -180.0 + i as f32 * spacing;
+        let points: Vec<Vertex> = steps(-90.0, 90.0).map(|lat| point(lon, lat)).collect();
+        graticule.lines.extend(points.windows(2).map(|p| Line::new(p[0], p[1])));
+    }
+    for i in 1..(90.0 / spacing).ceil() as i32 {
+        for lat in [i as f32 * spacing, -i as f32 * spacing] {
+            graticule.lines.extend(parallel(lat));
+        }
+    }
+    if settings.special_parallels {
+        for (_, lat) in special_parallels(axial_tilt) {
+            graticule.special_lines.extend(parallel(lat));
+        }
+    } else {
+        graticule.lines.extend(parallel(0.0));
+    }
+    graticule
+}
+
+/// Labels for the meridians crossing the top of the view and the parallels crossing its
+/// left side, with the names of the special parallels on the right, `view_top_left` and
+/// `view_bottom_right` being its corners in the world. Lines closer together than
+/// `min_gap` world units are only labelled every so often.
+pub fn edge_labels(
+    settings: &GraticuleSettings,
+    axial_tilt: f32,
+    top_left: (f32, f32),
+    bottom_right: (f32, f32),
+    view_top_left: (f32, f32),
+    view_bottom_right: (f32, f32),
+    min_gap: f32,
+) -> Vec<EdgeLabel> {
+    let mut labels = Vec::new();
+    if !settings.labels {
+        return labels;
+    }
+    let spacing = settings.spacing.max(1.0);
+    // how many lines to a label along each edge
+    let every = |world_span: f32, degrees: f32| (min_gap / (world_span.abs() * spacing / degrees)).ceil().max(1.0) as i32;
+    let meridians_every = every(bottom_right.0 - top_left.0, 360.0);
+    let parallels_every = every(bottom_right.1 - top_left.1, 180.0);
+    let corner = |x: f32, y: f32| utils::world_to_lon_lat(Vertex { position: [x, y], tex_coords: [x, y] }, top_left, bottom_right);
+    let (west, north) = corner(view_top_left.0, view_top_left.1);
+    let (east, south) = corner(view_bottom_right.0, view_bottom_right.1);
+
+    let top = view_top_left.1.min(top_left.1);
+    let first = (west.max(-180.0) / spacing).ceil() as i32;
+    let last = (east.min(180.0) / spacing).floor() as i32;
+    for i in (first..=last).filter(|i| i % meridians_every == 0) {
+        let lon = i as f32 * spacing;
+        let mut position = utils::lon_lat_to_world(lon, 0.0, top_left, bottom_right);
+        position.position[1] = top;
+        labels.push(EdgeLabel { position, text: utils::format_longitude(lon), align: Align2::CENTER_TOP });
+    }
+
+    let left = view_top_left.0.max(top_left.0);
+    let first = (south.max(-90.0) / spacing).ceil() as i32;
+    let last = (north.min(90.0) / spacing).floor() as i32;
+    // the poles are points, so have no line to label
+    for i in (first..=last).filter(|i| i % parallels_every == 0) {
+        let lat = i as f32 * spacing;
+        if lat.abs() >= 90.0 {
+            continue;
+        }
+        let mut position = utils::lon_lat_to_world(0.0, lat, top_left, bottom_right);
+        position.position[0] = left;
+        labels.push(EdgeLabel { position, text: utils::format_latitude(lat), align: Align2::LEFT_CENTER });
+    }
+
+    if settings.special_parallels {
+        let right = view_bottom_right.0.min(bottom_right.0);
+        for (name, lat) in special_parallels(axial_tilt) {
+            if lat < south || lat > north {
+                continue;
+            }
+            let mut position = utils::lon_lat_to_world(0.0, lat, top_left, bottom_right);
+            position.position[0] = right;
+            labels.push(EdgeLabel { position, text: name.to_string(), align: Align2::RIGHT_BOTTOM });
+        }
+    }
+    labels
+}
@@ -0,0 +1,73 @@
+use image::{DynamicImage, GenericImageView};
+
+/// A row-major raster laid out like the base image (y grows downwards).
+#[derive(Clone, Debug)]
+pub struct Grid<T> {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) data: Vec<T>,
+}
+
+impl<T: Copy> Grid<T> {
+    pub fn new(width: u32, height: u32, value: T) -> Self {
+        Grid {
+            width,
+            height,
+            data: vec![value; (width * height) as usize],
+        }
+    }
+
+    pub fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> T {
+        self.data[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, value: T) {
+        let i = self.index(x, y);
+        self.data[i] = value;
+    }
+
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width as i32 && y < self.height as i32
+    }
+
+    pub fn coords(&self, index: usize) -> (u32, u32) {
+        (index as u32 % self.width, index as u32 / self.width)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+impl Grid<bool> {
+    /// Land mask of an image, a pixel is land when its alpha is not zero.
+    pub fn from_alpha(img: &DynamicImage) -> Self {
+        let mut mask = Grid::new(img.width(), img.height(), false);
+        for (x, y, pix) in img.pixels() {
+            if pix.0[3] != 0 {
+                mask.set(x, y, true);
+            }
+        }
+        mask
+    }
+}
+
+impl Grid<f32> {
+    pub fn min_max(&self) -> (f32, f32) {
+        let mut min = f32::MAX;
+        let mut max = f32::MIN;
+        for v in &self.data {
+            min = min.min(*v);
+            max = max.max(*v);
+        }
+        (min, max)
+    }
+}
@@ -1,19 +1,19 @@
-use std::{alloc::System, collections::VecDeque, ops::Deref, path::PathBuf, time::Instant};
+use std::{alloc::System, collections::VecDeque, ops::Deref, path::PathBuf, sync::Arc, time::Instant};
 
 use egui::{epaint::tessellator::path, Context, TextBuffer, TextureHandle, Vec2};
-use glium::Display;
-use image::{DynamicImage, GenericImage, GenericImageView, Rgba};
+use image::{DynamicImage, GenericImage, GenericImageView, Rgba, RgbaImage};
 use rand::Rng;
 
 use crate::{
-    info::{GUIInfo, InputInfo, WorldInfo},
-    texture_manager, geometry, utils,
+    info::{GUIInfo, InputInfo, WorldInfo, VertexShaderInfo, MapTool},
+    texture_manager, geometry, utils, constants, grid::Grid, terrain, erosion::{ErosionJob, ErosionSimulation}, simulate::OceanFlow, climate::{self, ClimateView}, kingdoms, settlements, roads, navigation, names, rivers::{self, FlowMethod}, lakes, tectonics, worldgen, seed,
+    geometry::{Line, Vertex}, layers::LayerKind, export::{self, ExportArea, ExportRegion}, colormap::{self, Legend, Palette, ScalarField}, relief, projection::{self, Projection}, lines::{LineCap, LineJoin},
 };
 
 pub fn run(
     egui_ctx: &Context,
-    dis: &Display,
     input: &InputInfo,
+    vertex_info: &VertexShaderInfo,
     mut gui_info: GUIInfo,
     world_info: &mut WorldInfo,
 ) -> (bool, GUIInfo) {
@@ -22,7 +22,12 @@ pub fn run(
 
     let mut quit = false;
 
-    let mut new_world_menu = egui::Window::new("New World");
+    let new_world_menu = egui::Window::new("New World").vscroll(true);
+
+    sync_simulations(egui_ctx, world_info);
+    sync_projection(egui_ctx, world_info);
+
+    egui::TopBottomPanel::bottom("status").show(egui_ctx, |ui| status_bar(ui, vertex_info, world_info));
 
     egui::SidePanel::show(main_panel, egui_ctx, |ui| {
         ui.heading("Actions");
@@ -37,12 +42,45 @@ pub fn run(
         if ui.button("Quit").clicked() {
             quit = true;
         }
+
+        if ui.button("Inspector").clicked() {
+            gui_info.inspector_opened = !gui_info.inspector_opened;
+        }
+
+        if ui.button("Export").clicked() {
+            gui_info.export_opened = !gui_info.export_opened;
+        }
+
+        ui.separator();
+        simulation_panel(ui, world_info);
+        ui.separator();
+        layer_panel(ui, &mut gui_info, world_info);
+        ui.separator();
+        projection_menu(ui, world_info);
         //ui.add(egui::Slider::new(&mut input.zoom_modifier, 0.01..=0.05).text("Zoom Speed"));
     });
 
+    if gui_info.inspector_opened {
+        egui::Window::new("Inspector").vscroll(true).show(egui_ctx, |ui| inspector(ui, world_info));
+    }
+
+    legend(egui_ctx, world_info);
+
+    if gui_info.export_opened {
+        let screen = egui_ctx.input().screen_rect().size() * egui_ctx.pixels_per_point();
+        egui::Window::new("Export").vscroll(true).show(egui_ctx, |ui| export_menu(ui, vertex_info, [screen.x, screen.y], world_info));
+    }
+
     if gui_info.new_menu_opened {
         egui::Window::show(new_world_menu, egui_ctx, |ui| {
             ui.heading("New World Menu");
+            ui.horizontal(|ui| {
+                ui.label("World Seed");
+                ui.add(egui::DragValue::new(&mut world_info.seed));
+                if ui.button("roll").clicked() {
+                    world_info.seed = rand::thread_rng().gen();
+                }
+            });
             if ui.button("open base image").clicked() {
                 let document_dir = dirs_next::document_dir().unwrap();
                 let document_dir = document_dir.into_os_string().into_string().unwrap();
@@ -54,31 +92,16 @@ pub fn run(
                 if path_to_texture.is_some() {
                     let path_to_texture = path_to_texture.unwrap();
                     let dyn_tex = texture_manager::get_dynamic_image(&path_to_texture);
-                    let mut dyn_tex_copy = DynamicImage::clone(&dyn_tex);
-
-                    //calculating image width in world units
-                    let width = dyn_tex.width();
-                    let height = dyn_tex.height();
-                    let aspect = (width as f32)/(height as f32);
-                    let x: f32 = aspect;
-                    let y: f32 = -1.0;
-                    world_info.bottom_right = (x*aspect,y);
-                    world_info.top_left = (-1.0*aspect,1.0);
-
-                    
-                    let lines = geometry::generate_mesh_from_image(&mut dyn_tex_copy);
-                    world_info.lines = lines;
-                    println!("length of: {}", world_info.lines.len());
-                    let tri = utils::vertices_from_lines(0.01,&world_info.lines);
-                    world_info.triangles = tri;
-                    //println!("vertice representation {:?}", world_info.triangles);
-                    //println!("{:?}", &world_info.lines);
-
-                    let world_tex = texture_manager::get_texture_data(dis, egui_ctx, &dyn_tex);
-                    world_info.world_texture = Some(world_tex);
+                    create_world(egui_ctx, world_info, dyn_tex);
                 }
             }
 
+            ui.separator();
+            worldgen_menu(ui, egui_ctx, world_info);
+            ui.separator();
+            tectonics_menu(ui, egui_ctx, world_info);
+            ui.separator();
+
             if world_info.world_texture.is_some() {
                 let tex_han: &TextureHandle =
                     &world_info.world_texture.as_ref().unwrap().gui_texture;
@@ -88,7 +111,29 @@ pub fn run(
 
                 let slider_ocean = egui::Slider::new(&mut world_info.ocean_line_num, 1..=20).text("Ocean Line #");
                 ui.add(slider_ocean);
-                
+                ui.add(egui::Slider::new(&mut world_info.ocean_steps, 100..=10000).logarithmic(true).text("Ocean Steps"));
+                if ui.button("simulate ocean currents").clicked() {
+                    let flow = OceanFlow::new(world_info.ocean_line_num, world_info.ocean_steps, world_info.lines.clone(), world_info.top_left, world_info.bottom_right);
+                    world_info.scheduler.register(Box::new(flow), None);
+                    world_info.scheduler.play();
+                }
+
+                ui.separator();
+                heightmap_menu(ui, egui_ctx, &mut gui_info, world_info);
+                ui.separator();
+                climate_menu(ui, egui_ctx, &mut gui_info, world_info);
+                ui.separator();
+                color_map_menu(ui, egui_ctx, world_info);
+                ui.separator();
+                kingdom_menu(ui, &mut gui_info, world_info);
+                ui.separator();
+                settlement_menu(ui, world_info);
+                ui.separator();
+                road_menu(ui, world_info);
+                ui.separator();
+                sea_route_menu(ui, &mut gui_info, world_info);
+                ui.separator();
+                names_menu(ui, world_info);
             }
         });
     }
@@ -96,3 +141,1277 @@ pub fn run(
     (quit, gui_info)
 }
 
+
+/// Sets up the world from a base image, whether it was opened or generated.
+/// The alpha channel of the image is the land mask.
+pub fn create_world(egui_ctx: &Context, world_info: &mut WorldInfo, dyn_tex: DynamicImage) {
+    //calculating image width in world units
+    let width = dyn_tex.width();
+    let height = dyn_tex.height();
+    let aspect = (width as f32)/(height as f32);
+    let x: f32 = aspect;
+    let y: f32 = -1.0;
+    // the corners the base image is drawn at, which longitudes and latitudes are read from
+    world_info.bottom_right = (x,y);
+    world_info.top_left = (-1.0*aspect,1.0);
+
+    world_info.sea_level = 0.0;
+    world_info.heightmap = None;
+    world_info.heightmap_preview = None;
+    clear_relief(world_info);
+    world_info.tectonics = None;
+    world_info.ridge_lines.clear();
+    world_info.capitals.clear();
+    clear_derived(world_info);
+    extract_coastlines(egui_ctx, world_info, dyn_tex.into_rgba8());
+    // the same pixels as the world texture, until the sea level is changed
+    world_info.base_image = world_info.world_texture.as_ref().map(|t| Arc::clone(&t.levels));
+}
+
+/// Drops everything worked out from the land mask, which goes stale when the coast moves.
+fn clear_derived(world_info: &mut WorldInfo) {
+    world_info.rivers.clear();
+    world_info.lakes.clear();
+    world_info.climate = None;
+    world_info.climate_preview = None;
+    world_info.ice = None;
+    world_info.kingdoms.clear();
+    world_info.settlements.clear();
+    world_info.roads = roads::RoadNetwork::default();
+    world_info.sea_route = None;
+    if let Some(overlay) = world_info.legend.as_ref().map(|l| l.overlay) {
+        world_info.remove_overlay(overlay);
+    }
+    world_info.mark_dirty();
+}
+
+/// Coastlines, land mask and texture from an image whose alpha channel is the land.
+fn extract_coastlines(egui_ctx: &Context, world_info: &mut WorldInfo, image: RgbaImage) {
+    let dyn_tex = DynamicImage::ImageRgba8(image);
+    let mut dyn_tex_copy = DynamicImage::clone(&dyn_tex);
+    let lines = geometry::generate_mesh_from_image(&mut dyn_tex_copy, world_info.seed);
+    world_info.lines = lines;
+    let mask = Grid::from_alpha(&dyn_tex);
+    world_info.coast_stats = Some(terrain::coast_stats(&mask, &world_info.lines, world_info.top_left, world_info.bottom_right));
+    world_info.islands = terrain::find_areas(&mask, true);
+    world_info.seas = terrain::find_areas(&mask, false);
+    name_areas(world_info);
+    world_info.land_mask = Some(mask);
+    world_info.heightmap_replaced();
+    println!("length of: {}", world_info.lines.len());
+    let tri = utils::vertices_from_lines(0.01,&world_info.lines);
+    world_info.triangles = tri;
+
+    let world_tex = texture_manager::get_texture_data(egui_ctx, dyn_tex.into_rgba8());
+    world_info.world_texture = Some(world_tex);
+    world_info.mark_rasters_dirty();
+}
+
+fn worldgen_menu(ui: &mut egui::Ui, egui_ctx: &Context, world_info: &mut WorldInfo) {
+    ui.heading("Generate");
+    let settings = &mut world_info.worldgen_settings;
+    ui.horizontal(|ui| {
+        ui.label("Size");
+        ui.add(egui::DragValue::new(&mut settings.width).clamp_range(64..=8192));
+        ui.add(egui::DragValue::new(&mut settings.height).clamp_range(64..=8192));
+    });
+    ui.add(egui::Slider::new(&mut settings.continents, 0..=12).text("Continents"));
+    ui.add(egui::Slider::new(&mut settings.land_fraction, 0.0..=1.0).text("Land"));
+    ui.add(egui::Slider::new(&mut settings.warp, 0.0..=2.0).text("Warp"));
+
+    ui.horizontal(|ui| {
+        let mut generate = ui.button("generate world").clicked();
+        if ui.button("roll").clicked() {
+            world_info.seed = rand::thread_rng().gen();
+            generate = true;
+        }
+        if generate {
+            let mask = worldgen::generate_land_mask(&world_info.worldgen_settings, world_info.seed);
+            let image = texture_manager::image_from_mask(&mask);
+            create_world(egui_ctx, world_info, image);
+        }
+    });
+}
+
+fn tectonics_menu(ui: &mut egui::Ui, egui_ctx: &Context, world_info: &mut WorldInfo) {
+    ui.heading("Tectonics");
+    let settings = &mut world_info.tectonic_settings;
+    ui.add(egui::Slider::new(&mut settings.plates, 2..=64).text("Plates"));
+    ui.add(egui::Slider::new(&mut settings.continental_fraction, 0.0..=1.0).text("Continental Plates"));
+    ui.add(egui::Slider::new(&mut settings.uplift, 0.0..=9000.0).text("Uplift (m)"));
+
+    if let Some(mask) = &world_info.land_mask {
+        if ui.button("simulate plates").clicked() {
+            world_info.tectonics = Some(tectonics::simulate_plates(mask.width, mask.height, &world_info.tectonic_settings, world_info.seed));
+            world_info.mark_dirty();
+        }
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Size");
+        ui.add(egui::DragValue::new(&mut world_info.tectonic_settings.width).clamp_range(64..=8192));
+        ui.add(egui::DragValue::new(&mut world_info.tectonic_settings.height).clamp_range(64..=8192));
+    });
+    if ui.button("land mask from plates").clicked() {
+        let settings = world_info.tectonic_settings;
+        let plates = tectonics::simulate_plates(settings.width, settings.height, &settings, world_info.seed);
+        let image = texture_manager::image_from_mask(&plates.land_mask());
+        create_world(egui_ctx, world_info, image);
+        world_info.tectonics = Some(plates);
+        world_info.mark_dirty();
+    }
+
+    if let Some(plates) = &world_info.tectonics {
+        let continents = plates.plates.iter().filter(|p| p.continental).count();
+        ui.label(format!("{} plates, {} continental, {} boundary edges", plates.plates.len(), continents, plates.boundaries.len()));
+    }
+}
+
+fn climate_menu(ui: &mut egui::Ui, egui_ctx: &Context, gui_info: &mut GUIInfo, world_info: &mut WorldInfo) {
+    ui.heading("Climate");
+    let settings = &mut world_info.climate_settings;
+    ui.add(egui::Slider::new(&mut settings.axial_tilt, 0.0..=90.0).text("Axial Tilt (°)"));
+    ui.add(egui::Slider::new(&mut settings.equator_temperature, -20.0..=50.0).text("Equator (°C)"));
+    ui.add(egui::Slider::new(&mut settings.pole_temperature, -60.0..=20.0).text("Poles (°C)"));
+    ui.add(egui::Slider::new(&mut settings.lapse_rate, 0.0..=10.0).text("Lapse Rate (°C/km)"));
+    ui.add(egui::Slider::new(&mut settings.temperature_offset, -15.0..=15.0).text("Global Offset (°C)"));
+
+    if ui.button("simulate climate").clicked() {
+        if let Some(mask) = &world_info.land_mask {
+            let simulated = climate::simulate_climate(mask, world_info.heightmap.as_ref(), &world_info.climate_settings);
+            world_info.ice = Some(climate::ice_cover(&simulated, mask));
+            world_info.climate = Some(simulated);
+            world_info.climate_preview = None;
+            world_info.mark_dirty();
+        }
+    }
+
+    let (Some(climate), Some(mask)) = (&world_info.climate, &world_info.land_mask) else {
+        return;
+    };
+    let name = climate::MONTHS[gui_info.climate_month];
+    ui.add(egui::Slider::new(&mut gui_info.climate_month, 0..=11).text(name));
+    ui.horizontal(|ui| {
+        ui.radio_value(&mut gui_info.climate_view, ClimateView::Temperature, "Temperature");
+        ui.radio_value(&mut gui_info.climate_view, ClimateView::Precipitation, "Precipitation");
+    });
+
+    let month = gui_info.climate_month;
+    let view = gui_info.climate_view;
+    let stale = !matches!(&world_info.climate_preview, Some((_, m, v)) if *m == month && *v == view);
+    if stale {
+        let preview = texture_manager::get_climate_preview(egui_ctx, climate, month, view);
+        world_info.climate_preview = Some((preview, month, view));
+    }
+    if let Some((preview, _, _)) = &world_info.climate_preview {
+        let s = Vec2::new(100.0 * preview.aspect_ratio(), 100.0);
+        ui.image(preview, s);
+    }
+    ui.label(format!("Sea ice covers {:.1}% of the sea", climate.sea_ice_fraction(month, mask) * 100.0));
+    if let Some(ice) = &world_info.ice {
+        let summary = format!("{} cells of glacier, {} of permanent sea ice", ice.glacier_cells, ice.pack_ice_cells);
+        if ui.checkbox(&mut world_info.show_ice, "Show ice").changed() {
+            world_info.mark_dirty();
+        }
+        ui.label(summary);
+    }
+}
+
+fn kingdom_menu(ui: &mut egui::Ui, gui_info: &mut GUIInfo, world_info: &mut WorldInfo) {
+    ui.heading("Kingdoms");
+    let mut placing = gui_info.tool == MapTool::Capital;
+    if ui.checkbox(&mut placing, "Place capitals").changed() {
+        gui_info.tool = if placing { MapTool::Capital } else { MapTool::None };
+    }
+    ui.horizontal(|ui| {
+        ui.label(format!("{} capitals", world_info.capitals.len()));
+        if ui.button("Clear capitals").clicked() {
+            world_info.capitals.clear();
+            world_info.kingdoms.clear();
+            world_info.mark_dirty();
+        }
+    });
+
+    let settings = &mut world_info.kingdom_settings;
+    ui.add(egui::Slider::new(&mut settings.slope_weight, 0.0..=20.0).text("Slope Cost"));
+    ui.add(egui::Slider::new(&mut settings.elevation_weight, 0.0..=20.0).text("Elevation Cost"));
+    ui.add(egui::Slider::new(&mut settings.river_weight, 0.0..=200.0).text("River Cost"));
+    ui.add(egui::Slider::new(&mut settings.water_weight, 1.0..=500.0).logarithmic(true).text("Water Cost"));
+
+    if ui.button("expand kingdoms").clicked() {
+        if let Some(mask) = &world_info.land_mask {
+            if !world_info.capitals.is_empty() {
+                let water = lakes::lake_mask(&world_info.lakes, mask.width, mask.height);
+                let rivers = rivers::river_mask(&world_info.rivers, mask.width, mask.height);
+                world_info.kingdoms = kingdoms::expand_kingdoms(
+                    &world_info.capitals,
+                    mask,
+                    world_info.heightmap.as_ref(),
+                    &water,
+                    &rivers,
+                    &world_info.kingdom_settings,
+                    world_info.seed,
+                );
+                name_kingdoms(world_info);
+                world_info.mark_dirty();
+            }
+        }
+    }
+    if !world_info.kingdoms.is_empty() {
+        ui.collapsing(format!("{} kingdoms", world_info.kingdoms.len()), |ui| {
+            egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                for kingdom in &world_info.kingdoms {
+                    let [r, g, b, _] = kingdom.color.map(|c| (c * 255.0) as u8);
+                    ui.colored_label(egui::Color32::from_rgb(r, g, b), format!("{}: {} cells, capital ({:.2}, {:.2})", kingdom.name, kingdom.cells.len(), kingdom.capital.position[0], kingdom.capital.position[1]));
+                }
+            });
+        });
+    }
+}
+
+fn settlement_menu(ui: &mut egui::Ui, world_info: &mut WorldInfo) {
+    ui.heading("Settlements");
+    let settings = &mut world_info.settlement_settings;
+    ui.add(egui::Slider::new(&mut settings.cities, 0..=50).text("Cities"));
+    ui.add(egui::Slider::new(&mut settings.towns, 0..=200).text("Towns"));
+    ui.add(egui::Slider::new(&mut settings.villages, 0..=1000).text("Villages"));
+    ui.add(egui::Slider::new(&mut settings.city_spacing, 1.0..=200.0).text("City Spacing (cells)"));
+    ui.add(egui::Slider::new(&mut settings.town_spacing, 1.0..=200.0).text("Town Spacing (cells)"));
+    ui.add(egui::Slider::new(&mut settings.village_spacing, 1.0..=200.0).text("Village Spacing (cells)"));
+    ui.collapsing("Habitability", |ui| {
+        ui.add(egui::Slider::new(&mut settings.water_weight, 0.0..=5.0).text("Fresh Water"));
+        ui.add(egui::Slider::new(&mut settings.coast_weight, 0.0..=5.0).text("Coast"));
+        ui.add(egui::Slider::new(&mut settings.temperature_weight, 0.0..=5.0).text("Temperature"));
+        ui.add(egui::Slider::new(&mut settings.fertility_weight, 0.0..=5.0).text("Fertility"));
+        ui.add(egui::Slider::new(&mut settings.elevation_weight, 0.0..=5.0).text("Elevation"));
+    });
+
+    if ui.button("place settlements").clicked() {
+        if let Some(mask) = &world_info.land_mask {
+            let mut fresh_water = lakes::lake_mask(&world_info.lakes, mask.width, mask.height);
+            let rivers = rivers::river_mask(&world_info.rivers, mask.width, mask.height);
+            for i in 0..fresh_water.len() {
+                fresh_water.data[i] |= rivers.data[i];
+            }
+            let score = settlements::habitability(
+                mask,
+                world_info.heightmap.as_ref(),
+                &fresh_water,
+                world_info.climate.as_ref(),
+                &world_info.settlement_settings,
+            );
+            world_info.settlements = settlements::place_settlements(&score, &world_info.settlement_settings, world_info.seed);
+            name_settlements(world_info);
+            world_info.mark_dirty();
+        }
+    }
+    if !world_info.settlements.is_empty() {
+        ui.collapsing(format!("{} settlements", world_info.settlements.len()), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Search");
+                ui.text_edit_singleline(&mut world_info.settlement_search);
+            });
+            let search = world_info.settlement_search.to_lowercase();
+            egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                for settlement in &world_info.settlements {
+                    if !settlement.name.to_lowercase().contains(&search) {
+                        continue;
+                    }
+                    let [x, y] = settlement.position.position;
+                    ui.label(format!(
+                        "{} ({}): {} people, habitability {:.2}, ({:.2}, {:.2})",
+                        settlement.name,
+                        settlement.kind.name(),
+                        settlement.population,
+                        settlement.score,
+                        x,
+                        y
+                    ));
+                }
+            });
+        });
+    }
+}
+
+fn road_menu(ui: &mut egui::Ui, world_info: &mut WorldInfo) {
+    ui.heading("Roads");
+    let settings = &mut world_info.road_settings;
+    ui.add(egui::Slider::new(&mut settings.connections, 1..=6).text("Connections"));
+    ui.add(egui::Slider::new(&mut settings.slope_weight, 0.0..=50.0).text("Slope Cost"));
+    ui.add(egui::Slider::new(&mut settings.bridge_cost, 0.0..=200.0).text("Bridge Cost"));
+    ui.add(egui::Slider::new(&mut settings.reuse_factor, 0.1..=1.0).text("Reuse Factor"));
+
+    ui.horizontal(|ui| {
+        if ui.button("build roads").clicked() {
+            if let Some(mask) = &world_info.land_mask {
+                let water = lakes::lake_mask(&world_info.lakes, mask.width, mask.height);
+                let rivers = rivers::river_mask(&world_info.rivers, mask.width, mask.height);
+                world_info.roads = roads::build_roads(
+                    &world_info.settlements,
+                    mask,
+                    world_info.heightmap.as_ref(),
+                    &water,
+                    &rivers,
+                    &world_info.road_settings,
+                );
+                world_info.mark_dirty();
+            }
+        }
+        if !world_info.roads.edges.is_empty() && ui.button("export roads").clicked() {
+            let path = tinyfiledialogs::save_file_dialog_with_filter(
+                "export the road network",
+                "roads.geojson",
+                &["*.geojson"],
+                "GeoJSON",
+            );
+            if let Some(path) = path {
+                let json = world_info.roads.to_geojson(world_info.top_left, world_info.bottom_right);
+                if let Err(e) = std::fs::write(&path, json) {
+                    println!("could not export roads to {}: {}", path, e);
+                }
+            }
+        }
+    });
+    if !world_info.roads.edges.is_empty() {
+        let bridges: usize = world_info.roads.edges.iter().map(|e| e.bridges.len()).sum();
+        ui.label(format!("{} junctions, {} roads, {} bridges", world_info.roads.nodes.len(), world_info.roads.edges.len(), bridges));
+    }
+}
+
+fn sea_route_menu(ui: &mut egui::Ui, gui_info: &mut GUIInfo, world_info: &mut WorldInfo) {
+    ui.heading("Sea Routes");
+    let mut planning = gui_info.tool == MapTool::SeaRoute;
+    if ui.checkbox(&mut planning, "Plan a voyage (click two points at sea)").changed() {
+        gui_info.route_start = None;
+        gui_info.tool = if planning { MapTool::SeaRoute } else { MapTool::None };
+    }
+    let settings = &mut world_info.sea_route_settings;
+    ui.add(egui::Slider::new(&mut settings.speed, 1.0..=30.0).text("Speed (knots)"));
+    ui.checkbox(&mut settings.use_currents, "Follow ocean currents");
+    if settings.use_currents {
+        ui.add(egui::Slider::new(&mut settings.current_weight, 0.0..=0.9).text("Current Strength"));
+    }
+
+    if let Some(route) = &world_info.sea_route {
+        ui.label(format!(
+            "{:.0} km ({:.0} nmi), {:.1} days at sea",
+            route.length,
+            route.length / constants::KM_PER_NAUTICAL_MILE,
+            route.days
+        ));
+        if ui.button("Clear route").clicked() {
+            world_info.sea_route = None;
+            world_info.mark_dirty();
+        }
+    }
+}
+
+fn names_menu(ui: &mut egui::Ui, world_info: &mut WorldInfo) {
+    ui.heading("Names");
+    let selected = world_info.cultures.get(world_info.culture).map_or("", |c| c.name.as_str()).to_string();
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_label("Culture").selected_text(selected).show_ui(ui, |ui| {
+            for (i, culture) in world_info.cultures.iter().enumerate() {
+                ui.selectable_value(&mut world_info.culture, i, format!("{} ({} words)", culture.name, culture.words.len()));
+            }
+        });
+        if ui.button("load word list").clicked() {
+            let document_dir = dirs_next::document_dir().unwrap();
+            let document_dir = document_dir.into_os_string().into_string().unwrap();
+            let path = tinyfiledialogs::open_file_dialog(
+                "open a word list, one word per line",
+                &document_dir,
+                Some((&["*.txt"; 1], ".txt")),
+            );
+            if let Some(path) = path {
+                match std::fs::read_to_string(&path) {
+                    Ok(text) => {
+                        let name = PathBuf::from(&path).file_stem().map_or("Custom".to_string(), |s| s.to_string_lossy().to_string());
+                        world_info.cultures.push(names::Culture::from_word_list(&name, &text));
+                        world_info.culture = world_info.cultures.len() - 1;
+                    }
+                    Err(e) => println!("could not read word list {}: {}", path, e),
+                }
+            }
+        }
+    });
+    if ui.button("rename everything").clicked() {
+        name_areas(world_info);
+        name_kingdoms(world_info);
+        name_settlements(world_info);
+        name_lakes(world_info);
+        name_rivers(world_info);
+    }
+    ui.horizontal(|ui| {
+        if ui.button("save names").clicked() {
+            let path = tinyfiledialogs::save_file_dialog_with_filter(
+                "save the names of the world",
+                "names.txt",
+                &["*.txt"],
+                "Names",
+            );
+            if let Some(path) = path {
+                if let Err(e) = std::fs::write(&path, names::names_to_text(&saved_names(world_info))) {
+                    println!("could not save names to {}: {}", path, e);
+                }
+            }
+        }
+        if ui.button("load names").clicked() {
+            let document_dir = dirs_next::document_dir().unwrap();
+            let document_dir = document_dir.into_os_string().into_string().unwrap();
+            let path = tinyfiledialogs::open_file_dialog(
+                "open the names of the world",
+                &document_dir,
+                Some((&["*.txt"; 1], ".txt")),
+            );
+            if let Some(path) = path {
+                match std::fs::read_to_string(&path) {
+                    Ok(text) => load_names(world_info, &names::names_from_text(&text)),
+                    Err(e) => println!("could not read names {}: {}", path, e),
+                }
+            }
+        }
+    });
+}
+
+/// Where each kind of named thing is, in the order they are kept in the world.
+fn named_positions(world_info: &WorldInfo) -> [(&'static str, Vec<Vertex>); 6] {
+    let centre = |lines: &[Line]| {
+        let n = lines.len().max(1) as f32;
+        let x = lines.iter().map(|l| l.start.position[0]).sum::<f32>() / n;
+        let y = lines.iter().map(|l| l.start.position[1]).sum::<f32>() / n;
+        Vertex { position: [x, y], tex_coords: [x, y] }
+    };
+    [
+        ("island", world_info.islands.iter().map(|a| a.centre).collect()),
+        ("sea", world_info.seas.iter().map(|a| a.centre).collect()),
+        ("kingdom", world_info.kingdoms.iter().map(|k| k.capital).collect()),
+        ("settlement", world_info.settlements.iter().map(|s| s.position).collect()),
+        ("lake", world_info.lakes.iter().map(|l| centre(&l.lines)).collect()),
+        // rivers by their mouths
+        ("river", world_info.rivers.iter().map(|r| r.lines.last().map_or(centre(&r.lines), |l| l.end)).collect()),
+    ]
+}
+
+/// Every name in the world, with what it names and where, to be saved.
+fn saved_names(world_info: &WorldInfo) -> Vec<names::SavedName> {
+    let names: [Vec<&String>; 6] = [
+        world_info.islands.iter().map(|a| &a.name).collect(),
+        world_info.seas.iter().map(|a| &a.name).collect(),
+        world_info.kingdoms.iter().map(|k| &k.name).collect(),
+        world_info.settlements.iter().map(|s| &s.name).collect(),
+        world_info.lakes.iter().map(|l| &l.name).collect(),
+        world_info.rivers.iter().map(|r| &r.name).collect(),
+    ];
+    let mut saved = Vec::new();
+    for ((kind, positions), names) in named_positions(world_info).into_iter().zip(names) {
+        for (position, name) in positions.into_iter().zip(names) {
+            saved.push(names::SavedName { kind: kind.to_string(), position, name: name.clone() });
+        }
+    }
+    saved
+}
+
+/// Gives everything in the world the saved name of the same kind of thing nearest to it,
+/// leaving what has none nearby as it was.
+fn load_names(world_info: &mut WorldInfo, saved: &[names::SavedName]) {
+    let matched = named_positions(world_info).map(|(kind, positions): (&str, Vec<Vertex>)| names::match_saved_names(saved, kind, &positions));
+    let [islands, seas, kingdoms, settlements, lakes, rivers] = matched;
+    fn apply<'a>(names: impl Iterator<Item = &'a mut String>, matched: Vec<Option<String>>) {
+        for (name, found) in names.zip(matched) {
+            if let Some(found) = found {
+                *name = found;
+            }
+        }
+    }
+    apply(world_info.islands.iter_mut().map(|a| &mut a.name), islands);
+    apply(world_info.seas.iter_mut().map(|a| &mut a.name), seas);
+    apply(world_info.kingdoms.iter_mut().map(|k| &mut k.name), kingdoms);
+    apply(world_info.settlements.iter_mut().map(|s| &mut s.name), settlements);
+    apply(world_info.lakes.iter_mut().map(|l| &mut l.name), lakes);
+    apply(world_info.rivers.iter_mut().map(|r| &mut r.name), rivers);
+}
+
+/// Every named thing in the world, with its name open for editing.
+fn inspector(ui: &mut egui::Ui, world_info: &mut WorldInfo) {
+    fn section<T>(ui: &mut egui::Ui, title: &str, items: &mut [T], row: impl Fn(&mut egui::Ui, &mut T)) {
+        if items.is_empty() {
+            return;
+        }
+        ui.collapsing(format!("{} ({})", title, items.len()), |ui| {
+            egui::ScrollArea::vertical().id_source(title).max_height(200.0).show(ui, |ui| {
+                for item in items.iter_mut() {
+                    ui.horizontal(|ui| row(ui, item));
+                }
+            });
+        });
+    }
+
+    section(ui, "Islands", &mut world_info.islands, |ui, island| {
+        ui.text_edit_singleline(&mut island.name);
+        ui.label(format!("{} cells, centre ({:.2}, {:.2})", island.cells, island.centre.position[0], island.centre.position[1]));
+    });
+    section(ui, "Seas", &mut world_info.seas, |ui, sea| {
+        ui.text_edit_singleline(&mut sea.name);
+        ui.label(format!("{} cells, centre ({:.2}, {:.2})", sea.cells, sea.centre.position[0], sea.centre.position[1]));
+    });
+    section(ui, "Kingdoms", &mut world_info.kingdoms, |ui, kingdom| {
+        ui.text_edit_singleline(&mut kingdom.name);
+        ui.label(format!("{} cells", kingdom.cells.len()));
+    });
+    section(ui, "Settlements", &mut world_info.settlements, |ui, settlement| {
+        ui.text_edit_singleline(&mut settlement.name);
+        ui.label(format!("{}, {} people", settlement.kind.name(), settlement.population));
+    });
+    section(ui, "Lakes", &mut world_info.lakes, |ui, lake| {
+        ui.text_edit_singleline(&mut lake.name);
+        ui.label(format!("{} cells", lake.cells.len()));
+    });
+    section(ui, "Rivers", &mut world_info.rivers, |ui, river| {
+        ui.text_edit_singleline(&mut river.name);
+        ui.label(format!("order {}", river.order));
+    });
+}
+
+fn generate_names(world_info: &WorldInfo, count: usize, stream: &str) -> Vec<String> {
+    match world_info.cultures.get(world_info.culture) {
+        Some(culture) => names::generate_names(culture, count, world_info.seed, stream),
+        None => Vec::new(),
+    }
+}
+
+/// Islands and seas are named largest first, so the biggest always get the first names.
+fn name_areas(world_info: &mut WorldInfo) {
+    let names = generate_names(world_info, world_info.islands.len(), "names-islands");
+    for (island, name) in world_info.islands.iter_mut().zip(names) {
+        island.name = name;
+    }
+    let names = generate_names(world_info, world_info.seas.len(), "names-seas");
+    for (sea, name) in world_info.seas.iter_mut().zip(names) {
+        sea.name = format!("{} Sea", name);
+    }
+}
+
+fn name_kingdoms(world_info: &mut WorldInfo) {
+    let names = generate_names(world_info, world_info.kingdoms.len(), "names-kingdoms");
+    for (kingdom, name) in world_info.kingdoms.iter_mut().zip(names) {
+        kingdom.name = name;
+    }
+}
+
+fn name_settlements(world_info: &mut WorldInfo) {
+    let names = generate_names(world_info, world_info.settlements.len(), "names-settlements");
+    for (settlement, name) in world_info.settlements.iter_mut().zip(names) {
+        settlement.name = name;
+    }
+}
+
+fn name_lakes(world_info: &mut WorldInfo) {
+    let names = generate_names(world_info, world_info.lakes.len(), "names-lakes");
+    for (lake, name) in world_info.lakes.iter_mut().zip(names) {
+        lake.name = format!("Lake {}", name);
+    }
+}
+
+fn name_rivers(world_info: &mut WorldInfo) {
+    let names = generate_names(world_info, world_info.rivers.len(), "names-rivers");
+    for (river, name) in world_info.rivers.iter_mut().zip(names) {
+        river.name = format!("{} River", name);
+    }
+}
+
+fn heightmap_menu(ui: &mut egui::Ui, egui_ctx: &Context, gui_info: &mut GUIInfo, world_info: &mut WorldInfo) {
+    ui.heading("Heightmap");
+    let settings = &mut world_info.heightmap_settings;
+    ui.add(egui::Slider::new(&mut settings.max_elevation, 100.0..=9000.0).text("Max Elevation (m)"));
+    ui.add(egui::Slider::new(&mut settings.max_depth, 100.0..=11000.0).text("Max Depth (m)"));
+    ui.add(egui::Slider::new(&mut settings.roughness, 0.0..=1.0).text("Roughness"));
+
+    let mut drawing = gui_info.tool == MapTool::RidgeLine;
+    if ui.checkbox(&mut drawing, "Draw ridge lines").changed() {
+        gui_info.ridge_start = None;
+        gui_info.tool = if drawing { MapTool::RidgeLine } else { MapTool::None };
+    }
+    ui.horizontal(|ui| {
+        if ui.button("Finish ridge").clicked() {
+            gui_info.ridge_start = None;
+        }
+        if ui.button("Clear ridges").clicked() {
+            gui_info.ridge_start = None;
+            world_info.ridge_lines.clear();
+            world_info.mark_dirty();
+        }
+    });
+
+    if ui.button("generate heightmap").clicked() {
+        if let Some(mask) = &world_info.land_mask {
+            let uplift = world_info.tectonics.as_ref().map(|t| &t.uplift);
+            let heightmap = terrain::generate_heightmap(mask, &world_info.heightmap_settings, &world_info.ridge_lines, uplift, world_info.seed);
+            world_info.heightmap_preview = Some(texture_manager::get_heightmap_preview(egui_ctx, &heightmap));
+            world_info.heightmap = Some(heightmap);
+            world_info.heightmap_replaced();
+            clear_relief(world_info);
+            world_info.lakes.clear();
+            world_info.mark_dirty();
+        }
+    }
+
+    if let Some(preview) = &world_info.heightmap_preview {
+        let s = Vec2::new(100.0 * preview.aspect_ratio(), 100.0);
+        ui.image(preview, s);
+    }
+
+    if world_info.heightmap.is_some() {
+        ui.separator();
+        sea_level_menu(ui, egui_ctx, world_info);
+        ui.separator();
+        erosion_menu(ui, world_info);
+        ui.separator();
+        lake_menu(ui, egui_ctx, world_info);
+        ui.separator();
+        river_menu(ui, world_info);
+        ui.separator();
+        relief_menu(ui, egui_ctx, world_info);
+    }
+}
+
+fn relief_menu(ui: &mut egui::Ui, egui_ctx: &Context, world_info: &mut WorldInfo) {
+    ui.heading("Relief");
+    let settings = &mut world_info.relief_settings;
+    ui.add(egui::Slider::new(&mut settings.azimuth, 0.0..=360.0).text("Sun Azimuth (°)"));
+    ui.add(egui::Slider::new(&mut settings.altitude, 1.0..=90.0).text("Sun Altitude (°)"));
+    ui.add(egui::Slider::new(&mut settings.exaggeration, 1.0..=100.0).logarithmic(true).text("Exaggeration"));
+    if ui.button("shade relief").clicked() {
+        if let (Some(heightmap), Some(mask)) = (&world_info.heightmap, &world_info.land_mask) {
+            let shade = relief::hillshade(heightmap, &world_info.relief_settings);
+            let image = relief::hillshade_image(&shade, mask, &world_info.relief_settings);
+            world_info.hillshade = Some(texture_manager::get_texture_data(egui_ctx, image.into_rgba8()));
+            world_info.mark_rasters_dirty();
+        }
+    }
+
+    let settings = &mut world_info.relief_settings;
+    ui.add(egui::Slider::new(&mut settings.contour_interval, 10.0..=2000.0).logarithmic(true).text("Contour Interval (m)"));
+    ui.add(egui::Slider::new(&mut settings.index_every, 1..=10).text("Index Contour Every"));
+    ui.checkbox(&mut settings.bathymetry, "Contour the sea floor");
+    ui.horizontal(|ui| {
+        if ui.button("draw contours").clicked() {
+            if let Some(heightmap) = &world_info.heightmap {
+                world_info.contours = relief::contours(heightmap, &world_info.relief_settings);
+                world_info.mark_dirty();
+            }
+        }
+        if ui.button("clear").clicked() {
+            clear_relief(world_info);
+        }
+    });
+    let contours = &world_info.contours;
+    if !contours.lines.is_empty() || !contours.index_lines.is_empty() {
+        ui.label(format!("{} contour segments", contours.lines.len() + contours.index_lines.len()));
+    }
+}
+
+/// Moves the coast to where the heightmap meets the sea level.
+fn apply_sea_level(egui_ctx: &Context, world_info: &mut WorldInfo) {
+    if let (Some(heightmap), Some(base)) = (&world_info.heightmap, &world_info.base_image) {
+        let mask = terrain::sea_level_mask(heightmap, world_info.sea_level);
+        let image = texture_manager::image_at_sea_level(&base[0], &mask);
+        let islands = std::mem::take(&mut world_info.islands);
+        let seas = std::mem::take(&mut world_info.seas);
+        clear_derived(world_info);
+        extract_coastlines(egui_ctx, world_info, image);
+        // the same islands and seas keep the names they were given
+        terrain::carry_names(&islands, &mut world_info.islands);
+        terrain::carry_names(&seas, &mut world_info.seas);
+    }
+}
+
+/// Drops the hillshade and contours, which go stale when the heightmap changes.
+fn clear_relief(world_info: &mut WorldInfo) {
+    world_info.hillshade = None;
+    world_info.contours = relief::Contours::default();
+    world_info.mark_rasters_dirty();
+}
+
+fn sea_level_menu(ui: &mut egui::Ui, egui_ctx: &Context, world_info: &mut WorldInfo) {
+    ui.heading("Sea Level");
+    ui.add(egui::Slider::new(&mut world_info.sea_level, -1000.0..=1000.0).text("Sea Level (m)"));
+    ui.horizontal(|ui| {
+        if ui.button("ice age").clicked() {
+            world_info.sea_level = -120.0;
+        }
+        if ui.button("present").clicked() {
+            world_info.sea_level = 0.0;
+        }
+        if ui.button("after the flood").clicked() {
+            world_info.sea_level = 200.0;
+        }
+    });
+
+    if ui.button("apply sea level").clicked() {
+        apply_sea_level(egui_ctx, world_info);
+    }
+    if let Some(stats) = &world_info.coast_stats {
+        ui.label(format!(
+            "{:.1}% land, {} islands, {:.0} km of coast",
+            stats.land_fraction * 100.0,
+            stats.islands,
+            stats.coastline_length
+        ));
+    }
+}
+
+fn lake_menu(ui: &mut egui::Ui, egui_ctx: &Context, world_info: &mut WorldInfo) {
+    ui.heading("Lakes");
+    ui.add(egui::Slider::new(&mut world_info.lake_settings.min_size, 1..=1000).logarithmic(true).text("Min Size (cells)"));
+
+    if ui.button("fill depressions").clicked() {
+        if let (Some(heightmap), Some(mask)) = (&world_info.heightmap, &world_info.land_mask) {
+            let (lakes, drained) = lakes::find_lakes(heightmap, mask, &world_info.lake_settings);
+            world_info.heightmap_preview = Some(texture_manager::get_heightmap_preview(egui_ctx, &drained));
+            world_info.heightmap = Some(drained);
+            world_info.heightmap_replaced();
+            clear_relief(world_info);
+            world_info.lakes = lakes;
+            name_lakes(world_info);
+            world_info.mark_dirty();
+        }
+    }
+    if !world_info.lakes.is_empty() {
+        ui.collapsing(format!("{} lakes", world_info.lakes.len()), |ui| {
+            egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                for lake in &world_info.lakes {
+                    let outlet = match lake.outlet {
+                        Some(o) => format!("outlet ({:.2}, {:.2})", o.position[0], o.position[1]),
+                        None => "no outlet".to_string(),
+                    };
+                    let kind = if lake.from_mask { ", from mask" } else { "" };
+                    ui.label(format!("{}: {} cells, level {:.0} m, {}{}", lake.name, lake.cells.len(), lake.level, outlet, kind));
+                }
+            });
+        });
+    }
+}
+
+fn river_menu(ui: &mut egui::Ui, world_info: &mut WorldInfo) {
+    ui.heading("Rivers");
+    let settings = &mut world_info.river_settings;
+    ui.horizontal(|ui| {
+        ui.label("Flow");
+        ui.radio_value(&mut settings.method, FlowMethod::D8, "D8");
+        ui.radio_value(&mut settings.method, FlowMethod::DInfinity, "D-infinity");
+    });
+    ui.add(egui::Slider::new(&mut settings.threshold, 10.0..=5000.0).logarithmic(true).text("Threshold (cells)"));
+
+    if ui.button("generate rivers").clicked() {
+        if let (Some(heightmap), Some(mask)) = (&world_info.heightmap, &world_info.land_mask) {
+            let lake_cells = lakes::lake_mask(&world_info.lakes, mask.width, mask.height);
+            world_info.rivers = rivers::generate_rivers(heightmap, mask, &lake_cells, &world_info.river_settings);
+            name_rivers(world_info);
+            world_info.mark_dirty();
+        }
+    }
+    if !world_info.rivers.is_empty() {
+        ui.collapsing(format!("{} rivers", world_info.rivers.len()), |ui| {
+            egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                for river in &world_info.rivers {
+                    ui.label(format!("{} (order {})", river.name, river.order));
+                }
+            });
+        });
+        if ui.button("clear rivers").clicked() {
+            world_info.rivers.clear();
+            world_info.mark_dirty();
+        }
+    }
+}
+
+fn erosion_menu(ui: &mut egui::Ui, world_info: &mut WorldInfo) {
+    ui.heading("Erosion");
+    let settings = &mut world_info.erosion_settings;
+    ui.add(egui::Slider::new(&mut settings.droplets, 0..=500000).text("Droplets"));
+    ui.add(egui::Slider::new(&mut settings.thermal_iterations, 0..=200).text("Thermal Passes"));
+    ui.add(egui::Slider::new(&mut settings.talus, 1.0..=500.0).text("Talus (m)"));
+    ui.add(egui::Slider::new(&mut settings.inertia, 0.0..=1.0).text("Inertia"));
+    ui.add(egui::Slider::new(&mut settings.erode_speed, 0.0..=1.0).text("Erode Speed"));
+    ui.add(egui::Slider::new(&mut settings.deposit_speed, 0.0..=1.0).text("Deposit Speed"));
+
+    if ui.button("erode").clicked() {
+        if let (Some(heightmap), Some(mask)) = (&world_info.heightmap, &world_info.land_mask) {
+            let job = ErosionJob::new(world_info.erosion_settings, seed::derive(world_info.seed, "erosion"));
+            let simulation = ErosionSimulation::new(job, heightmap.clone(), mask.clone());
+            world_info.scheduler.register(Box::new(simulation), Some(world_info.heightmap_generation));
+            world_info.scheduler.play();
+        }
+    }
+}
+
+/// The map's layers, top of the stack first, and the style of the one selected.
+fn layer_panel(ui: &mut egui::Ui, gui_info: &mut GUIInfo, world_info: &mut WorldInfo) {
+    ui.heading("Layers");
+    let count = world_info.layers.layers.len();
+    let mut raise = None;
+    let mut lower = None;
+    egui::ScrollArea::vertical().id_source("layers").max_height(250.0).show(ui, |ui| {
+        for index in (0..count).rev() {
+            let layer = &mut world_info.layers.layers[index];
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut layer.visible, "");
+                let selected = gui_info.selected_layer == Some(layer.kind);
+                if ui.selectable_label(selected, layer.kind.name()).clicked() {
+                    gui_info.selected_layer = if selected { None } else { Some(layer.kind) };
+                }
+                if ui.small_button("⏶").clicked() {
+                    raise = Some(index);
+                }
+                if ui.small_button("⏷").clicked() {
+                    lower = Some(index);
+                }
+            });
+        }
+    });
+    if let Some(index) = raise {
+        world_info.layers.raise(index);
+    }
+    if let Some(index) = lower {
+        world_info.layers.lower(index);
+    }
+
+    let Some(kind) = gui_info.selected_layer else {
+        return;
+    };
+    let Some(layer) = world_info.layers.layers.iter_mut().find(|l| l.kind == kind) else {
+        return;
+    };
+    ui.label(kind.name());
+    ui.add(egui::Slider::new(&mut layer.opacity, 0.0..=1.0).text("Opacity"));
+    if kind.is_texture() {
+        return;
+    }
+    let mut recolor = layer.style.color.is_some();
+    ui.horizontal(|ui| {
+        if ui.checkbox(&mut recolor, "Colour").changed() {
+            layer.style.color = if recolor { Some([1.0, 1.0, 1.0, 1.0]) } else { None };
+        }
+        if let Some(color) = &mut layer.style.color {
+            ui.color_edit_button_rgba_unmultiplied(color);
+        }
+    });
+    let text = if kind == LayerKind::Labels { "Text Size" } else { "Line Width" };
+    let rescaled = ui.add(egui::Slider::new(&mut layer.style.line_scale, 0.25..=4.0).logarithmic(true).text(text)).changed();
+    if kind != LayerKind::Labels {
+        let style = &mut layer.style;
+        egui::ComboBox::from_label("Joins").selected_text(style.join.name()).show_ui(ui, |ui| {
+            for join in [LineJoin::Miter, LineJoin::Round] {
+                ui.selectable_value(&mut style.join, join, join.name());
+            }
+        });
+        egui::ComboBox::from_label("Caps").selected_text(style.cap.name()).show_ui(ui, |ui| {
+            for cap in [LineCap::Butt, LineCap::Square, LineCap::Round] {
+                ui.selectable_value(&mut style.cap, cap, cap.name());
+            }
+        });
+    }
+    let mut regrid = false;
+    if kind == LayerKind::Graticule {
+        let settings = &mut world_info.graticule_settings;
+        regrid |= ui.add(egui::Slider::new(&mut settings.spacing, 1.0..=45.0).text("Spacing (°)")).changed();
+        regrid |= ui.checkbox(&mut settings.special_parallels, "Equator, tropics and polar circles").changed();
+        ui.checkbox(&mut settings.labels, "Label the edges");
+    }
+    // marker sizes are baked into the vertex buffers, unlike line widths and colours
+    if rescaled || regrid {
+        world_info.mark_dirty();
+    }
+}
+
+fn color_map_menu(ui: &mut egui::Ui, egui_ctx: &Context, world_info: &mut WorldInfo) {
+    ui.heading("Colour Maps");
+    let settings = &mut world_info.color_map_settings;
+    let field = settings.field;
+    egui::ComboBox::from_label("Field").selected_text(settings.field.name()).show_ui(ui, |ui| {
+        for field in [ScalarField::Elevation, ScalarField::Temperature, ScalarField::Precipitation, ScalarField::DistanceToCoast] {
+            ui.selectable_value(&mut settings.field, field, field.name());
+        }
+    });
+    if settings.field != field {
+        settings.palette = settings.field.palette();
+        settings.auto_range = true;
+    }
+    egui::ComboBox::from_label("Palette").selected_text(settings.palette.name()).show_ui(ui, |ui| {
+        for palette in [Palette::Viridis, Palette::Terrain, Palette::Diverging] {
+            ui.selectable_value(&mut settings.palette, palette, palette.name());
+        }
+    });
+    if settings.field.is_seasonal() {
+        let mut annual = settings.month.is_none();
+        if ui.checkbox(&mut annual, "Mean over the year").changed() {
+            settings.month = if annual { None } else { Some(0) };
+        }
+        if let Some(month) = &mut settings.month {
+            let name = climate::MONTHS[*month];
+            ui.add(egui::Slider::new(month, 0..=11).text(name));
+        }
+    }
+    ui.checkbox(&mut settings.auto_range, "Fit the range to the values");
+    if !settings.auto_range {
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut settings.min).speed(1.0).prefix("min "));
+            ui.add(egui::DragValue::new(&mut settings.max).speed(1.0).prefix("max "));
+        });
+    }
+
+    let field = settings.field;
+    let available = field.is_available(world_info);
+    ui.horizontal(|ui| {
+        if ui.add_enabled(available, egui::Button::new("show")).clicked() {
+            show_color_map(egui_ctx, world_info);
+        }
+        if let Some(overlay) = world_info.legend.as_ref().map(|l| l.overlay) {
+            if ui.button("remove").clicked() {
+                world_info.remove_overlay(overlay);
+            }
+        }
+    });
+    if !available {
+        let needs = match field {
+            ScalarField::Elevation => "a heightmap",
+            ScalarField::Temperature | ScalarField::Precipitation => "a climate",
+            ScalarField::DistanceToCoast => "a land mask",
+        };
+        ui.label(format!("needs {} first", needs));
+    }
+}
+
+/// Colours the chosen field into an overlay, replacing the last colour map if there is one.
+fn show_color_map(egui_ctx: &Context, world_info: &mut WorldInfo) {
+    let settings = &world_info.color_map_settings;
+    let Some(grid) = settings.field.grid(world_info, settings.month) else {
+        return;
+    };
+    let (min, max) = colormap::value_range(&grid, settings);
+    let image = colormap::colorize(&grid, settings.palette, min, max);
+    let texture = texture_manager::get_texture_data(egui_ctx, image.into_rgba8());
+
+    let mut title = settings.field.name().to_string();
+    if settings.field.is_seasonal() {
+        title += &format!(", {}", settings.month.map_or("annual mean", |m| climate::MONTHS[m]));
+    }
+    let overlay = match &world_info.legend {
+        Some(legend) => {
+            world_info.texture_list[legend.overlay] = texture;
+            legend.overlay
+        }
+        None => {
+            world_info.texture_list.push(texture);
+            world_info.texture_list.len() - 1
+        }
+    };
+    world_info.legend = Some(Legend {
+        title,
+        unit: settings.field.unit().to_string(),
+        palette: settings.palette,
+        min,
+        max,
+        overlay,
+    });
+    // a starting point for setting the range by hand
+    world_info.color_map_settings.min = min;
+    world_info.color_map_settings.max = max;
+    world_info.mark_rasters_dirty();
+}
+
+/// The colour bar of the colour map overlay, in the corner of the map.
+fn legend(egui_ctx: &Context, world_info: &WorldInfo) {
+    let Some(legend) = &world_info.legend else {
+        return;
+    };
+    if world_info.layers.visible(LayerKind::Overlays).is_none() {
+        return;
+    }
+    egui::Area::new("legend").anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0]).show(egui_ctx, |ui| {
+        egui::Frame::popup(ui.style()).show(ui, |ui| {
+            ui.label(format!("{} ({})", legend.title, legend.unit));
+            let (rect, _) = ui.allocate_exact_size(Vec2::new(200.0, 14.0), egui::Sense::hover());
+            let steps = 64;
+            let step = rect.width() / steps as f32;
+            for i in 0..steps {
+                let [r, g, b] = legend.palette.color((i as f32 + 0.5) / steps as f32);
+                let x = rect.min.x + i as f32 * step;
+                let slice = egui::Rect::from_min_max(egui::pos2(x, rect.min.y), egui::pos2(x + step + 0.5, rect.max.y));
+                ui.painter().rect_filled(slice, 0.0, egui::Color32::from_rgb(r, g, b));
+            }
+            ui.horizontal(|ui| {
+                ui.set_width(rect.width());
+                ui.label(format!("{:.1}", legend.min));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.label(format!("{:.1}", legend.max));
+                });
+            });
+        });
+    });
+}
+
+fn export_menu(ui: &mut egui::Ui, vertex_info: &VertexShaderInfo, screen: [f32; 2], world_info: &mut WorldInfo) {
+    ui.heading("Export Map");
+    let settings = &mut world_info.export_settings;
+    ui.horizontal(|ui| {
+        for region in [ExportRegion::World, ExportRegion::View] {
+            ui.radio_value(&mut settings.region, region, region.name());
+        }
+    });
+    ui.add(egui::Slider::new(&mut settings.scale, 0.25..=8.0).logarithmic(true).text("Scale"));
+
+    ui.label("Layers");
+    for layer in world_info.layers.layers.iter().rev().filter(|l| l.visible) {
+        let mut included = !settings.excluded.contains(&layer.kind);
+        if ui.checkbox(&mut included, layer.kind.name()).changed() {
+            if included {
+                settings.excluded.retain(|k| *k != layer.kind);
+            } else {
+                settings.excluded.push(layer.kind);
+            }
+        }
+    }
+
+    let (region, scale) = (settings.region, settings.scale);
+    let area = match region {
+        ExportRegion::World => ExportArea::world(world_info, scale),
+        ExportRegion::View => Some(ExportArea::view(vertex_info, screen, scale)),
+    };
+    let Some(area) = area else {
+        ui.label("open a base image first");
+        return;
+    };
+    ui.label(format!("{} x {} pixels", area.width, area.height));
+    if area.capped {
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            format!("too large, scaled down to {:.2} to stay under {} megapixels", area.scale, constants::MAX_EXPORT_PIXELS / 1_000_000),
+        );
+    }
+    if ui.button("export png").clicked() {
+        let path = tinyfiledialogs::save_file_dialog_with_filter(
+            "export the map",
+            "map.png",
+            &["*.png"],
+            "PNG",
+        );
+        if let Some(path) = path {
+            // drawn on the simulation thread, its progress shown with the simulations
+            let job = export::ExportJob::new(world_info, &area, &world_info.export_settings.excluded, path);
+            world_info.scheduler.register(Box::new(job), None);
+        }
+    }
+}
+
+fn projection_menu(ui: &mut egui::Ui, world_info: &mut WorldInfo) {
+    ui.heading("Projection");
+    let settings = &mut world_info.projection_settings;
+    let projections = [
+        Projection::Equirectangular,
+        Projection::Mercator,
+        Projection::Robinson,
+        Projection::Mollweide,
+        Projection::Orthographic,
+        Projection::PolarStereographic,
+    ];
+    egui::ComboBox::from_label("Projection").selected_text(settings.projection.name()).show_ui(ui, |ui| {
+        for projection in projections {
+            ui.selectable_value(&mut settings.projection, projection, projection.name());
+        }
+    });
+    if settings.projection != Projection::Equirectangular {
+        ui.add(egui::Slider::new(&mut settings.centre_lon, -180.0..=180.0).text("Centre Longitude (°)"));
+    }
+    if settings.projection == Projection::Orthographic {
+        ui.add(egui::Slider::new(&mut settings.centre_lat, -90.0..=90.0).text("Centre Latitude (°)"));
+    }
+    if settings.projection == Projection::PolarStereographic {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut settings.north, true, "North Pole");
+            ui.radio_value(&mut settings.north, false, "South Pole");
+        });
+    }
+    let changed = world_info.projection != world_info.projection_settings;
+    if ui.add_enabled(changed, egui::Button::new("apply projection")).clicked() {
+        world_info.projection = world_info.projection_settings;
+        world_info.mark_dirty();
+    }
+}
+
+/// Resamples the world's images into the projection when it or they have changed, on the
+/// simulation thread. The map shows no images until they are done.
+fn sync_projection(egui_ctx: &Context, world_info: &mut WorldInfo) {
+    if world_info.projector().is_identity() {
+        world_info.projected = None;
+        world_info.projecting = None;
+        return;
+    }
+    let current = (world_info.projection, world_info.raster_version);
+    if matches!(&world_info.projected, Some(p) if (p.settings, p.version) == current) {
+        return;
+    }
+    if let Some(pending) = &world_info.projecting {
+        if (pending.settings, pending.version) == current {
+            let done = pending.slot.lock().unwrap().take();
+            if let Some(projected) = done {
+                world_info.projected = Some(projected);
+                world_info.projecting = None;
+            }
+            return;
+        }
+    }
+    // replacing what is pending stops the job making it
+    let (job, pending) = projection::ReprojectJob::new(egui_ctx, world_info);
+    world_info.projecting = Some(pending);
+    world_info.scheduler.register(Box::new(job), None);
+}
+
+/// Where the cursor is on the globe.
+fn status_bar(ui: &mut egui::Ui, vertex_info: &VertexShaderInfo, world_info: &WorldInfo) {
+    if world_info.world_texture.is_none() {
+        ui.label("no world open");
+        return;
+    }
+    let Some(point) = world_info.projector().unproject(vertex_info.mouse_pos) else {
+        ui.label("off the map");
+        return;
+    };
+    let (lon, lat) = utils::world_to_lon_lat(point, world_info.top_left, world_info.bottom_right);
+    if !(-180.0..=180.0).contains(&lon) || !(-90.0..=90.0).contains(&lat) {
+        ui.label("off the map");
+        return;
+    }
+    ui.label(format!("{}  {}", utils::format_latitude(lat), utils::format_longitude(lon)));
+}
+
+fn simulation_panel(ui: &mut egui::Ui, world_info: &mut WorldInfo) {
+    ui.heading("Simulation");
+    let scheduler = &world_info.scheduler;
+    ui.horizontal(|ui| {
+        if scheduler.is_playing() {
+            if ui.button("pause").clicked() {
+                scheduler.pause();
+            }
+        } else if ui.button("play").clicked() {
+            scheduler.play();
+        }
+        if ui.button("step").clicked() {
+            scheduler.step();
+        }
+    });
+    let mut speed = scheduler.speed();
+    if ui.add(egui::Slider::new(&mut speed, 1.0..=240.0).logarithmic(true).text("Ticks/s")).changed() {
+        scheduler.set_speed(speed);
+    }
+
+    for status in &world_info.simulations {
+        ui.label(format!("{} (tick {})", status.name, status.ticks));
+        if let Some(progress) = status.progress {
+            ui.add(egui::ProgressBar::new(progress).show_percentage());
+        }
+    }
+    if world_info.simulations.iter().any(|s| s.finished) && ui.button("clear finished").clicked() {
+        scheduler.clear_finished();
+    }
+}
+
+// picks up the latest simulation state, heightmaps being eroded are copied into the world
+// as long as it still has the heightmap they started from
+fn sync_simulations(egui_ctx: &Context, world_info: &mut WorldInfo) {
+    let (version, statuses) = world_info.scheduler.statuses();
+    if version != world_info.simulations_version {
+        for (i, status) in statuses.iter().enumerate() {
+            // a heightmap only comes back once, when its simulation has finished
+            let unchanged = world_info.simulations.get(i).is_some_and(|s| s.name == status.name && s.ticks == status.ticks);
+            if unchanged || status.generation != Some(world_info.heightmap_generation) {
+                continue;
+            }
+            let Some(current) = &world_info.heightmap else {
+                continue;
+            };
+            if let Some(heightmap) = status.frame.heightmap.as_ref().filter(|h| h.width == current.width && h.height == current.height) {
+                heightmap_eroded(egui_ctx, world_info, Grid::clone(heightmap));
+            }
+        }
+        world_info.simulations = statuses;
+        world_info.simulations_version = version;
+    }
+
+    if world_info.scheduler.is_playing() {
+        egui_ctx.request_repaint();
+    } else if world_info.simulations.iter().any(|s| !s.finished) {
+        // single steps finish on the simulation thread after this frame
+        egui_ctx.request_repaint_after(std::time::Duration::from_millis(100));
+    }
+}
+
+/// Takes the heightmap an erosion finished with, dropping everything worked out from the old one.
+fn heightmap_eroded(egui_ctx: &Context, world_info: &mut WorldInfo, heightmap: Grid<f32>) {
+    world_info.heightmap_preview = Some(texture_manager::get_heightmap_preview(egui_ctx, &heightmap));
+    let coast_moved = world_info.land_mask.as_ref().is_some_and(|mask| terrain::sea_level_mask(&heightmap, world_info.sea_level).data != mask.data);
+    world_info.heightmap = Some(heightmap);
+    clear_relief(world_info);
+    world_info.rivers.clear();
+    world_info.lakes.clear();
+    if coast_moved {
+        // also redoes the coast stats and the sea's mesh
+        apply_sea_level(egui_ctx, world_info);
+    }
+    world_info.mark_dirty();
+}
+
+/// Handles a left click on the map (outside of any egui area) for the active tool.
+pub fn map_clicked(mut gui_info: GUIInfo, vertex_info: &VertexShaderInfo, world_info: &mut WorldInfo) -> GUIInfo {
+    // tools work on the base image, wherever the projection has moved it to
+    let Some(point) = world_info.projector().unproject(vertex_info.mouse_pos) else {
+        return gui_info;
+    };
+    match gui_info.tool {
+        MapTool::None => {}
+        MapTool::RidgeLine => {
+            if let Some(start) = gui_info.ridge_start {
+                world_info.ridge_lines.push(Line::new(start, point));
+                world_info.mark_dirty();
+            }
+            gui_info.ridge_start = Some(point);
+        }
+        MapTool::SeaRoute => {
+            let Some(mask) = &world_info.land_mask else {
+                return gui_info;
+            };
+            let (px, py) = utils::world_to_pixel(point, mask.width, mask.height);
+            if !mask.in_bounds(px as i32, py as i32) || mask.get(px as u32, py as u32) {
+                return gui_info;
+            }
+            match gui_info.route_start.take() {
+                None => gui_info.route_start = Some(point),
+                Some(start) => {
+                    let lines: Vec<Line> = world_info.simulations.iter().flat_map(|s| s.frame.lines.iter().copied()).collect();
+                    let currents = navigation::current_field(&lines, mask.width, mask.height);
+                    world_info.sea_route = navigation::find_sea_route(
+                        start,
+                        point,
+                        mask,
+                        Some(&currents),
+                        &world_info.sea_route_settings,
+                        world_info.top_left,
+                        world_info.bottom_right,
+                    );
+                    world_info.mark_dirty();
+                }
+            }
+        }
+        MapTool::Capital => {
+            if let Some(mask) = &world_info.land_mask {
+                let (px, py) = utils::world_to_pixel(point, mask.width, mask.height);
+                if mask.in_bounds(px as i32, py as i32) && mask.get(px as u32, py as u32) {
+                    world_info.capitals.push(point);
+                    world_info.mark_dirty();
+                }
+            }
+        }
+    }
+    gui_info
+}
@@ -1,10 +1,11 @@
-use std::{result, ops::Deref};
+use std::{result, ops::Deref, sync::Arc};
 
 use egui::TextureHandle;
 use egui_glium::EguiGlium;
 use glium::{Display, texture::SrgbTexture2d};
+use image::RgbaImage;
 
-use crate::{texture_manager::TextureData, geometry::{Vertex, Line}, utils};
+use crate::{names, colormap::{ColorMapSettings, Legend}, export::ExportSettings, relief::{Contours, ReliefSettings}, graticule::GraticuleSettings, projection::{PendingRasters, ProjectedRasters, ProjectionSettings, Projector}, layers::{LayerKind, LayerStack}, texture_manager::TextureData, geometry::{Vertex, Line}, utils, grid::Grid, terrain::HeightmapSettings, erosion::ErosionSettings, scheduler::{Scheduler, SimulationStatus}, climate::{Climate, ClimateSettings, ClimateView, IceCover}, kingdoms::{Kingdom, KingdomSettings}, settlements::{Settlement, SettlementSettings}, roads::{RoadNetwork, RoadSettings}, navigation::{SeaRoute, SeaRouteSettings}, terrain::{Area, CoastStats}, names::Culture, rivers::{River, RiverSettings}, lakes::{Lake, LakeSettings}, tectonics::{TectonicSettings, Tectonics}, worldgen::WorldGenSettings};
 #[derive(Copy, Clone)]
 pub struct VertexShaderInfo {
     pub(crate) aspect: f32,
@@ -22,6 +23,7 @@ pub struct InputInfo {
     pub(crate) control: bool,
     pub(crate) drag_start: (f32, f32),
     pub(crate) mouse_pos: (f32, f32),
+    pub(crate) clicked: bool,
 
     pub(crate) zoom_modifier: f32,
 }
@@ -29,6 +31,24 @@ pub struct InputInfo {
 #[derive(Copy, Clone)]
 pub struct GUIInfo {
     pub(crate) new_menu_opened: bool,
+    pub(crate) inspector_opened: bool,
+    pub(crate) tool: MapTool,
+    pub(crate) ridge_start: Option<Vertex>,
+    pub(crate) climate_month: usize,
+    pub(crate) climate_view: ClimateView,
+    pub(crate) route_start: Option<Vertex>,
+    /// Layer whose style the layer panel shows.
+    pub(crate) selected_layer: Option<LayerKind>,
+    pub(crate) export_opened: bool,
+}
+
+/// What a left click on the map does.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MapTool {
+    None,
+    RidgeLine,
+    Capital,
+    SeaRoute,
 }
 
 pub struct WorldInfo {
@@ -39,13 +59,217 @@ pub struct WorldInfo {
     pub(crate) triangles: Vec<Vertex>,
     pub(crate) debug_lines: Vec<Line>,
     pub(crate) ocean_line_num: u32,
+    /// Ticks an ocean current simulation runs for at most.
+    pub(crate) ocean_steps: u32,
     pub(crate) top_left: (f32,f32),
     pub(crate) bottom_right: (f32,f32),
+    pub(crate) land_mask: Option<Grid<bool>>,
+    /// Pyramid of the image the world was created from, before any change of sea level.
+    /// Shared with the world texture until the sea level is changed.
+    pub(crate) base_image: Option<Arc<Vec<RgbaImage>>>,
+    /// Metres above the heightmap's zero that the coast is drawn at.
+    pub(crate) sea_level: f32,
+    pub(crate) coast_stats: Option<CoastStats>,
+    pub(crate) islands: Vec<Area>,
+    pub(crate) seas: Vec<Area>,
+    /// Word lists that names are generated from, and the one in use.
+    pub(crate) cultures: Vec<Culture>,
+    pub(crate) culture: usize,
+    pub(crate) heightmap: Option<Grid<f32>>,
+    pub(crate) heightmap_settings: HeightmapSettings,
+    pub(crate) heightmap_preview: Option<TextureHandle>,
+    /// Bumped whenever the heightmap or land mask is replaced other than by erosion, so
+    /// that erosion started from the old ones doesn't overwrite the new.
+    pub(crate) heightmap_generation: u64,
+    pub(crate) ridge_lines: Vec<Line>,
+    pub(crate) erosion_settings: ErosionSettings,
+    pub(crate) river_settings: RiverSettings,
+    pub(crate) rivers: Vec<River>,
+    pub(crate) lake_settings: LakeSettings,
+    pub(crate) lakes: Vec<Lake>,
+    pub(crate) tectonic_settings: TectonicSettings,
+    pub(crate) tectonics: Option<Tectonics>,
+    pub(crate) worldgen_settings: WorldGenSettings,
+    /// Every random choice made for the world is derived from this.
+    pub(crate) seed: u64,
+    pub(crate) scheduler: Scheduler,
+    /// Latest state of the scheduler's simulations and the version it was read at.
+    pub(crate) simulations: Vec<SimulationStatus>,
+    pub(crate) simulations_version: u64,
+    pub(crate) climate_settings: ClimateSettings,
+    pub(crate) climate: Option<Climate>,
+    /// Preview of the climate and the month and view it shows.
+    pub(crate) climate_preview: Option<(TextureHandle, usize, ClimateView)>,
+    pub(crate) ice: Option<IceCover>,
+    pub(crate) show_ice: bool,
+    pub(crate) capitals: Vec<Vertex>,
+    pub(crate) kingdom_settings: KingdomSettings,
+    pub(crate) kingdoms: Vec<Kingdom>,
+    pub(crate) settlement_settings: SettlementSettings,
+    pub(crate) settlements: Vec<Settlement>,
+    pub(crate) settlement_search: String,
+    pub(crate) road_settings: RoadSettings,
+    pub(crate) roads: RoadNetwork,
+    pub(crate) sea_route_settings: SeaRouteSettings,
+    pub(crate) sea_route: Option<SeaRoute>,
+    /// Bumped whenever something that is drawn on the map changes.
+    pub(crate) render_version: u64,
+    pub(crate) layers: LayerStack,
+    pub(crate) export_settings: ExportSettings,
+    pub(crate) color_map_settings: ColorMapSettings,
+    /// Key to the colour map overlay, while there is one.
+    pub(crate) legend: Option<Legend>,
+    pub(crate) relief_settings: ReliefSettings,
+    pub(crate) hillshade: Option<TextureData>,
+    pub(crate) contours: Contours,
+    pub(crate) graticule_settings: GraticuleSettings,
+    /// The projection chosen in the gui, applied to `projection` on request.
+    pub(crate) projection_settings: ProjectionSettings,
+    /// The projection the map is drawn and exported in.
+    pub(crate) projection: ProjectionSettings,
+    pub(crate) projected: Option<ProjectedRasters>,
+    /// The projected images being made on the simulation thread.
+    pub(crate) projecting: Option<PendingRasters>,
+    /// Bumped whenever one of the world's images changes, for reprojecting them.
+    pub(crate) raster_version: u64,
 }
 
 impl WorldInfo {
     pub fn new(tex_data: Option<TextureData>) -> Self {
-        WorldInfo{created: false, world_texture: tex_data, texture_list: Vec::new(), lines: Vec::new(), triangles: Vec::new(), debug_lines: Vec::new(), ocean_line_num: 1, top_left: (0.0,0.0), bottom_right: (0.0,0.0)}
+        WorldInfo {
+            created: false,
+            world_texture: tex_data,
+            texture_list: Vec::new(),
+            lines: Vec::new(),
+            triangles: Vec::new(),
+            debug_lines: Vec::new(),
+            ocean_line_num: 1,
+            ocean_steps: 1000,
+            top_left: (0.0,0.0),
+            bottom_right: (0.0,0.0),
+            land_mask: None,
+            base_image: None,
+            sea_level: 0.0,
+            coast_stats: None,
+            islands: Vec::new(),
+            seas: Vec::new(),
+            cultures: names::default_cultures(),
+            culture: 0,
+            heightmap: None,
+            heightmap_settings: HeightmapSettings::default(),
+            heightmap_preview: None,
+            heightmap_generation: 0,
+            ridge_lines: Vec::new(),
+            erosion_settings: ErosionSettings::default(),
+            river_settings: RiverSettings::default(),
+            rivers: Vec::new(),
+            lake_settings: LakeSettings::default(),
+            lakes: Vec::new(),
+            tectonic_settings: TectonicSettings::default(),
+            tectonics: None,
+            worldgen_settings: WorldGenSettings::default(),
+            seed: 0,
+            scheduler: Scheduler::new(),
+            simulations: Vec::new(),
+            simulations_version: 0,
+            climate_settings: ClimateSettings::default(),
+            climate: None,
+            climate_preview: None,
+            ice: None,
+            show_ice: true,
+            capitals: Vec::new(),
+            kingdom_settings: KingdomSettings::default(),
+            kingdoms: Vec::new(),
+            settlement_settings: SettlementSettings::default(),
+            settlements: Vec::new(),
+            settlement_search: String::new(),
+            road_settings: RoadSettings::default(),
+            roads: RoadNetwork::default(),
+            sea_route_settings: SeaRouteSettings::default(),
+            sea_route: None,
+            render_version: 0,
+            layers: LayerStack::default(),
+            export_settings: ExportSettings::default(),
+            color_map_settings: ColorMapSettings::default(),
+            legend: None,
+            relief_settings: ReliefSettings::default(),
+            hillshade: None,
+            contours: Contours::default(),
+            graticule_settings: GraticuleSettings::default(),
+            projection_settings: ProjectionSettings::default(),
+            projection: ProjectionSettings::default(),
+            projected: None,
+            projecting: None,
+            raster_version: 0,
+        }
+    }
+
+    /// Tells the renderer to upload the world's geometry again on the next frame.
+    pub fn mark_dirty(&mut self) {
+        self.render_version += 1;
+    }
+
+    /// Tells the renderer that an image changed as well, for when it is projected.
+    pub fn mark_rasters_dirty(&mut self) {
+        self.raster_version += 1;
+        self.mark_dirty();
+    }
+
+    /// Starts a new generation of the heightmap, cancelling the simulations of the last.
+    pub fn heightmap_replaced(&mut self) {
+        self.heightmap_generation += 1;
+        self.scheduler.cancel_stale(self.heightmap_generation);
+    }
+
+    pub fn projector(&self) -> Projector {
+        Projector::new(self.projection, self.top_left, self.bottom_right)
+    }
+
+    /// The projected images, while they are of the images and the projection the map has.
+    fn projected_rasters(&self) -> Option<&ProjectedRasters> {
+        self.projected.as_ref().filter(|p| p.settings == self.projection && p.version == self.raster_version)
+    }
+
+    /// Which images the rasters below give out: when this changes their tiles are stale.
+    pub fn raster_key(&self) -> (u64, Option<(ProjectionSettings, u64)>) {
+        (self.raster_version, self.projected_rasters().map(|p| (p.settings, p.version)))
+    }
+
+    /// The base image as it is drawn. On a projected map there is none until it has been
+    /// projected.
+    pub fn base_raster(&self) -> Option<&TextureData> {
+        if self.projector().is_identity() {
+            return self.world_texture.as_ref();
+        }
+        self.projected_rasters()?.base.as_ref()
+    }
+
+    pub fn hillshade_raster(&self) -> Option<&TextureData> {
+        if self.projector().is_identity() {
+            return self.hillshade.as_ref();
+        }
+        self.projected_rasters()?.hillshade.as_ref()
+    }
+
+    pub fn overlay_rasters(&self) -> &[TextureData] {
+        if self.projector().is_identity() {
+            return &self.texture_list;
+        }
+        self.projected_rasters().map_or(&[], |p| &p.overlays)
+    }
+
+    /// Drops an image from `texture_list`, keeping the legend pointing at its overlay.
+    pub fn remove_overlay(&mut self, index: usize) {
+        self.texture_list.remove(index);
+        self.raster_version += 1;
+        if let Some(legend) = &mut self.legend {
+            if legend.overlay == index {
+                self.legend = None;
+            } else if legend.overlay > index {
+                legend.overlay -= 1;
+            }
+        }
+        self.mark_dirty();
     }
 }
 
@@ -0,0 +1,111 @@
+use rand::Rng;
+
+use crate::{
+    geometry::{self, Line, Vertex},
+    grid::Grid,
+    seed, utils,
+};
+
+/// How hard each kind of terrain is to push a border across.
+#[derive(Copy, Clone, Debug)]
+pub struct KingdomSettings {
+    /// Extra cost per 100 m climbed or descended between neighbouring cells.
+    pub(crate) slope_weight: f32,
+    /// Extra cost per kilometre of elevation, highlands are harder to hold.
+    pub(crate) elevation_weight: f32,
+    /// Cost of stepping onto a river.
+    pub(crate) river_weight: f32,
+    /// Cost of every cell of sea or lake crossed.
+    pub(crate) water_weight: f32,
+}
+
+impl Default for KingdomSettings {
+    fn default() -> Self {
+        KingdomSettings {
+            slope_weight: 2.0,
+            elevation_weight: 1.0,
+            river_weight: 20.0,
+            water_weight: 30.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Kingdom {
+    pub(crate) name: String,
+    pub(crate) capital: Vertex,
+    pub(crate) color: [f32; 4],
+    pub(crate) cells: Vec<usize>,
+    /// Border of the kingdom's territory.
+    pub(crate) lines: Vec<Line>,
+    /// Triangles covering the territory, one quad per run of cells in a row.
+    pub(crate) fill: Vec<Vertex>,
+}
+
+/// Grows a kingdom out of every capital at once, each claiming the land it can reach
+/// most cheaply. Water can be crossed but never held, so borders settle along mountain
+/// ranges, rivers and straits.
+pub fn expand_kingdoms(
+    capitals: &[Vertex],
+    mask: &Grid<bool>,
+    heightmap: Option<&Grid<f32>>,
+    water: &Grid<bool>,
+    rivers: &Grid<bool>,
+    settings: &KingdomSettings,
+    world_seed: u64,
+) -> Vec<Kingdom> {
+    let width = mask.width;
+    let height = mask.height;
+    let heightmap = heightmap.filter(|h| h.width == width && h.height == height);
+
+    let mut sources = Vec::new();
+    for capital in capitals {
+        let (px, py) = utils::world_to_pixel(*capital, width, height);
+        let px = (px as u32).min(width - 1);
+        let py = (py as u32).min(height - 1);
+        sources.push(mask.index(px, py));
+    }
+
+    let is_water = |i: usize| !mask.data[i] || water.data[i];
+    let elevation = |i: usize| heightmap.map_or(0.0, |h| h.data[i].max(0.0));
+    let step_cost = |from: usize, to: usize| {
+        if is_water(to) {
+            return settings.water_weight.max(1.0);
+        }
+        let mut cost = 1.0;
+        cost += settings.slope_weight * (elevation(to) - elevation(from)).abs() / 100.0;
+        cost += settings.elevation_weight * elevation(to) / 1000.0;
+        if rivers.data[to] && !rivers.data[from] {
+            cost += settings.river_weight;
+        }
+        cost
+    };
+    let (owner, _) = geometry::cost_fill(width, height, &sources, step_cost);
+
+    let mut rng = seed::rng(world_seed, "kingdoms");
+    let mut kingdoms: Vec<Kingdom> = capitals
+        .iter()
+        .enumerate()
+        .map(|(i, capital)| Kingdom {
+            name: format!("Kingdom {}", i + 1),
+            capital: *capital,
+            color: [rng.gen_range(0.2..1.0), rng.gen_range(0.2..1.0), rng.gen_range(0.2..1.0), 1.0],
+            cells: Vec::new(),
+            lines: Vec::new(),
+            fill: Vec::new(),
+        })
+        .collect();
+
+    let held = |i: usize| if is_water(i) { None } else { owner.data[i] };
+    for i in 0..owner.len() {
+        if let Some(id) = held(i) {
+            kingdoms[id as usize].cells.push(i);
+        }
+    }
+    for (id, kingdom) in kingdoms.iter_mut().enumerate() {
+        let inside = |i: usize| held(i) == Some(id as u16);
+        kingdom.lines = geometry::outline_cells(width, height, &kingdom.cells, inside);
+        kingdom.fill = geometry::fill_cells(width, height, inside);
+    }
+    kingdoms
+}
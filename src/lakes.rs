@@ -0,0 +1,222 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, VecDeque},
+};
+
+use crate::{
+    constants,
+    geometry::{self, Line, Vertex},
+    grid::Grid,
+    utils,
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct LakeSettings {
+    /// Smallest basin, in cells, that is kept as a lake.
+    pub(crate) min_size: u32,
+}
+
+impl Default for LakeSettings {
+    fn default() -> Self {
+        LakeSettings { min_size: 20 }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Lake {
+    pub(crate) name: String,
+    pub(crate) lines: Vec<Line>,
+    pub(crate) cells: Vec<usize>,
+    /// Where the lake spills over, `None` for lakes that have nowhere lower to go.
+    pub(crate) outlet: Option<Vertex>,
+    pub(crate) level: f32,
+    /// Lakes that are holes in an island of the land mask rather than filled basins.
+    pub(crate) from_mask: bool,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+struct Cell {
+    height: f32,
+    index: usize,
+}
+
+impl Eq for Cell {}
+
+impl Ord for Cell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.height.total_cmp(&other.height).then(self.index.cmp(&other.index))
+    }
+}
+
+impl PartialOrd for Cell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Priority-flood: raises every land cell until it has a downhill path to water or the map edge.
+/// With an `epsilon` above zero filled areas get a slight slope so that every cell drains,
+/// with zero they are left flat at the level of their spill point.
+pub fn priority_flood(heightmap: &Grid<f32>, mask: &Grid<bool>, epsilon: f32) -> Grid<f32> {
+    let mut filled = heightmap.clone();
+    let mut closed = vec![false; heightmap.len()];
+    let mut open = BinaryHeap::new();
+
+    for y in 0..heightmap.height {
+        for x in 0..heightmap.width {
+            let i = heightmap.index(x, y);
+            let edge = x == 0 || y == 0 || x == heightmap.width - 1 || y == heightmap.height - 1;
+            if edge || !mask.data[i] {
+                closed[i] = true;
+                open.push(Reverse(Cell { height: filled.data[i], index: i }));
+            }
+        }
+    }
+
+    while let Some(Reverse(cell)) = open.pop() {
+        let (x, y) = heightmap.coords(cell.index);
+        for (dx, dy) in constants::NEIGHBOURS_8 {
+            let nx = x as i32 + dx;
+            let ny = y as i32 + dy;
+            if !heightmap.in_bounds(nx, ny) {
+                continue;
+            }
+            let n = heightmap.index(nx as u32, ny as u32);
+            if closed[n] {
+                continue;
+            }
+            closed[n] = true;
+            filled.data[n] = filled.data[n].max(cell.height + epsilon);
+            open.push(Reverse(Cell { height: filled.data[n], index: n }));
+        }
+    }
+
+    filled
+}
+
+/// Finds lakes: basins of the heightmap that filling turned into flats, and water enclosed by
+/// land in the land mask. Returns them together with the drained (epsilon filled) heightmap.
+pub fn find_lakes(heightmap: &Grid<f32>, mask: &Grid<bool>, settings: &LakeSettings) -> (Vec<Lake>, Grid<f32>) {
+    let flat = priority_flood(heightmap, mask, 0.0);
+    let drained = priority_flood(heightmap, mask, constants::FILL_EPSILON);
+
+    let basin = |i: usize| mask.data[i] && flat.data[i] > heightmap.data[i] + constants::LAKE_DEPTH_TOLERANCE;
+    let mut lakes = Vec::new();
+    for cells in components(heightmap, &basin) {
+        if (cells.len() as u32) < settings.min_size {
+            continue;
+        }
+        let level = flat.data[cells[0]];
+        lakes.push(new_lake(heightmap, mask, cells, level, false));
+    }
+
+    // water that doesn't reach the edge of the map is a hole in an island
+    let water = |i: usize| !mask.data[i];
+    for cells in components(heightmap, &water) {
+        let touches_edge = cells.iter().any(|i| {
+            let (x, y) = heightmap.coords(*i);
+            x == 0 || y == 0 || x == heightmap.width - 1 || y == heightmap.height - 1
+        });
+        if touches_edge || (cells.len() as u32) < settings.min_size {
+            continue;
+        }
+        lakes.push(new_lake(heightmap, mask, cells, 0.0, true));
+    }
+
+    for (i, lake) in lakes.iter_mut().enumerate() {
+        lake.name = format!("Lake {}", i + 1);
+    }
+    (lakes, drained)
+}
+
+fn new_lake(heightmap: &Grid<f32>, mask: &Grid<bool>, cells: Vec<usize>, level: f32, from_mask: bool) -> Lake {
+    let mut inside = vec![false; heightmap.len()];
+    for i in &cells {
+        inside[*i] = true;
+    }
+
+    // the spill point is the lowest land cell on the shore
+    let mut outlet: Option<usize> = None;
+    for i in &cells {
+        let (x, y) = heightmap.coords(*i);
+        for (dx, dy) in constants::NEIGHBOURS_8 {
+            let nx = x as i32 + dx;
+            let ny = y as i32 + dy;
+            if !heightmap.in_bounds(nx, ny) {
+                continue;
+            }
+            let n = heightmap.index(nx as u32, ny as u32);
+            if inside[n] || !mask.data[n] {
+                continue;
+            }
+            if outlet.is_none_or(|o| heightmap.data[n] < heightmap.data[o]) {
+                outlet = Some(n);
+            }
+        }
+    }
+
+    // a hole in the land mask has no filled level, it sits at the height of its spill point
+    let level = match outlet {
+        Some(o) if from_mask => heightmap.data[o],
+        _ => level,
+    };
+
+    let lines = geometry::outline_cells(heightmap.width, heightmap.height, &cells, |i| inside[i]);
+    let outlet = outlet.map(|o| {
+        let (x, y) = heightmap.coords(o);
+        utils::pixel_to_world(x as f32 + 0.5, y as f32 + 0.5, heightmap.width, heightmap.height)
+    });
+
+    Lake {
+        name: String::new(),
+        lines,
+        cells,
+        outlet,
+        level,
+        from_mask,
+    }
+}
+
+/// 8-connected groups of cells matching `inside`.
+pub fn components<T: Copy>(grid: &Grid<T>, inside: &dyn Fn(usize) -> bool) -> Vec<Vec<usize>> {
+    let mut seen = vec![false; grid.len()];
+    let mut groups = Vec::new();
+    for start in 0..grid.len() {
+        if seen[start] || !inside(start) {
+            continue;
+        }
+        seen[start] = true;
+        let mut group = Vec::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        while let Some(i) = queue.pop_front() {
+            group.push(i);
+            let (x, y) = grid.coords(i);
+            for (dx, dy) in constants::NEIGHBOURS_8 {
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+                if !grid.in_bounds(nx, ny) {
+                    continue;
+                }
+                let n = grid.index(nx as u32, ny as u32);
+                if !seen[n] && inside(n) {
+                    seen[n] = true;
+                    queue.push_back(n);
+                }
+            }
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+/// Raster of the cells covered by any of the lakes.
+pub fn lake_mask(lakes: &[Lake], width: u32, height: u32) -> Grid<bool> {
+    let mut mask = Grid::new(width, height, false);
+    for lake in lakes {
+        for i in &lake.cells {
+            mask.data[*i] = true;
+        }
+    }
+    mask
+}
@@ -0,0 +1,178 @@
+use crate::lines::{LineCap, LineJoin};
+
+/// Everything that can be drawn on the map, each kind being one layer of the stack.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LayerKind {
+    BaseTexture,
+    /// Light and shadow from the heightmap, from `WorldInfo.hillshade`.
+    Hillshade,
+    /// Extra images laid over the base texture, from `WorldInfo.texture_list`.
+    Overlays,
+    LandFill,
+    Coastlines,
+    Contours,
+    /// The red fill of the land under the mouse's row.
+    Scanline,
+    Tectonics,
+    Ice,
+    Kingdoms,
+    Lakes,
+    Rivers,
+    Currents,
+    DebugLines,
+    Roads,
+    Settlements,
+    SeaRoute,
+    Capitals,
+    Ridges,
+    /// Meridians and parallels.
+    Graticule,
+    Labels,
+}
+
+impl LayerKind {
+    pub fn name(&self) -> &str {
+        match self {
+            LayerKind::BaseTexture => "Base Texture",
+            LayerKind::Hillshade => "Hillshade",
+            LayerKind::Overlays => "Overlays",
+            LayerKind::LandFill => "Land Fill",
+            LayerKind::Coastlines => "Coastlines",
+            LayerKind::Contours => "Contours",
+            LayerKind::Scanline => "Scanline",
+            LayerKind::Tectonics => "Plate Boundaries",
+            LayerKind::Ice => "Ice",
+            LayerKind::Kingdoms => "Kingdoms",
+            LayerKind::Lakes => "Lakes",
+            LayerKind::Rivers => "Rivers",
+            LayerKind::Currents => "Currents",
+            LayerKind::DebugLines => "Debug Lines",
+            LayerKind::Roads => "Roads",
+            LayerKind::Settlements => "Settlements",
+            LayerKind::SeaRoute => "Sea Route",
+            LayerKind::Capitals => "Capitals",
+            LayerKind::Ridges => "Ridge Lines",
+            LayerKind::Graticule => "Graticule",
+            LayerKind::Labels => "Labels",
+        }
+    }
+
+    /// Whether the layer is an image rather than geometry, so has no colour or line width.
+    pub fn is_texture(&self) -> bool {
+        matches!(self, LayerKind::BaseTexture | LayerKind::Hillshade | LayerKind::Overlays)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LayerStyle {
+    /// Replaces the layer's own colours when set, its alpha multiplying theirs.
+    pub(crate) color: Option<[f32; 4]>,
+    /// Multiplies the width of the layer's lines and markers, or the size of its text.
+    pub(crate) line_scale: f32,
+    pub(crate) join: LineJoin,
+    pub(crate) cap: LineCap,
+}
+
+impl Default for LayerStyle {
+    fn default() -> Self {
+        LayerStyle {
+            color: None,
+            line_scale: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Round,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Layer {
+    pub(crate) kind: LayerKind,
+    pub(crate) visible: bool,
+    /// `0.0..=1.0`, multiplies the alpha of everything in the layer.
+    pub(crate) opacity: f32,
+    pub(crate) style: LayerStyle,
+}
+
+impl Layer {
+    pub fn new(kind: LayerKind) -> Self {
+        Layer {
+            kind,
+            visible: true,
+            opacity: 1.0,
+            style: LayerStyle::default(),
+        }
+    }
+
+    /// A colour of the layer's own, after the style and opacity are applied.
+    pub fn tint(&self, color: [f32; 4]) -> [f32; 4] {
+        let [r, g, b, a] = color;
+        match self.style.color {
+            Some([sr, sg, sb, sa]) => [sr, sg, sb, a * sa * self.opacity],
+            None => [r, g, b, a * self.opacity],
+        }
+    }
+}
+
+/// The layers of the map, drawn from the first to the last.
+#[derive(Clone, Debug)]
+pub struct LayerStack {
+    pub(crate) layers: Vec<Layer>,
+}
+
+impl Default for LayerStack {
+    fn default() -> Self {
+        let order = [
+            LayerKind::BaseTexture,
+            LayerKind::Hillshade,
+            LayerKind::Overlays,
+            LayerKind::LandFill,
+            LayerKind::Contours,
+            LayerKind::Coastlines,
+            LayerKind::Scanline,
+            LayerKind::Tectonics,
+            LayerKind::Ice,
+            LayerKind::Kingdoms,
+            LayerKind::Lakes,
+            LayerKind::Rivers,
+            LayerKind::Currents,
+            LayerKind::DebugLines,
+            LayerKind::Roads,
+            LayerKind::Settlements,
+            LayerKind::SeaRoute,
+            LayerKind::Capitals,
+            LayerKind::Ridges,
+            LayerKind::Graticule,
+            LayerKind::Labels,
+        ];
+        let mut layers: Vec<Layer> = order.into_iter().map(Layer::new).collect();
+        // the land is already on the base texture, only worth turning on without it
+        for layer in layers.iter_mut().filter(|l| l.kind == LayerKind::LandFill) {
+            layer.visible = false;
+        }
+        LayerStack { layers }
+    }
+}
+
+impl LayerStack {
+    pub fn get(&self, kind: LayerKind) -> Option<&Layer> {
+        self.layers.iter().find(|l| l.kind == kind)
+    }
+
+    /// The layer if it is to be drawn at all.
+    pub fn visible(&self, kind: LayerKind) -> Option<&Layer> {
+        self.get(kind).filter(|l| l.visible && l.opacity > 0.0)
+    }
+
+    /// Swaps a layer with the one drawn after it, bringing it up the stack.
+    pub fn raise(&mut self, index: usize) {
+        if index + 1 < self.layers.len() {
+            self.layers.swap(index, index + 1);
+        }
+    }
+
+    pub fn lower(&mut self, index: usize) {
+        if index > 0 && index < self.layers.len() {
+            self.layers.swap(index, index - 1);
+        }
+    }
+}
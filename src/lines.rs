@@ -0,0 +1,221 @@
+use glium::{Display, Program};
+
+use crate::geometry::Line;
+
+/// One corner of the quad drawn around a line segment. Every corner carries the whole
+/// segment and its neighbours, so the shaders can widen it in screen space and shape
+/// the joins and caps per pixel.
+#[derive(Copy, Clone, Debug)]
+pub struct LineVertex {
+    start: [f32; 2],
+    end: [f32; 2],
+    /// Start of the previous segment of the polyline, `start` itself if there is none.
+    previous: [f32; 2],
+    /// End of the next segment of the polyline, `end` itself if there is none.
+    next: [f32; 2],
+    /// `x` is 0 at the start and 1 at the end, `y` the side of the line, -1 or 1.
+    corner: [f32; 2],
+    /// Multiplies the width the line is drawn at.
+    width: f32,
+}
+implement_vertex!(LineVertex, start, end, previous, next, corner, width);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LineJoin {
+    Miter,
+    Round,
+}
+
+impl LineJoin {
+    pub fn name(&self) -> &str {
+        match self {
+            LineJoin::Miter => "Miter",
+            LineJoin::Round => "Round",
+        }
+    }
+
+    /// The value the fragment shader switches on.
+    pub fn uniform(&self) -> i32 {
+        match self {
+            LineJoin::Miter => 0,
+            LineJoin::Round => 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LineCap {
+    Butt,
+    Square,
+    Round,
+}
+
+impl LineCap {
+    pub fn name(&self) -> &str {
+        match self {
+            LineCap::Butt => "Butt",
+            LineCap::Square => "Square",
+            LineCap::Round => "Round",
+        }
+    }
+
+    /// The value the fragment shader switches on.
+    pub fn uniform(&self) -> i32 {
+        match self {
+            LineCap::Butt => 0,
+            LineCap::Square => 1,
+            LineCap::Round => 2,
+        }
+    }
+}
+
+/// Quads for every segment, those that follow on from each other being joined.
+pub fn line_vertices(lines: &[Line]) -> Vec<LineVertex> {
+    segment_vertices(lines, |_| 1.0)
+}
+
+/// Like `line_vertices`, with the width at every point of the polyline, so one more
+/// width than there are lines. Each segment is drawn at the mean of its ends.
+pub fn tapered_line_vertices(lines: &[Line], widths: &[f32]) -> Vec<LineVertex> {
+    segment_vertices(lines, |i| {
+        let end = widths.get(i + 1).or(widths.last()).copied().unwrap_or(1.0);
+        (widths.get(i).copied().unwrap_or(end) + end) / 2.0
+    })
+}
+
+fn segment_vertices(lines: &[Line], width: impl Fn(usize) -> f32) -> Vec<LineVertex> {
+    let mut vertices = Vec::with_capacity(lines.len() * 6);
+    for (i, line) in lines.iter().enumerate() {
+        let start = line.start.position;
+        let end = line.end.position;
+        let previous = match i.checked_sub(1).map(|p| lines[p]) {
+            Some(p) if p.end.position == start => p.start.position,
+            _ => start,
+        };
+        let next = match lines.get(i + 1) {
+            Some(n) if n.start.position == end => n.end.position,
+            _ => end,
+        };
+        let width = width(i);
+        let corner = |x: f32, y: f32| LineVertex { start, end, previous, next, corner: [x, y], width };
+        vertices.extend([corner(0.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(0.0, -1.0), corner(1.0, 1.0), corner(0.0, 1.0)]);
+    }
+    vertices
+}
+
+const VERTEX_SHADER: &str = r#"
+    #version 140
+    in vec2 start;
+    in vec2 end;
+    in vec2 previous;
+    in vec2 next;
+    in vec2 corner;
+    in float width;
+    out vec2 v_pixel;
+    out vec2 v_start;
+    out vec2 v_end;
+    out vec2 v_previous;
+    out vec2 v_next;
+    out float v_half_width;
+
+    uniform float aspect;
+    uniform float zoom;
+    uniform vec2 offset;
+    uniform vec2 viewport;
+    uniform float lineWidth;
+    uniform float miterLimit;
+
+    vec2 to_pixel(vec2 p) {
+        vec2 clip = vec2((p.x+offset.x)*zoom, (p.y*aspect+offset.y)*zoom);
+        return (clip + 1.0) * 0.5 * viewport;
+    }
+
+    void main() {
+        vec2 a = to_pixel(start);
+        vec2 b = to_pixel(end);
+        vec2 d = b - a;
+        float len = length(d);
+        d = len > 0.0 ? d / len : vec2(1.0, 0.0);
+        vec2 n = vec2(-d.y, d.x);
+        float half_width = 0.5 * lineWidth * width;
+
+        // room past either end for a miter or cap, and a pixel for the feathered edge
+        float reach = half_width * miterLimit + 1.0;
+        vec2 base = corner.x < 0.5 ? a - d * reach : b + d * reach;
+        vec2 p = base + n * corner.y * (half_width + 1.0);
+
+        v_pixel = p;
+        v_start = a;
+        v_end = b;
+        v_previous = to_pixel(previous);
+        v_next = to_pixel(next);
+        v_half_width = half_width;
+        gl_Position = vec4(p / viewport * 2.0 - 1.0, 0.0, 1.0);
+    }
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+    #version 140
+    in vec2 v_pixel;
+    in vec2 v_start;
+    in vec2 v_end;
+    in vec2 v_previous;
+    in vec2 v_next;
+    in float v_half_width;
+    out vec4 color;
+
+    uniform vec4 lineColor;
+    uniform int join;
+    uniform int cap;
+    uniform float miterLimit;
+
+    // distance from the line that decides the coverage of a pixel past one end of a
+    // segment, `outward` pointing away from the segment
+    float end_distance(vec2 p, vec2 e, vec2 outward, float side, vec2 neighbour) {
+        float past = dot(p - e, outward);
+        if (past <= 0.0) {
+            return side;
+        }
+        if (distance(neighbour, e) > 0.0) {
+            vec2 miter = outward + normalize(neighbour - e);
+            float m = length(miter);
+            if (join == 0 && m > 0.0 && dot(miter / m, outward) >= 1.0 / miterLimit) {
+                // the neighbouring segment draws everything beyond the miter line
+                return dot(p - e, miter) <= 0.0 ? side : 1e9;
+            }
+            return length(p - e);
+        }
+        if (cap == 0) {
+            return max(side, past + v_half_width);
+        }
+        if (cap == 1) {
+            return max(side, past);
+        }
+        return length(p - e);
+    }
+
+    void main() {
+        vec2 d = v_end - v_start;
+        float len = length(d);
+        d = len > 0.0 ? d / len : vec2(1.0, 0.0);
+        vec2 n = vec2(-d.y, d.x);
+        float side = abs(dot(v_pixel - v_start, n));
+
+        float dist = side;
+        if (dot(v_pixel - v_start, d) < 0.0) {
+            dist = end_distance(v_pixel, v_start, -d, side, v_previous);
+        } else if (dot(v_pixel - v_end, d) > 0.0) {
+            dist = end_distance(v_pixel, v_end, d, side, v_next);
+        }
+
+        float coverage = clamp(v_half_width + 0.5 - dist, 0.0, 1.0);
+        if (coverage <= 0.0) {
+            discard;
+        }
+        color = vec4(lineColor.rgb, lineColor.a * coverage);
+    }
+"#;
+
+pub fn program(display: &Display) -> Program {
+    glium::Program::from_source(display, VERTEX_SHADER, FRAGMENT_SHADER, None).unwrap()
+}
@@ -17,6 +17,29 @@ pub mod data_displayer;
 pub mod utils;
 pub mod constants;
 pub mod simulate;
+pub mod grid;
+pub mod noise;
+pub mod terrain;
+pub mod erosion;
+pub mod rivers;
+pub mod lakes;
+pub mod tectonics;
+pub mod worldgen;
+pub mod seed;
+pub mod scheduler;
+pub mod climate;
+pub mod kingdoms;
+pub mod settlements;
+pub mod roads;
+pub mod navigation;
+pub mod names;
+pub mod layers;
+pub mod lines;
+pub mod export;
+pub mod colormap;
+pub mod relief;
+pub mod graticule;
+pub mod projection;
 
 fn main() {
     let mut vertex_info = info::VertexShaderInfo {
@@ -34,12 +57,21 @@ fn main() {
         control: false,
         drag_start: (0.0, 0.0),
         mouse_pos: (0.0, 0.0),
+        clicked: false,
 
         zoom_modifier: 0.05,
     };
 
     let mut gui_info = info::GUIInfo {
         new_menu_opened: false,
+        inspector_opened: false,
+        tool: info::MapTool::None,
+        ridge_start: None,
+        climate_month: 0,
+        climate_view: climate::ClimateView::Temperature,
+        route_start: None,
+        selected_layer: None,
+        export_opened: false,
     };
     
 
@@ -73,13 +105,16 @@ fn main() {
 
     uniform sampler2D tex;
     uniform bool useTexture;
+    uniform vec4 lineColor;
+    uniform float opacity;
 
     void main() {
         //color = vec4(1.0, 0.0, 0.0, 1.0);
         if (useTexture == true) {
             color = texture(tex, v_tex_coords);
+            color.a *= opacity;
         } else {
-            color = vec4(1.0, 0.0, 0.0, 1.0);
+            color = lineColor;
         }
     }
     "#;
@@ -87,10 +122,12 @@ fn main() {
     let program =
         glium::Program::from_source(&display, vertex_shader_src, fragment_shader_src, None)
             .unwrap();
+    let line_program = lines::program(&display);
 
     //let image = texture_manager::get_texture(&display, &egui_glium.egui_ctx);
 
     let mut world_info = WorldInfo::new(None);
+    let mut render_cache = data_displayer::RenderCache::new();
 
     let mut scroll = false;
     event_loop.run(move |event, _, control_flow| {
@@ -98,9 +135,11 @@ fn main() {
             let mut quit = false;
 
             let repaint_after = egui_glium.run(&display, |egui_ctx| {
-                let run_results = gui::run(egui_ctx, &display, &input_info, gui_info, &mut world_info);
+                let run_results = gui::run(egui_ctx, &input_info, &vertex_info, gui_info, &mut world_info);
                 quit = run_results.0;
                 gui_info = run_results.1;
+                data_displayer::draw_labels(egui_ctx, &vertex_info, &world_info);
+                data_displayer::draw_graticule_labels(egui_ctx, &vertex_info, &world_info);
                 //egui_ctx.load_texture(name, image, filter);
             });
 
@@ -128,6 +167,13 @@ fn main() {
                     scroll = false
                 }
 
+                if input_info.clicked {
+                    input_info.clicked = false;
+                    if !egui_glium.egui_ctx.wants_pointer_input() {
+                        gui_info = gui::map_clicked(gui_info, &vertex_info, &mut world_info);
+                    }
+                }
+
                 use glium::Surface as _;
                 let mut target = display.draw();
 
@@ -140,7 +186,7 @@ fn main() {
                 // draw things behind egui here
                 //let mut target = draw_things(&display, target, &program, &image, &vertex_info);
 
-                let draw_pass = data_displayer::draw_things(&display, target, &program, &vertex_info, &world_info);
+                let draw_pass = data_displayer::draw_things(&display, target, &program, &line_program, &vertex_info, &world_info, &mut render_cache);
                 let mut target = draw_pass;
 
                 egui_glium.paint(&display, &mut target);
@@ -187,6 +233,10 @@ fn main() {
                                     input_info.drag_start = input_info.mouse_pos;
                                     vertex_info.init_camera[0] = vertex_info.camera[0];
                                     vertex_info.init_camera[1] = vertex_info.camera[1];
+                                    if !input_info.control {
+                                        input_info.clicked = true;
+                                        display.gl_window().window().request_redraw();
+                                    }
                                 }
 
                                 // vertex_info.init_offset[0] = vertex_info.offset[0];
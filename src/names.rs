@@ -0,0 +1,218 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{constants, geometry::Vertex, seed};
+
+const NORDIC: &[&str] = &[
+    "askeby", "bergen", "bjornholm", "dalvik", "eidsvoll", "falun", "grimstad", "hafnar", "hallstein",
+    "hammerfest", "holmsund", "jorvik", "kalmar", "kirkenes", "lindholm", "mora", "narvik", "odense",
+    "ringsted", "roskilde", "sigtuna", "skagen", "solvang", "stavanger", "sundsvall", "torshavn",
+    "trondheim", "uppsala", "vesteras", "visby", "ystad",
+];
+
+const LATIN: &[&str] = &[
+    "aquileia", "arretium", "augusta", "brundisium", "capua", "clusium", "cremona", "florentia",
+    "genua", "hispalis", "italica", "londinium", "lugdunum", "mediolanum", "narbo", "nemausus",
+    "ostia", "patavium", "placentia", "ravenna", "regium", "salona", "saguntum", "tarraco",
+    "tolosa", "valentia", "verona", "vindobona", "volsinii",
+];
+
+const STEPPE: &[&str] = &[
+    "aktobe", "almaty", "aral", "atyrau", "balkhash", "bukhara", "karakum", "kashgar", "khiva",
+    "khorgos", "kokand", "kyzyl", "merv", "namangan", "navoi", "nukus", "osh", "samarkand", "shymkent",
+    "tashkent", "taraz", "termez", "turkestan", "urgench", "zhambyl",
+];
+
+/// A people's word list, which its place names are made to sound like.
+#[derive(Clone, Debug)]
+pub struct Culture {
+    pub(crate) name: String,
+    pub(crate) words: Vec<String>,
+}
+
+impl Culture {
+    pub fn new(name: &str, words: &[&str]) -> Self {
+        Culture {
+            name: name.to_string(),
+            words: words.iter().map(|w| w.to_string()).collect(),
+        }
+    }
+
+    /// Reads a word list with one word per line, blank lines and `#` comments skipped.
+    pub fn from_word_list(name: &str, text: &str) -> Self {
+        let words = text
+            .lines()
+            .map(|l| l.trim().to_lowercase())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .collect();
+        Culture { name: name.to_string(), words }
+    }
+}
+
+pub fn default_cultures() -> Vec<Culture> {
+    vec![
+        Culture::new("Nordic", NORDIC),
+        Culture::new("Latin", LATIN),
+        Culture::new("Steppe", STEPPE),
+    ]
+}
+
+/// Character level Markov chain: the next letter is picked by how often it followed
+/// the previous `order` letters in the training words.
+pub struct NameGenerator {
+    order: usize,
+    /// Letters seen after each context, with their counts.
+    chains: HashMap<Vec<char>, Vec<(char, u32)>>,
+    training: HashSet<String>,
+}
+
+// marks the start and end of a word in the chains
+const START: char = '^';
+const END: char = '$';
+
+impl NameGenerator {
+    pub fn new(words: &[String], order: usize) -> Self {
+        let order = order.max(1);
+        let mut chains: HashMap<Vec<char>, Vec<(char, u32)>> = HashMap::new();
+        for word in words {
+            let mut letters = vec![START; order];
+            letters.extend(word.chars());
+            letters.push(END);
+            for window in letters.windows(order + 1) {
+                let followers = chains.entry(window[..order].to_vec()).or_default();
+                let next = window[order];
+                match followers.iter_mut().find(|(c, _)| *c == next) {
+                    Some((_, count)) => *count += 1,
+                    None => followers.push((next, 1)),
+                }
+            }
+        }
+        NameGenerator {
+            order,
+            chains,
+            training: words.iter().cloned().collect(),
+        }
+    }
+
+    /// A new name that isn't one of the training words, `None` if the chain keeps
+    /// producing words that are too short, too long or already known.
+    pub fn generate(&self, rng: &mut ChaCha8Rng) -> Option<String> {
+        for _ in 0..constants::NAME_ATTEMPTS {
+            let mut context = vec![START; self.order];
+            let mut word = String::new();
+            while let Some(followers) = self.chains.get(&context) {
+                let total: u32 = followers.iter().map(|(_, n)| n).sum();
+                let mut pick = rng.gen_range(0..total);
+                let mut next = END;
+                for (c, n) in followers {
+                    if pick < *n {
+                        next = *c;
+                        break;
+                    }
+                    pick -= n;
+                }
+                if next == END || word.chars().count() > constants::MAX_NAME_LENGTH {
+                    break;
+                }
+                word.push(next);
+                context.remove(0);
+                context.push(next);
+            }
+
+            let length = word.chars().count();
+            if (constants::MIN_NAME_LENGTH..=constants::MAX_NAME_LENGTH).contains(&length) && !self.training.contains(&word) {
+                return Some(capitalise(&word));
+            }
+        }
+        None
+    }
+}
+
+fn capitalise(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// `count` different names in the style of a culture. The same world seed and stream always
+/// give the same names, so that naming islands doesn't change what the cities are called.
+pub fn generate_names(culture: &Culture, count: usize, world_seed: u64, stream: &str) -> Vec<String> {
+    let generator = NameGenerator::new(&culture.words, constants::NAME_ORDER);
+    let mut rng = seed::rng(world_seed, stream);
+    let mut used = HashSet::new();
+    let mut names = Vec::with_capacity(count);
+    while names.len() < count {
+        let fresh = (0..constants::NAME_ATTEMPTS)
+            .filter_map(|_| generator.generate(&mut rng))
+            .find(|name| !used.contains(name));
+        // small word lists run dry, number what's left rather than loop forever
+        let name = fresh.unwrap_or_else(|| format!("{} {}", culture.name, names.len() + 1));
+        used.insert(name.clone());
+        names.push(name);
+    }
+    names
+}
+
+/// A name as it is saved with the world: the kind of thing that has it and where that is.
+#[derive(Clone, Debug)]
+pub struct SavedName {
+    pub(crate) kind: String,
+    pub(crate) position: Vertex,
+    pub(crate) name: String,
+}
+
+/// One name a line, the kind, position and name separated by tabs.
+pub fn names_to_text(names: &[SavedName]) -> String {
+    let mut text = String::new();
+    for saved in names {
+        let [x, y] = saved.position.position;
+        let name = saved.name.replace(['\t', '\n'], " ");
+        text += &format!("{}\t{}\t{}\t{}\n", saved.kind, x, y, name);
+    }
+    text
+}
+
+/// Reads names written by `names_to_text`, skipping lines that aren't.
+pub fn names_from_text(text: &str) -> Vec<SavedName> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let kind = fields.next()?.to_string();
+            let x = fields.next()?.parse().ok()?;
+            let y = fields.next()?.parse().ok()?;
+            let name = fields.next()?.to_string();
+            Some(SavedName { kind, position: Vertex { position: [x, y], tex_coords: [x, y] }, name })
+        })
+        .collect()
+}
+
+/// The saved name for each thing of a kind at `positions`: that of the nearest saved thing
+/// of the kind within `NAME_MATCH_DISTANCE`, each saved name going to one thing only.
+pub fn match_saved_names(saved: &[SavedName], kind: &str, positions: &[Vertex]) -> Vec<Option<String>> {
+    let mut pairs = Vec::new();
+    for (j, saved) in saved.iter().enumerate().filter(|(_, s)| s.kind == kind) {
+        for (i, position) in positions.iter().enumerate() {
+            let dx = position.position[0] - saved.position.position[0];
+            let dy = position.position[1] - saved.position.position[1];
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance <= constants::NAME_MATCH_DISTANCE {
+                pairs.push((distance, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut names = vec![None; positions.len()];
+    let mut used = vec![false; saved.len()];
+    for (_, i, j) in pairs {
+        if names[i].is_none() && !used[j] {
+            names[i] = Some(saved[j].name.clone());
+            used[j] = true;
+        }
+    }
+    names
+}
@@ -0,0 +1,201 @@
+use crate::{
+    constants,
+    geometry::{self, Line, Vertex},
+    grid::Grid,
+    utils,
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct SeaRouteSettings {
+    /// Speed of the ship through the water, in knots.
+    pub(crate) speed: f32,
+    pub(crate) use_currents: bool,
+    /// How much a current along the route helps, or one against it hurts, `0.0..1.0`.
+    pub(crate) current_weight: f32,
+}
+
+impl Default for SeaRouteSettings {
+    fn default() -> Self {
+        SeaRouteSettings {
+            speed: 5.0,
+            use_currents: false,
+            current_weight: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SeaRoute {
+    pub(crate) lines: Vec<Line>,
+    /// Great circle length of the route in kilometres.
+    pub(crate) length: f32,
+    /// Time at sea in days.
+    pub(crate) days: f32,
+}
+
+/// Direction of the ocean currents at every sea cell, averaged from the current lines
+/// that pass through it, zero where there are none.
+pub fn current_field(lines: &[Line], width: u32, height: u32) -> Grid<(f32, f32)> {
+    let mut field = Grid::new(width, height, (0.0, 0.0));
+    for line in lines {
+        let (rise, run) = line.get_rise_and_run();
+        let length = (rise * rise + run * run).sqrt();
+        if length == 0.0 {
+            continue;
+        }
+        let (px, py) = utils::world_to_pixel(line.start, width, height);
+        if field.in_bounds(px as i32, py as i32) {
+            let (x, y) = field.get(px as u32, py as u32);
+            // world y points up, the grid's points down
+            field.set(px as u32, py as u32, (x + run / length, y - rise / length));
+        }
+    }
+    for v in &mut field.data {
+        let length = (v.0 * v.0 + v.1 * v.1).sqrt();
+        if length > 0.0 {
+            *v = (v.0 / length, v.1 / length);
+        }
+    }
+    field
+}
+
+/// Shortest passage by sea between two points on a navigation grid of the ocean cells,
+/// straightened wherever there is open water between the turns. Currents, when given,
+/// make sailing with them cheaper and against them dearer.
+pub fn find_sea_route(
+    from: Vertex,
+    to: Vertex,
+    mask: &Grid<bool>,
+    currents: Option<&Grid<(f32, f32)>>,
+    settings: &SeaRouteSettings,
+    top_left: (f32, f32),
+    bottom_right: (f32, f32),
+) -> Option<SeaRoute> {
+    let width = mask.width;
+    let height = mask.height;
+    let cell_of = |v: Vertex| {
+        let (px, py) = utils::world_to_pixel(v, width, height);
+        if !mask.in_bounds(px as i32, py as i32) {
+            return None;
+        }
+        Some(mask.index(px as u32, py as u32))
+    };
+    let start = cell_of(from)?;
+    let goal = cell_of(to)?;
+
+    let currents = currents.filter(|c| settings.use_currents && c.width == width && c.height == height);
+    let weight = settings.current_weight.clamp(0.0, 0.9);
+    let step_cost = |a: usize, b: usize| {
+        let Some(currents) = currents else {
+            return 1.0;
+        };
+        let (ax, ay) = mask.coords(a);
+        let (bx, by) = mask.coords(b);
+        let dx = bx as f32 - ax as f32;
+        let dy = by as f32 - ay as f32;
+        let length = (dx * dx + dy * dy).sqrt();
+        let (cx, cy) = currents.data[a];
+        1.0 - weight * (dx * cx + dy * cy) / length
+    };
+
+    let sea = |i: usize| !mask.data[i];
+    let mut search = geometry::PathSearch::new(mask.len());
+    let cheapest_step = if currents.is_some() { 1.0 - weight } else { 1.0 };
+    let path = search.a_star(mask, start, goal, sea, step_cost, cheapest_step)?;
+
+    // pull the path tight: from each turn go straight as far along it as can be seen
+    let mut turns = vec![path[0]];
+    let mut anchor = 0;
+    while anchor < path.len() - 1 {
+        let mut next = anchor + 1;
+        while next + 1 < path.len() && in_sight(mask, path[anchor], path[next + 1]) {
+            next += 1;
+        }
+        turns.push(path[next]);
+        anchor = next;
+    }
+
+    let point = |i: usize| {
+        let (x, y) = mask.coords(i);
+        utils::pixel_to_world(x as f32 + 0.5, y as f32 + 0.5, width, height)
+    };
+    let mut points: Vec<Vertex> = turns.iter().map(|i| point(*i)).collect();
+    points[0] = from;
+    *points.last_mut().unwrap() = to;
+
+    let mut length = 0.0;
+    let mut hours = 0.0;
+    let speed = settings.speed.max(0.1) * constants::KM_PER_NAUTICAL_MILE;
+    for pair in points.windows(2) {
+        let leg = great_circle_distance(pair[0], pair[1], top_left, bottom_right);
+        length += leg;
+        let mut leg_speed = speed;
+        if let Some(currents) = currents {
+            // average help from the currents under the leg, sampled at its cells
+            let cells = cells_between(mask, cell_of(pair[0])?, cell_of(pair[1])?);
+            let (dx, dy) = (pair[1].position[0] - pair[0].position[0], pair[0].position[1] - pair[1].position[1]);
+            let d = (dx * dx + dy * dy).sqrt().max(f32::EPSILON);
+            let along: f32 = cells.iter().map(|c| (currents.data[*c].0 * dx + currents.data[*c].1 * dy) / d).sum::<f32>()
+                / cells.len().max(1) as f32;
+            leg_speed *= 1.0 + weight * along;
+        }
+        hours += leg / leg_speed;
+    }
+
+    Some(SeaRoute {
+        lines: points.windows(2).map(|p| Line::new(p[0], p[1])).collect(),
+        length,
+        days: hours / 24.0,
+    })
+}
+
+/// Distance over the globe between two world points in kilometres.
+pub fn great_circle_distance(a: Vertex, b: Vertex, top_left: (f32, f32), bottom_right: (f32, f32)) -> f32 {
+    let (lon1, lat1) = utils::world_to_lon_lat(a, top_left, bottom_right);
+    let (lon2, lat2) = utils::world_to_lon_lat(b, top_left, bottom_right);
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (lon2 - lon1).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * constants::EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+}
+
+/// Every cell the straight line between the centres of two cells passes through, by
+/// Amanatides and Woo's traversal. Where the line runs exactly through a corner, both cells
+/// beside it are included, so that it can't slip between land touching diagonally.
+fn cells_between(grid: &Grid<bool>, a: usize, b: usize) -> Vec<usize> {
+    let (ax, ay) = grid.coords(a);
+    let (bx, by) = grid.coords(b);
+    let (dx, dy) = (bx as i64 - ax as i64, by as i64 - ay as i64);
+    let (step_x, step_y) = (dx.signum(), dy.signum());
+    let (nx, ny) = (dx.abs(), dy.abs());
+    let (mut x, mut y) = (ax as i64, ay as i64);
+    let mut cells = vec![a];
+    // the line crosses its i-th column side at (2i + 1) / 2nx of the way along and its j-th
+    // row side at (2j + 1) / 2ny, compared multiplied out so that corners are exact
+    let (mut i, mut j) = (0, 0);
+    while i < nx || j < ny {
+        let next_x = (2 * i + 1) * ny;
+        let next_y = (2 * j + 1) * nx;
+        if next_x == next_y {
+            cells.push(grid.index((x + step_x) as u32, y as u32));
+            cells.push(grid.index(x as u32, (y + step_y) as u32));
+            x += step_x;
+            y += step_y;
+            i += 1;
+            j += 1;
+        } else if next_x < next_y {
+            x += step_x;
+            i += 1;
+        } else {
+            y += step_y;
+            j += 1;
+        }
+        cells.push(grid.index(x as u32, y as u32));
+    }
+    cells
+}
+
+fn in_sight(mask: &Grid<bool>, a: usize, b: usize) -> bool {
+    cells_between(mask, a, b).into_iter().all(|c| !mask.data[c])
+}
@@ -0,0 +1,87 @@
+use rand::seq::SliceRandom;
+
+use crate::seed;
+
+/// Seeded 2D gradient (Perlin) noise.
+pub struct Noise {
+    perm: [u8; 512],
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        let mut table: Vec<u8> = (0..=255).collect();
+        let mut rng = seed::from_seed(seed);
+        table.shuffle(&mut rng);
+
+        let mut perm = [0; 512];
+        for (i, p) in perm.iter_mut().enumerate() {
+            *p = table[i % 256];
+        }
+        Noise { perm }
+    }
+
+    /// Noise value at `(x, y)`, roughly in `-1.0..=1.0`.
+    pub fn get(&self, x: f32, y: f32) -> f32 {
+        let xi = x.floor();
+        let yi = y.floor();
+        let xf = x - xi;
+        let yf = y - yi;
+        let xi = (xi as i32 & 255) as usize;
+        let yi = (yi as i32 & 255) as usize;
+
+        let aa = self.perm[self.perm[xi] as usize + yi];
+        let ab = self.perm[self.perm[xi] as usize + yi + 1];
+        let ba = self.perm[self.perm[xi + 1] as usize + yi];
+        let bb = self.perm[self.perm[xi + 1] as usize + yi + 1];
+
+        let u = fade(xf);
+        let v = fade(yf);
+
+        let x1 = lerp(gradient(aa, xf, yf), gradient(ba, xf - 1.0, yf), u);
+        let x2 = lerp(
+            gradient(ab, xf, yf - 1.0),
+            gradient(bb, xf - 1.0, yf - 1.0),
+            u,
+        );
+        lerp(x1, x2, v)
+    }
+
+    /// Fractal brownian motion, normalised to `0.0..=1.0`.
+    pub fn fbm(&self, x: f32, y: f32, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max = 0.0;
+        for _ in 0..octaves {
+            total += self.get(x * frequency, y * frequency) * amplitude;
+            max += amplitude;
+            amplitude *= gain;
+            frequency *= lacunarity;
+        }
+        if max == 0.0 {
+            return 0.5;
+        }
+        (total / max * 0.5 + 0.5).clamp(0.0, 1.0)
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn gradient(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
@@ -0,0 +1,774 @@
+use std::{
+    collections::VecDeque,
+    f32::consts::{FRAC_PI_2, FRAC_PI_4, PI, SQRT_2},
+    sync::{Arc, Mutex},
+};
+
+use egui::Context;
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    constants,
+    geometry::{Line, Vertex},
+    info::WorldInfo,
+    scheduler::{Simulation, SimulationFrame},
+    texture_manager::{self, TextureData},
+    utils,
+};
+
+// Robinson's table, every 5° of latitude from the equator to the pole: the length of the
+// parallel and its distance from the equator
+const ROBINSON_X: [f32; 19] = [
+    1.0000, 0.9986, 0.9954, 0.9900, 0.9822, 0.9730, 0.9600, 0.9427, 0.9216, 0.8962, 0.8679, 0.8350, 0.7986, 0.7597, 0.7186,
+    0.6732, 0.6213, 0.5722, 0.5322,
+];
+const ROBINSON_Y: [f32; 19] = [
+    0.0000, 0.0620, 0.1240, 0.1860, 0.2480, 0.3100, 0.3720, 0.4340, 0.4958, 0.5571, 0.6176, 0.6769, 0.7346, 0.7903, 0.8435,
+    0.8936, 0.9394, 0.9761, 1.0000,
+];
+
+/// How the globe is laid flat. The base image is taken to be equirectangular, so that one
+/// shows the world as it is stored.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Projection {
+    Equirectangular,
+    Mercator,
+    Robinson,
+    Mollweide,
+    /// The globe seen from far away, over `centre_lon` and `centre_lat`.
+    Orthographic,
+    /// Looking straight down on a pole.
+    PolarStereographic,
+}
+
+impl Projection {
+    pub fn name(&self) -> &str {
+        match self {
+            Projection::Equirectangular => "Equirectangular",
+            Projection::Mercator => "Mercator",
+            Projection::Robinson => "Robinson",
+            Projection::Mollweide => "Mollweide",
+            Projection::Orthographic => "Orthographic",
+            Projection::PolarStereographic => "Polar Stereographic",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProjectionSettings {
+    pub(crate) projection: Projection,
+    /// Longitude in the middle of the map, in degrees.
+    pub(crate) centre_lon: f32,
+    /// Latitude the orthographic globe is seen from, in degrees.
+    pub(crate) centre_lat: f32,
+    /// Which pole the polar stereographic projection looks down on.
+    pub(crate) north: bool,
+}
+
+impl Default for ProjectionSettings {
+    fn default() -> Self {
+        ProjectionSettings {
+            projection: Projection::Equirectangular,
+            centre_lon: 0.0,
+            centre_lat: 0.0,
+            north: true,
+        }
+    }
+}
+
+/// Moves world points between the base image's equirectangular frame and a projection.
+/// Projected maps are scaled to be two world units high and centred, like the base image.
+#[derive(Copy, Clone, Debug)]
+pub struct Projector {
+    settings: ProjectionSettings,
+    top_left: (f32, f32),
+    bottom_right: (f32, f32),
+    /// Half the width and height of the projection, in its own units.
+    extent: (f32, f32),
+}
+
+impl Projector {
+    pub fn new(settings: ProjectionSettings, top_left: (f32, f32), bottom_right: (f32, f32)) -> Self {
+        let extent = match settings.projection {
+            Projection::Equirectangular => (PI, FRAC_PI_2),
+            Projection::Mercator => (PI, mercator_y(constants::MERCATOR_LIMIT.to_radians())),
+            Projection::Robinson => (0.8487 * PI, 1.3523),
+            Projection::Mollweide => (2.0 * SQRT_2, SQRT_2),
+            Projection::Orthographic => (1.0, 1.0),
+            Projection::PolarStereographic => {
+                let radius = stereographic_radius(-constants::STEREOGRAPHIC_LIMIT.to_radians());
+                (radius, radius)
+            }
+        };
+        Projector { settings, top_left, bottom_right, extent }
+    }
+
+    /// The base image's own frame, where nothing needs to move.
+    pub fn is_identity(&self) -> bool {
+        self.settings.projection == Projection::Equirectangular
+    }
+
+    /// Corners of the projected map in world units.
+    pub fn bounds(&self) -> ((f32, f32), (f32, f32)) {
+        if self.is_identity() {
+            return (self.top_left, self.bottom_right);
+        }
+        let aspect = self.extent.0 / self.extent.1;
+        ((-aspect, 1.0), (aspect, -1.0))
+    }
+
+    /// Width over height of the projected map.
+    pub fn aspect_ratio(&self) -> f32 {
+        let ((left, top), (right, bottom)) = self.bounds();
+        (right - left) / (top - bottom)
+    }
+
+    /// Where a point of the base image ends up, if the projection shows it at all.
+    pub fn project(&self, world: Vertex) -> Option<Vertex> {
+        self.project_near(world, world)
+    }
+
+    /// Like `project`, but a point on the edge of the map goes to the side of it that
+    /// `near` is on, so that what is cut at the edge reaches right up to it.
+    pub fn project_near(&self, world: Vertex, near: Vertex) -> Option<Vertex> {
+        if self.is_identity() {
+            return Some(world);
+        }
+        let (lon, lat) = utils::world_to_lon_lat(world, self.top_left, self.bottom_right);
+        let (near_lon, _) = utils::world_to_lon_lat(near, self.top_left, self.bottom_right);
+        let mut lon = self.longitude(lon.to_radians());
+        let near_lon = self.longitude(near_lon.to_radians());
+        if lon - near_lon > PI {
+            lon -= 2.0 * PI;
+        } else if near_lon - lon > PI {
+            lon += 2.0 * PI;
+        }
+        let (x, y) = self.forward(lon, lat.to_radians())?;
+        let scale = 1.0 / self.extent.1;
+        Some(Vertex { position: [x * scale, y * scale], tex_coords: world.tex_coords })
+    }
+
+    /// A triangle of the base image as triangles of the map. It is cut where the edges of
+    /// the map and the limit of what the projection shows run through it, and into pieces
+    /// no longer than `step` on a side so that it can bend.
+    pub fn project_triangle(&self, triangle: [Vertex; 3], step: f32) -> Vec<Vertex> {
+        if self.is_identity() {
+            return triangle.to_vec();
+        }
+        let mut projected = Vec::new();
+        for piece in self.cut_at_seams(&triangle) {
+            let mut pieces = Vec::new();
+            for corners in fan(&piece) {
+                subdivide(corners, step, &mut pieces);
+            }
+            for corners in pieces {
+                let shown = clip_polygon(&corners, |v| self.shows(v), |a, b| lerp(a, b, self.limb(a, b)));
+                if shown.len() < 3 {
+                    continue;
+                }
+                let near = centroid(&shown);
+                let corners: Option<Vec<Vertex>> = shown.iter().map(|v| self.project_near(*v, near)).collect();
+                if let Some(corners) = corners {
+                    projected.extend(fan(&corners).into_iter().flatten());
+                }
+            }
+        }
+        projected
+    }
+
+    /// A line of the base image as lines of the map, cut like `project_triangle`, each with
+    /// how far along the line it starts and ends, from 0 to 1.
+    pub fn project_line(&self, line: Line, step: f32) -> Vec<(Line, f32, f32)> {
+        if self.is_identity() {
+            return vec![(line, 0.0, 1.0)];
+        }
+        let (a, b) = (line.start, line.end);
+        let dx = b.position[0] - a.position[0];
+        let mut cuts = vec![0.0, 1.0];
+        if dx != 0.0 {
+            cuts.extend(self.seams().into_iter().map(|seam| (seam - a.position[0]) / dx).filter(|t| *t > 0.0 && *t < 1.0));
+        }
+        cuts.sort_by(f32::total_cmp);
+
+        let length = ((b.position[0] - a.position[0]).powi(2) + (b.position[1] - a.position[1]).powi(2)).sqrt();
+        let mut pieces = Vec::new();
+        for span in cuts.windows(2) {
+            let count = (length * (span[1] - span[0]) / step).ceil().max(1.0) as usize;
+            for k in 0..count {
+                let mut from = span[0] + (span[1] - span[0]) * k as f32 / count as f32;
+                let mut to = span[0] + (span[1] - span[0]) * (k + 1) as f32 / count as f32;
+                let (start, end) = (lerp(a, b, from), lerp(a, b, to));
+                match (self.shows(start), self.shows(end)) {
+                    (true, true) => {}
+                    (false, false) => continue,
+                    (true, false) => to = from + (to - from) * self.limb(start, end),
+                    (false, true) => from = to + (from - to) * self.limb(end, start),
+                }
+                let near = lerp(a, b, (from + to) / 2.0);
+                let start = self.project_near(lerp(a, b, from), near);
+                let end = self.project_near(lerp(a, b, to), near);
+                if let (Some(start), Some(end)) = (start, end) {
+                    pieces.push((Line::new(start, end), from, to));
+                }
+            }
+        }
+        pieces
+    }
+
+    /// The point of the base image shown at a point of the projected map.
+    pub fn unproject(&self, projected: Vertex) -> Option<Vertex> {
+        if self.is_identity() {
+            return Some(projected);
+        }
+        let x = projected.position[0] * self.extent.1;
+        let y = projected.position[1] * self.extent.1;
+        let (lon, lat) = self.inverse(x, y)?;
+        Some(utils::lon_lat_to_world(lon.to_degrees(), lat.to_degrees(), self.top_left, self.bottom_right))
+    }
+
+    /// World x of the meridians of the base image that the left and right edges of the map
+    /// are cut along, a world to either side included, for projections with such edges.
+    fn seams(&self) -> Vec<f32> {
+        if !matches!(self.settings.projection, Projection::Mercator | Projection::Robinson | Projection::Mollweide) {
+            return Vec::new();
+        }
+        let lon = self.settings.centre_lon.rem_euclid(360.0) - 180.0;
+        let x = utils::lon_lat_to_world(lon, 0.0, self.top_left, self.bottom_right).position[0];
+        let width = self.bottom_right.0 - self.top_left.0;
+        vec![x - width, x, x + width]
+    }
+
+    /// The pieces of a convex polygon between the seams.
+    fn cut_at_seams(&self, polygon: &[Vertex]) -> Vec<Vec<Vertex>> {
+        let seams = self.seams();
+        if seams.is_empty() {
+            return vec![polygon.to_vec()];
+        }
+        let crossing = |x: f32| move |a: Vertex, b: Vertex| lerp(a, b, (x - a.position[0]) / (b.position[0] - a.position[0]));
+        let bounds: Vec<f32> = [f32::NEG_INFINITY].into_iter().chain(seams).chain([f32::INFINITY]).collect();
+        bounds
+            .windows(2)
+            .map(|strip| {
+                let (low, high) = (strip[0], strip[1]);
+                let piece = clip_polygon(polygon, |v| v.position[0] >= low, crossing(low));
+                clip_polygon(&piece, |v| v.position[0] <= high, crossing(high))
+            })
+            .filter(|piece| piece.len() >= 3)
+            .collect()
+    }
+
+    /// Whether the projection shows a point of the base image.
+    fn shows(&self, world: Vertex) -> bool {
+        let (lon, lat) = utils::world_to_lon_lat(world, self.top_left, self.bottom_right);
+        self.visibility(self.longitude(lon.to_radians()), lat.to_radians()) >= 0.0
+    }
+
+    /// How far along from `a`, which the projection shows, to `b`, which it doesn't, the
+    /// edge of what it shows is, as far as halving the gap a few times finds.
+    fn limb(&self, a: Vertex, b: Vertex) -> f32 {
+        let (mut shown, mut hidden) = (0.0, 1.0);
+        for _ in 0..16 {
+            let t = (shown + hidden) / 2.0;
+            if self.shows(lerp(a, b, t)) {
+                shown = t;
+            } else {
+                hidden = t;
+            }
+        }
+        shown
+    }
+
+    fn longitude(&self, lon: f32) -> f32 {
+        (lon - self.settings.centre_lon.to_radians() + PI).rem_euclid(2.0 * PI) - PI
+    }
+
+    /// How far inside what the projection shows a longitude from the centre of the map and
+    /// a latitude, both in radians, are: negative where they are hidden.
+    fn visibility(&self, lon: f32, lat: f32) -> f32 {
+        match self.settings.projection {
+            Projection::Mercator => constants::MERCATOR_LIMIT.to_radians() - lat.abs(),
+            Projection::Orthographic => {
+                // the far side of the globe is hidden
+                let centre = self.settings.centre_lat.to_radians();
+                centre.sin() * lat.sin() + centre.cos() * lat.cos() * lon.cos()
+            }
+            Projection::PolarStereographic => {
+                let lat = if self.settings.north { lat } else { -lat };
+                lat + constants::STEREOGRAPHIC_LIMIT.to_radians()
+            }
+            Projection::Equirectangular | Projection::Robinson | Projection::Mollweide => 1.0,
+        }
+    }
+
+    /// Projected coordinates of a longitude from the centre of the map and a latitude,
+    /// both in radians.
+    fn forward(&self, lon: f32, lat: f32) -> Option<(f32, f32)> {
+        if self.visibility(lon, lat) < 0.0 {
+            return None;
+        }
+        match self.settings.projection {
+            Projection::Equirectangular => Some((lon, lat)),
+            Projection::Mercator => Some((lon, mercator_y(lat))),
+            Projection::Robinson => {
+                let (x, y) = robinson_row(lat.abs().to_degrees());
+                Some((0.8487 * x * lon, 1.3523 * y * lat.signum()))
+            }
+            Projection::Mollweide => {
+                let theta = mollweide_theta(lat);
+                Some((2.0 * SQRT_2 / PI * lon * theta.cos(), SQRT_2 * theta.sin()))
+            }
+            Projection::Orthographic => {
+                let centre = self.settings.centre_lat.to_radians();
+                Some((lat.cos() * lon.sin(), centre.cos() * lat.sin() - centre.sin() * lat.cos() * lon.cos()))
+            }
+            Projection::PolarStereographic => {
+                let lat = if self.settings.north { lat } else { -lat };
+                let radius = stereographic_radius(lat);
+                let y = radius * lon.cos();
+                Some((radius * lon.sin(), if self.settings.north { -y } else { y }))
+            }
+        }
+    }
+
+    /// Longitude and latitude in radians at projected coordinates.
+    fn inverse(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let centre_lon = self.settings.centre_lon.to_radians();
+        let (lon, lat) = match self.settings.projection {
+            Projection::Equirectangular => (x, y),
+            Projection::Mercator => (x, 2.0 * y.exp().atan() - FRAC_PI_2),
+            Projection::Robinson => {
+                let lat = robinson_latitude((y / 1.3523).abs())?.to_radians() * y.signum();
+                let (length, _) = robinson_row(lat.abs().to_degrees());
+                (x / (0.8487 * length), lat)
+            }
+            Projection::Mollweide => {
+                let theta = (y / SQRT_2).clamp(-1.0, 1.0).asin();
+                let lat = ((2.0 * theta + (2.0 * theta).sin()) / PI).clamp(-1.0, 1.0).asin();
+                (PI * x / (2.0 * SQRT_2 * theta.cos().max(f32::EPSILON)), lat)
+            }
+            Projection::Orthographic => {
+                let rho = (x * x + y * y).sqrt();
+                if rho > 1.0 {
+                    return None;
+                }
+                if rho == 0.0 {
+                    return Some((centre_lon, self.settings.centre_lat.to_radians()));
+                }
+                let centre = self.settings.centre_lat.to_radians();
+                let c = rho.asin();
+                let lat = (c.cos() * centre.sin() + y * c.sin() * centre.cos() / rho).clamp(-1.0, 1.0).asin();
+                let lon = (x * c.sin()).atan2(rho * c.cos() * centre.cos() - y * c.sin() * centre.sin());
+                (lon, lat)
+            }
+            Projection::PolarStereographic => {
+                let rho = (x * x + y * y).sqrt();
+                let lat = FRAC_PI_2 - 2.0 * (rho / 2.0).atan();
+                if lat < -constants::STEREOGRAPHIC_LIMIT.to_radians() {
+                    return None;
+                }
+                if self.settings.north {
+                    (x.atan2(-y), lat)
+                } else {
+                    (x.atan2(y), -lat)
+                }
+            }
+        };
+        if lon.abs() > PI + f32::EPSILON || lat.abs() > FRAC_PI_2 + f32::EPSILON {
+            return None;
+        }
+        Some(((lon + centre_lon + PI).rem_euclid(2.0 * PI) - PI, lat))
+    }
+}
+
+fn lerp(a: Vertex, b: Vertex, t: f32) -> Vertex {
+    let p = [a.position[0] + (b.position[0] - a.position[0]) * t, a.position[1] + (b.position[1] - a.position[1]) * t];
+    Vertex { position: p, tex_coords: p }
+}
+
+fn centroid(polygon: &[Vertex]) -> Vertex {
+    let n = polygon.len() as f32;
+    let p = [polygon.iter().map(|v| v.position[0]).sum::<f32>() / n, polygon.iter().map(|v| v.position[1]).sum::<f32>() / n];
+    Vertex { position: p, tex_coords: p }
+}
+
+/// A convex polygon as triangles around its first corner.
+fn fan(polygon: &[Vertex]) -> Vec<[Vertex; 3]> {
+    (1..polygon.len().saturating_sub(1)).map(|i| [polygon[0], polygon[i], polygon[i + 1]]).collect()
+}
+
+/// The part of a convex polygon where `inside` holds, `crossing` finding where an edge
+/// leaves it when given the end inside first.
+fn clip_polygon(polygon: &[Vertex], inside: impl Fn(Vertex) -> bool, crossing: impl Fn(Vertex, Vertex) -> Vertex) -> Vec<Vertex> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 2);
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        match (inside(*a), inside(b)) {
+            (true, true) => clipped.push(b),
+            (true, false) => clipped.push(crossing(*a, b)),
+            (false, true) => {
+                clipped.push(crossing(b, *a));
+                clipped.push(b);
+            }
+            (false, false) => {}
+        }
+    }
+    clipped
+}
+
+/// Cuts a triangle until no side is longer than `step`, halving every side that is. Whether
+/// a side is halved depends on nothing but the side, so triangles that share it cut it at
+/// the same points and stay closed up once projected.
+fn subdivide(triangle: [Vertex; 3], step: f32, pieces: &mut Vec<[Vertex; 3]>) {
+    let mut stack = vec![triangle];
+    while let Some([a, b, c]) = stack.pop() {
+        let long = |p: Vertex, q: Vertex| (q.position[0] - p.position[0]).powi(2) + (q.position[1] - p.position[1]).powi(2) > step * step;
+        let (ab, bc, ca) = (lerp(a, b, 0.5), lerp(b, c, 0.5), lerp(c, a, 0.5));
+        match (long(a, b), long(b, c), long(c, a)) {
+            (false, false, false) => pieces.push([a, b, c]),
+            (true, false, false) => stack.extend([[a, ab, c], [ab, b, c]]),
+            (false, true, false) => stack.extend([[b, bc, a], [bc, c, a]]),
+            (false, false, true) => stack.extend([[c, ca, b], [ca, a, b]]),
+            (true, true, false) => stack.extend([[ab, b, bc], [a, ab, bc], [a, bc, c]]),
+            (false, true, true) => stack.extend([[bc, c, ca], [b, bc, ca], [b, ca, a]]),
+            (true, false, true) => stack.extend([[ca, a, ab], [c, ca, ab], [c, ab, b]]),
+            (true, true, true) => stack.extend([[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]),
+        }
+    }
+}
+
+fn mercator_y(lat: f32) -> f32 {
+    (FRAC_PI_4 + lat / 2.0).tan().ln()
+}
+
+/// Distance from the pole on a stereographic map of a unit globe.
+fn stereographic_radius(lat: f32) -> f32 {
+    2.0 * (FRAC_PI_4 - lat / 2.0).tan()
+}
+
+/// The auxiliary angle of the Mollweide projection, from `2θ + sin 2θ = π sin φ`.
+fn mollweide_theta(lat: f32) -> f32 {
+    if lat.abs() >= FRAC_PI_2 - f32::EPSILON {
+        return lat;
+    }
+    let target = PI * lat.sin();
+    let mut theta = lat;
+    for _ in 0..20 {
+        let step = (2.0 * theta + (2.0 * theta).sin() - target) / (2.0 + 2.0 * (2.0 * theta).cos());
+        theta -= step;
+        if step.abs() < 1e-6 {
+            break;
+        }
+    }
+    theta
+}
+
+/// Robinson's table read at a latitude in degrees, between its rows.
+fn robinson_row(lat: f32) -> (f32, f32) {
+    let position = (lat / 5.0).clamp(0.0, 18.0);
+    let i = (position as usize).min(17);
+    let f = position - i as f32;
+    (
+        ROBINSON_X[i] + (ROBINSON_X[i + 1] - ROBINSON_X[i]) * f,
+        ROBINSON_Y[i] + (ROBINSON_Y[i + 1] - ROBINSON_Y[i]) * f,
+    )
+}
+
+/// The latitude in degrees at a height of Robinson's table, the inverse of `robinson_row`.
+fn robinson_latitude(y: f32) -> Option<f32> {
+    if y > 1.0 {
+        return None;
+    }
+    let i = ROBINSON_Y.windows(2).position(|w| y <= w[1]).unwrap_or(17);
+    let f = (y - ROBINSON_Y[i]) / (ROBINSON_Y[i + 1] - ROBINSON_Y[i]);
+    Some((i as f32 + f) * 5.0)
+}
+
+/// The rasters of the world resampled into a projection, and what they were made from.
+pub struct ProjectedRasters {
+    pub(crate) settings: ProjectionSettings,
+    /// `WorldInfo.raster_version` when they were made.
+    pub(crate) version: u64,
+    pub(crate) base: Option<TextureData>,
+    pub(crate) hillshade: Option<TextureData>,
+    pub(crate) overlays: Vec<TextureData>,
+}
+
+/// Projected rasters on their way from a `ReprojectJob`, which leaves them in `slot`.
+/// Dropping it stops the job.
+pub struct PendingRasters {
+    pub(crate) settings: ProjectionSettings,
+    pub(crate) version: u64,
+    pub(crate) slot: Arc<Mutex<Option<ProjectedRasters>>>,
+}
+
+#[derive(Copy, Clone)]
+enum RasterKind {
+    Base,
+    Hillshade,
+    Overlay,
+}
+
+/// Resamples the world's images into the projection on the simulation thread, a band of
+/// rows a tick. What the projection doesn't show is left clear.
+pub struct ReprojectJob {
+    egui_ctx: Context,
+    projector: Projector,
+    /// The images still to do, as pyramids of the equirectangular originals.
+    sources: VecDeque<(RasterKind, Arc<Vec<RgbaImage>>)>,
+    /// The image being done and the next row of it.
+    current: Option<(RasterKind, Arc<Vec<RgbaImage>>, RgbaImage, u32)>,
+    result: Option<ProjectedRasters>,
+    slot: Arc<Mutex<Option<ProjectedRasters>>>,
+    rows_done: u64,
+    rows: u64,
+}
+
+impl ReprojectJob {
+    pub fn new(egui_ctx: &Context, world_info: &WorldInfo) -> (Self, PendingRasters) {
+        let projector = world_info.projector();
+        let mut sources = VecDeque::new();
+        let mut add = |kind: RasterKind, texture: &TextureData| sources.push_back((kind, Arc::clone(&texture.levels)));
+        if let Some(texture) = &world_info.world_texture {
+            add(RasterKind::Base, texture);
+        }
+        if let Some(texture) = &world_info.hillshade {
+            add(RasterKind::Hillshade, texture);
+        }
+        for texture in &world_info.texture_list {
+            add(RasterKind::Overlay, texture);
+        }
+        let rows = sources.iter().map(|(_, levels)| projected_size(&projector, &levels[0]).1 as u64).sum();
+
+        let (settings, version) = (world_info.projection, world_info.raster_version);
+        let slot = Arc::new(Mutex::new(None));
+        let job = ReprojectJob {
+            egui_ctx: egui_ctx.clone(),
+            projector,
+            sources,
+            current: None,
+            result: Some(ProjectedRasters { settings, version, base: None, hillshade: None, overlays: Vec::new() }),
+            slot: Arc::clone(&slot),
+            rows_done: 0,
+            rows,
+        };
+        (job, PendingRasters { settings, version, slot })
+    }
+}
+
+impl Simulation for ReprojectJob {
+    fn name(&self) -> &str {
+        "Reprojection"
+    }
+
+    fn tick(&mut self) {
+        // nobody is waiting for these any more
+        if Arc::strong_count(&self.slot) == 1 {
+            self.result = None;
+            return;
+        }
+        if self.current.is_none() {
+            let Some((kind, levels)) = self.sources.pop_front() else {
+                *self.slot.lock().unwrap() = self.result.take();
+                return;
+            };
+            let (width, height) = projected_size(&self.projector, &levels[0]);
+            self.current = Some((kind, levels, RgbaImage::new(width, height), 0));
+        }
+
+        let Some((_, levels, image, row)) = &mut self.current else {
+            return;
+        };
+        let ((left, top), (right, bottom)) = self.projector.bounds();
+        let (width, height) = image.dimensions();
+        let pixel = (right - left) / width as f32;
+        let end = (*row + (constants::REPROJECT_PIXELS_PER_TICK / width).max(1)).min(height);
+        for y in *row..end {
+            for x in 0..width {
+                let point = [left + (x as f32 + 0.5) * pixel, top - (y as f32 + 0.5) / height as f32 * (top - bottom)];
+                if let Some(color) = sample_projected(levels, &self.projector, point, pixel) {
+                    image.put_pixel(x, y, Rgba(color.map(|c| (c * 255.0).round() as u8)));
+                }
+            }
+        }
+        self.rows_done += (end - *row) as u64;
+        *row = end;
+
+        if end == height {
+            let (kind, _, image, _) = self.current.take().unwrap();
+            let texture = texture_manager::get_texture_data(&self.egui_ctx, image);
+            let result = self.result.as_mut().unwrap();
+            match kind {
+                RasterKind::Base => result.base = Some(texture),
+                RasterKind::Hillshade => result.hillshade = Some(texture),
+                RasterKind::Overlay => result.overlays.push(texture),
+            }
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.result.is_none()
+    }
+
+    fn progress(&self) -> Option<f32> {
+        Some(self.rows_done as f32 / self.rows.max(1) as f32)
+    }
+
+    fn frame(&self) -> SimulationFrame {
+        SimulationFrame::default()
+    }
+
+    fn is_background(&self) -> bool {
+        true
+    }
+}
+
+/// The size of an image's projected copy: as tall as it is and as wide as the projected
+/// map is, unless that is more than `PROJECTED_MAX_SIZE`.
+fn projected_size(projector: &Projector, image: &RgbaImage) -> (u32, u32) {
+    let (width, height) = (image.height() as f32 * projector.aspect_ratio(), image.height() as f32);
+    let scale = (constants::PROJECTED_MAX_SIZE as f32 / width.max(height)).min(1.0);
+    ((width * scale).round().max(1.0) as u32, (height * scale).round().max(1.0) as u32)
+}
+
+/// The colour of a projected map at `point`, in its world units, from the pyramid of an
+/// equirectangular image. It is read from the level whose pixels are about as big there as
+/// the map's pixels, `pixel` world units wide, are. None where the projection shows nothing.
+pub fn sample_projected(levels: &[RgbaImage], projector: &Projector, point: [f32; 2], pixel: f32) -> Option<[f32; 4]> {
+    let at = |x: f32, y: f32| projector.unproject(Vertex { position: [x, y], tex_coords: [x, y] });
+    let (width, height) = levels[0].dimensions();
+    let (sx, sy) = utils::world_to_pixel(at(point[0], point[1])?, width, height);
+    if !(0.0..width as f32).contains(&sx) || !(0.0..height as f32).contains(&sy) {
+        return None;
+    }
+    // pixels of the full image between this pixel of the map and the ones beside it
+    let mut footprint: f32 = 0.0;
+    for neighbour in [at(point[0] + pixel, point[1]), at(point[0], point[1] - pixel)].into_iter().flatten() {
+        let (nx, ny) = utils::world_to_pixel(neighbour, width, height);
+        let dx = (nx - sx).abs();
+        // the short way round the globe
+        let dx = dx.min(width as f32 - dx);
+        footprint = footprint.max(dx).max((ny - sy).abs());
+    }
+    let level = &levels[texture_manager::level_for(levels, 1.0 / footprint)];
+    let (fx, fy) = (level.width() as f32 / width as f32, level.height() as f32 / height as f32);
+    Some(texture_manager::sample_bilinear(level, sx * fx - 0.5, sy * fy - 0.5))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOP_LEFT: (f32, f32) = (-2.0, 1.0);
+    const BOTTOM_RIGHT: (f32, f32) = (2.0, -1.0);
+
+    fn projector(projection: Projection, centre_lon: f32, centre_lat: f32, north: bool) -> Projector {
+        Projector::new(ProjectionSettings { projection, centre_lon, centre_lat, north }, TOP_LEFT, BOTTOM_RIGHT)
+    }
+
+    fn world(lon: f32, lat: f32) -> Vertex {
+        utils::lon_lat_to_world(lon, lat, TOP_LEFT, BOTTOM_RIGHT)
+    }
+
+    fn area(triangle: &[Vertex; 3]) -> f32 {
+        let [a, b, c] = triangle.map(|v| v.position);
+        ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])).abs() / 2.0
+    }
+
+    #[test]
+    fn unprojects_what_it_projects() {
+        let projectors = [
+            projector(Projection::Equirectangular, 0.0, 0.0, true),
+            projector(Projection::Mercator, 30.0, 0.0, true),
+            projector(Projection::Robinson, 30.0, 0.0, true),
+            projector(Projection::Mollweide, -60.0, 0.0, true),
+            projector(Projection::Orthographic, 20.0, 40.0, true),
+            projector(Projection::PolarStereographic, 0.0, 0.0, true),
+            projector(Projection::PolarStereographic, 45.0, 0.0, false),
+        ];
+        for projector in projectors {
+            // five degrees off every multiple of ten, so clear of each map's seam
+            for lon in (0..18).map(|i| -175.0 + 20.0 * i as f32) {
+                for lat in (0..16).map(|i| -75.0 + 10.0 * i as f32) {
+                    let point = world(lon, lat);
+                    let Some(projected) = projector.project(point) else {
+                        continue;
+                    };
+                    let back = projector.unproject(projected).unwrap();
+                    let error = (back.position[0] - point.position[0]).abs().max((back.position[1] - point.position[1]).abs());
+                    assert!(error < 1e-3, "{:?} at {}, {} came back {} off", projector.settings.projection, lon, lat, error);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hides_what_each_projection_cannot_show() {
+        let mercator = projector(Projection::Mercator, 0.0, 0.0, true);
+        assert!(mercator.project(world(10.0, constants::MERCATOR_LIMIT - 1.0)).is_some());
+        assert!(mercator.project(world(10.0, constants::MERCATOR_LIMIT + 1.0)).is_none());
+        assert!(mercator.project(world(10.0, -constants::MERCATOR_LIMIT - 1.0)).is_none());
+
+        let orthographic = projector(Projection::Orthographic, 0.0, 0.0, true);
+        assert!(orthographic.project(world(89.0, 0.0)).is_some());
+        assert!(orthographic.project(world(91.0, 0.0)).is_none());
+        assert!(orthographic.project(world(180.0 - 1.0, 0.0)).is_none());
+        assert!(orthographic.unproject(Vertex { position: [0.8, 0.8], tex_coords: [0.0, 0.0] }).is_none());
+
+        let north = projector(Projection::PolarStereographic, 0.0, 0.0, true);
+        assert!(north.project(world(0.0, -constants::STEREOGRAPHIC_LIMIT + 1.0)).is_some());
+        assert!(north.project(world(0.0, -constants::STEREOGRAPHIC_LIMIT - 1.0)).is_none());
+        let south = projector(Projection::PolarStereographic, 0.0, 0.0, false);
+        assert!(south.project(world(0.0, constants::STEREOGRAPHIC_LIMIT - 1.0)).is_some());
+        assert!(south.project(world(0.0, constants::STEREOGRAPHIC_LIMIT + 1.0)).is_none());
+    }
+
+    #[test]
+    fn solves_the_projections_own_equations() {
+        for lat in (-8..=8).map(|i| (i as f32 * 11.0).to_radians()) {
+            let theta = mollweide_theta(lat);
+            assert!((2.0 * theta + (2.0 * theta).sin() - PI * lat.sin()).abs() < 1e-4);
+        }
+        for lat in (0..18).map(|i| i as f32 * 5.0 + 2.5) {
+            let (_, y) = robinson_row(lat);
+            assert!((robinson_latitude(y).unwrap() - lat).abs() < 1e-3);
+        }
+        assert!(robinson_latitude(1.01).is_none());
+    }
+
+    #[test]
+    fn clips_polygons() {
+        let square = [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]].map(|p| Vertex { position: p, tex_coords: p });
+        let crossing = |a: Vertex, b: Vertex| lerp(a, b, (0.5 - a.position[0]) / (b.position[0] - a.position[0]));
+        let clipped = clip_polygon(&square, |v| v.position[0] <= 0.5, crossing);
+        let area: f32 = fan(&clipped).iter().map(area).sum();
+        assert!((area - 3.0).abs() < 1e-6);
+        assert!(clipped.iter().all(|v| v.position[0] <= 0.5));
+        assert!(clip_polygon(&square, |v| v.position[0] > 2.0, crossing).is_empty());
+    }
+
+    #[test]
+    fn subdivides_into_matching_pieces() {
+        let v = |x: f32, y: f32| Vertex { position: [x, y], tex_coords: [x, y] };
+        // two triangles sharing the side from (0, 0) to (3, 1)
+        let (first, second) = ([v(0.0, 0.0), v(3.0, 1.0), v(0.5, 2.0)], [v(3.0, 1.0), v(0.0, 0.0), v(2.0, -0.7)]);
+        let step = 0.4;
+        let on_shared_side = |pieces: &[[Vertex; 3]]| {
+            let mut xs: Vec<f32> = pieces.iter().flatten().filter(|p| (p.position[1] - p.position[0] / 3.0).abs() < 1e-5).map(|p| p.position[0]).collect();
+            xs.sort_by(f32::total_cmp);
+            xs.dedup();
+            xs
+        };
+
+        let mut sides = Vec::new();
+        for triangle in [first, second] {
+            let mut pieces = Vec::new();
+            subdivide(triangle, step, &mut pieces);
+            let length = |a: Vertex, b: Vertex| ((b.position[0] - a.position[0]).powi(2) + (b.position[1] - a.position[1]).powi(2)).sqrt();
+            assert!(pieces.iter().all(|[a, b, c]| length(*a, *b) <= step && length(*b, *c) <= step && length(*c, *a) <= step));
+            let total: f32 = pieces.iter().map(area).sum();
+            assert!((total - area(&triangle)).abs() < 1e-4);
+            sides.push(on_shared_side(&pieces));
+        }
+        assert_eq!(sides[0], sides[1]);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{constants, geometry::{Line, Vertex}, grid::Grid, utils};
+
+#[derive(Clone, Debug)]
+pub struct ReliefSettings {
+    /// Direction the light comes from in degrees, clockwise from north.
+    pub(crate) azimuth: f32,
+    /// Height of the sun above the horizon in degrees.
+    pub(crate) altitude: f32,
+    /// Multiplies heights before shading, the world's cells being kilometres wide.
+    pub(crate) exaggeration: f32,
+    /// Metres between contour lines.
+    pub(crate) contour_interval: f32,
+    /// Every this many contours is drawn thicker.
+    pub(crate) index_every: u32,
+    /// Contour the sea floor as well as the land.
+    pub(crate) bathymetry: bool,
+}
+
+impl Default for ReliefSettings {
+    fn default() -> Self {
+        ReliefSettings {
+            azimuth: 315.0,
+            altitude: 45.0,
+            exaggeration: 10.0,
+            contour_interval: 200.0,
+            index_every: 5,
+            bathymetry: false,
+        }
+    }
+}
+
+/// A side of a cell of the heightmap, as the corners at its ends.
+type Edge = (usize, usize);
+
+/// Where a contour crosses between two neighbouring cell centres: its step and the two
+/// centres, top left first, so that the cells on both sides name it the same.
+type Crossing = (i32, (u32, u32), (u32, u32));
+
+/// Contour lines, the index contours kept apart so they can be drawn thicker.
+#[derive(Clone, Debug, Default)]
+pub struct Contours {
+    pub(crate) lines: Vec<Line>,
+    pub(crate) index_lines: Vec<Line>,
+}
+
+/// How brightly each cell is lit, `0.0..=1.0`, from the slope and aspect of the terrain
+/// (Horn's method). The world is taken to span the globe, so cells narrow towards the poles.
+pub fn hillshade(heightmap: &Grid<f32>, settings: &ReliefSettings) -> Grid<f32> {
+    let (width, height) = (heightmap.width, heightmap.height);
+    let cell_height = std::f32::consts::PI * constants::EARTH_RADIUS_KM * 1000.0 / height as f32;
+    let azimuth = settings.azimuth.to_radians();
+    let zenith = (90.0 - settings.altitude).to_radians();
+
+    let at = |x: i32, y: i32| {
+        let x = x.rem_euclid(width as i32) as u32;
+        let y = y.clamp(0, height as i32 - 1) as u32;
+        heightmap.get(x, y) * settings.exaggeration
+    };
+    let mut shade = Grid::new(width, height, 0.0);
+    for y in 0..height as i32 {
+        let latitude = utils::row_latitude(y as f32 + 0.5, height).to_radians();
+        let cell_width = cell_height * latitude.cos().max(constants::MIN_CELL_WIDTH);
+        for x in 0..width as i32 {
+            let dzdx = ((at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x - 1, y) + at(x - 1, y + 1)))
+                / (8.0 * cell_width);
+            // rows count down the image, so this is the rise to the north
+            let dzdy = ((at(x - 1, y - 1) + 2.0 * at(x, y - 1) + at(x + 1, y - 1))
+                - (at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1)))
+                / (8.0 * cell_height);
+            let slope = (dzdx * dzdx + dzdy * dzdy).sqrt().atan();
+            // direction the ground faces, clockwise from north
+            let aspect = (-dzdx).atan2(-dzdy);
+            let light = zenith.cos() * slope.cos() + zenith.sin() * slope.sin() * (azimuth - aspect).cos();
+            shade.set(x as u32, y as u32, light.max(0.0));
+        }
+    }
+    shade
+}
+
+/// The shading as an overlay to blend over the base image: black where the ground is
+/// darker than flat ground would be, white where it is lighter, and clear at sea.
+pub fn hillshade_image(shade: &Grid<f32>, mask: &Grid<bool>, settings: &ReliefSettings) -> DynamicImage {
+    let flat = settings.altitude.to_radians().sin();
+    let image = RgbaImage::from_fn(shade.width, shade.height, |x, y| {
+        if !mask.get(x, y) {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let light = shade.get(x, y);
+        if light < flat {
+            Rgba([0, 0, 0, ((flat - light) / flat * 255.0) as u8])
+        } else {
+            let alpha = (light - flat) / (1.0 - flat).max(f32::EPSILON) * constants::HIGHLIGHT_STRENGTH;
+            Rgba([255, 255, 255, (alpha * 255.0) as u8])
+        }
+    });
+    DynamicImage::ImageRgba8(image)
+}
+
+/// Lines of equal height every `contour_interval` metres, by marching squares over the
+/// centres of the cells, joined up into polylines. Sea level is left to the coastline.
+pub fn contours(heightmap: &Grid<f32>, settings: &ReliefSettings) -> Contours {
+    let (width, height) = (heightmap.width, heightmap.height);
+    let interval = settings.contour_interval.max(1.0);
+    let mut contours = Contours::default();
+    if width < 2 || height < 2 {
+        return contours;
+    }
+    let point = |x: f32, y: f32| utils::pixel_to_world(x + 0.5, y + 0.5, width, height);
+    let mut segments = Vec::new();
+
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            // corners clockwise from the top left
+            let corners = [heightmap.get(x, y), heightmap.get(x + 1, y), heightmap.get(x + 1, y + 1), heightmap.get(x, y + 1)];
+            let low = corners.iter().copied().fold(f32::MAX, f32::min);
+            let high = corners.iter().copied().fold(f32::MIN, f32::max);
+            let first = (low / interval).floor() as i32 + 1;
+            let last = (high / interval).ceil() as i32 - 1;
+            for step in first..=last {
+                if step == 0 || (step < 0 && !settings.bathymetry) {
+                    continue;
+                }
+                let level = step as f32 * interval;
+                let crossing = |a: usize, b: usize| -> (Crossing, Vertex) {
+                    let offsets = [(0, 0), (1, 0), (1, 1), (0, 1)];
+                    let centre = |i: usize| (x + offsets[i].0, y + offsets[i].1);
+                    // always worked out from the same end, for the same point on both sides
+                    let (a, b) = if (centre(a).1, centre(a).0) <= (centre(b).1, centre(b).0) { (a, b) } else { (b, a) };
+                    let (ha, hb) = (corners[a], corners[b]);
+                    let t = ((level - ha) / (hb - ha)).clamp(0.0, 1.0);
+                    let ((ax, ay), (bx, by)) = (centre(a), centre(b));
+                    let position = point(ax as f32 + (bx as f32 - ax as f32) * t, ay as f32 + (by as f32 - ay as f32) * t);
+                    ((step, centre(a), centre(b)), position)
+                };
+                let case = corners.iter().enumerate().fold(0, |case, (i, h)| case | ((*h >= level) as usize) << i);
+                // edges as the pairs of corners at their ends: top, right, bottom, left
+                let (top, right, bottom, left) = ((0, 1), (1, 2), (2, 3), (3, 0));
+                let cell_segments: &[(Edge, Edge)] = match case {
+                    1 | 14 => &[(left, top)],
+                    2 | 13 => &[(top, right)],
+                    3 | 12 => &[(left, right)],
+                    4 | 11 => &[(right, bottom)],
+                    6 | 9 => &[(top, bottom)],
+                    7 | 8 => &[(left, bottom)],
+                    // saddles, split by whether the middle of the cell is above the level
+                    5 if corners.iter().sum::<f32>() / 4.0 >= level => &[(left, bottom), (top, right)],
+                    5 => &[(left, top), (right, bottom)],
+                    10 if corners.iter().sum::<f32>() / 4.0 >= level => &[(left, top), (right, bottom)],
+                    10 => &[(left, bottom), (top, right)],
+                    _ => &[],
+                };
+                for (a, b) in cell_segments {
+                    segments.push([crossing(a.0, a.1), crossing(b.0, b.1)]);
+                }
+            }
+        }
+    }
+
+    for (step, polyline) in join_segments(&segments) {
+        if step % settings.index_every.max(1) as i32 == 0 {
+            contours.index_lines.extend(polyline);
+        } else {
+            contours.lines.extend(polyline);
+        }
+    }
+    contours
+}
+
+/// Chains segments that meet at a crossing into polylines, each line starting where the
+/// one before it ended so that they are drawn with joins rather than overlapping ends.
+fn join_segments(segments: &[[(Crossing, Vertex); 2]]) -> Vec<(i32, Vec<Line>)> {
+    let mut ends: HashMap<Crossing, Vec<usize>> = HashMap::new();
+    for (i, segment) in segments.iter().enumerate() {
+        for (crossing, _) in segment {
+            ends.entry(*crossing).or_default().push(i);
+        }
+    }
+    let open = |crossing: &Crossing| ends[crossing].len() == 1;
+
+    let mut used = vec![false; segments.len()];
+    let mut polylines = Vec::new();
+    // open contours from one of their ends first, then the closed loops that are left
+    let starts = segments
+        .iter()
+        .enumerate()
+        .filter_map(|(i, [a, b])| if open(&a.0) { Some((i, 0)) } else if open(&b.0) { Some((i, 1)) } else { None })
+        .chain((0..segments.len()).map(|i| (i, 0)));
+    for (first, side) in starts.collect::<Vec<_>>() {
+        if used[first] {
+            continue;
+        }
+        let mut polyline = Vec::new();
+        let (mut i, mut side) = (first, side);
+        loop {
+            used[i] = true;
+            let (from, to) = (segments[i][side], segments[i][1 - side]);
+            polyline.push(Line::new(from.1, to.1));
+            let next = ends[&to.0].iter().copied().find(|j| !used[*j]);
+            let Some(j) = next else {
+                break;
+            };
+            side = if segments[j][0].0 == to.0 { 0 } else { 1 };
+            i = j;
+        }
+        polylines.push((segments[first][0].0 .0, polyline));
+    }
+    polylines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(width: u32, height: u32, height_at: impl Fn(f32, f32) -> f32) -> Grid<f32> {
+        let mut grid = Grid::new(width, height, 0.0);
+        for i in 0..grid.len() {
+            let (x, y) = grid.coords(i);
+            grid.data[i] = height_at(x as f32, y as f32);
+        }
+        grid
+    }
+
+    /// The cell coordinates of a point of the world, the inverse of `contours`' `point`.
+    fn cell(v: Vertex, width: u32, height: u32) -> (f32, f32) {
+        let aspect = width as f32 / height as f32;
+        ((v.position[0] / aspect + 1.0) / 2.0 * width as f32 - 0.5, (1.0 - v.position[1]) / 2.0 * height as f32 - 0.5)
+    }
+
+    /// Splits lines back into the polylines they were joined into.
+    fn polylines(lines: &[Line]) -> Vec<Vec<Line>> {
+        let mut polylines: Vec<Vec<Line>> = Vec::new();
+        for line in lines {
+            match polylines.last_mut() {
+                Some(polyline) if polyline.last().unwrap().end.position == line.start.position => polyline.push(*line),
+                _ => polylines.push(vec![*line]),
+            }
+        }
+        polylines
+    }
+
+    fn is_closed(polyline: &[Line]) -> bool {
+        polyline.first().unwrap().start.position == polyline.last().unwrap().end.position
+    }
+
+    #[test]
+    fn rings_a_cone() {
+        let (width, height) = (40, 40);
+        let cone = |x: f32, y: f32| 1000.0 - 60.0 * ((x - 19.5).powi(2) + (y - 19.5).powi(2)).sqrt();
+        let settings = ReliefSettings { contour_interval: 100.0, index_every: 3, ..Default::default() };
+        let contours = contours(&grid(width, height, cone), &settings);
+
+        // one closed ring for each of the levels from 100 to 900, every third an index contour
+        for (lines, index) in [(&contours.lines, false), (&contours.index_lines, true)] {
+            let rings = polylines(lines);
+            assert_eq!(rings.len(), if index { 3 } else { 6 });
+            for ring in rings {
+                assert!(is_closed(&ring));
+                let (x, y) = cell(ring[0].start, width, height);
+                let step = (cone(x, y) / 100.0).round() as i32;
+                assert_eq!(step % 3 == 0, index, "level {} drawn as the wrong kind of contour", step * 100);
+                assert!(ring.iter().all(|l| {
+                    let (x, y) = cell(l.start, width, height);
+                    (cone(x, y) - step as f32 * 100.0).abs() < 20.0
+                }));
+            }
+        }
+    }
+
+    #[test]
+    fn runs_straight_across_a_plane() {
+        let (width, height) = (20, 10);
+        let settings = ReliefSettings { contour_interval: 100.0, index_every: 1, ..Default::default() };
+        let contours = contours(&grid(width, height, |x, _| 10.0 + 100.0 * x), &settings);
+        assert!(contours.lines.is_empty());
+
+        let lines = polylines(&contours.index_lines);
+        // 100 to 1900 metres, each from the top row of cells to the bottom one
+        assert_eq!(lines.len(), 19);
+        for line in lines {
+            assert!(!is_closed(&line));
+            let (_, top) = cell(line.first().unwrap().start, width, height);
+            let (_, bottom) = cell(line.last().unwrap().end, width, height);
+            assert!(top.min(bottom).abs() < 1e-3 && (top.max(bottom) - 9.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn splits_saddles_by_the_middle_of_the_cell() {
+        // high corners top left and bottom right
+        let settings = ReliefSettings { contour_interval: 50.0, index_every: 1, ..Default::default() };
+        for (corners, middle_above) in [([90.0, 40.0, 40.0, 90.0], true), ([60.0, 0.0, 0.0, 60.0], false)] {
+            let saddle = Grid { width: 2, height: 2, data: corners.to_vec() };
+            let contours = contours(&saddle, &settings);
+            assert_eq!(contours.index_lines.len(), 2);
+            // each line cuts off one of the corners on the other side of the level from the middle
+            for line in &contours.index_lines {
+                let (sx, sy) = cell(line.start, 2, 2);
+                let (ex, ey) = cell(line.end, 2, 2);
+                let (mx, my) = ((sx + ex) / 2.0, (sy + ey) / 2.0);
+                let nearest = |points: [(f32, f32); 2]| points.iter().map(|(x, y)| (mx - x).hypot(my - y)).fold(f32::MAX, f32::min);
+                let (low, high) = (nearest([(1.0, 0.0), (0.0, 1.0)]), nearest([(0.0, 0.0), (1.0, 1.0)]));
+                assert_eq!(low < high, middle_above);
+            }
+        }
+    }
+}
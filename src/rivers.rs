@@ -0,0 +1,266 @@
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+
+use crate::{
+    constants,
+    geometry::{Line, Vertex},
+    grid::Grid,
+    utils,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FlowMethod {
+    D8,
+    DInfinity,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RiverSettings {
+    pub(crate) method: FlowMethod,
+    /// Number of upstream cells needed before a cell counts as a river.
+    pub(crate) threshold: f32,
+}
+
+impl Default for RiverSettings {
+    fn default() -> Self {
+        RiverSettings {
+            method: FlowMethod::DInfinity,
+            threshold: 200.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct River {
+    pub(crate) name: String,
+    pub(crate) lines: Vec<Line>,
+    /// Width at each point of the polyline, one more than there are lines.
+    pub(crate) widths: Vec<f32>,
+    /// Strahler order where the river ends.
+    pub(crate) order: u32,
+}
+
+// Tarboton's eight triangular facets, each given by its cardinal and diagonal neighbour
+const FACETS: [((i32, i32), (i32, i32)); 8] = [
+    ((1, 0), (1, -1)),
+    ((0, -1), (1, -1)),
+    ((0, -1), (-1, -1)),
+    ((-1, 0), (-1, -1)),
+    ((-1, 0), (-1, 1)),
+    ((0, 1), (-1, 1)),
+    ((0, 1), (1, 1)),
+    ((1, 0), (1, 1)),
+];
+
+/// Steepest descent neighbour of every land cell as an index into `NEIGHBOURS_8`, -1 for sea cells and pits.
+pub fn flow_directions(heightmap: &Grid<f32>, mask: &Grid<bool>) -> Grid<i8> {
+    let mut directions = Grid::new(heightmap.width, heightmap.height, -1);
+    for y in 0..heightmap.height as i32 {
+        for x in 0..heightmap.width as i32 {
+            if !mask.get(x as u32, y as u32) {
+                continue;
+            }
+            let h = heightmap.get(x as u32, y as u32);
+            let mut best = 0.0;
+            for (n, (dx, dy)) in constants::NEIGHBOURS_8.iter().enumerate() {
+                if !heightmap.in_bounds(x + dx, y + dy) {
+                    continue;
+                }
+                let distance = if dx.abs() + dy.abs() == 2 { SQRT_2 } else { 1.0 };
+                let slope = (h - heightmap.get((x + dx) as u32, (y + dy) as u32)) / distance;
+                if slope > best {
+                    best = slope;
+                    directions.set(x as u32, y as u32, n as i8);
+                }
+            }
+        }
+    }
+    directions
+}
+
+/// Receivers of a cell and the share of flow each one gets.
+fn receivers(heightmap: &Grid<f32>, directions: &Grid<i8>, method: FlowMethod, x: u32, y: u32) -> Vec<(usize, f32)> {
+    let dir = directions.get(x, y);
+    if dir < 0 {
+        return Vec::new();
+    }
+
+    if method == FlowMethod::D8 {
+        let (dx, dy) = constants::NEIGHBOURS_8[dir as usize];
+        return vec![(heightmap.index((x as i32 + dx) as u32, (y as i32 + dy) as u32), 1.0)];
+    }
+
+    let e0 = heightmap.get(x, y);
+    let mut best: Option<(f32, f32, usize, usize)> = None;
+    for ((c_x, c_y), (d_x, d_y)) in FACETS {
+        let (x1, y1) = (x as i32 + c_x, y as i32 + c_y);
+        let (x2, y2) = (x as i32 + d_x, y as i32 + d_y);
+        if !heightmap.in_bounds(x1, y1) || !heightmap.in_bounds(x2, y2) {
+            continue;
+        }
+        let e1 = heightmap.get(x1 as u32, y1 as u32);
+        let e2 = heightmap.get(x2 as u32, y2 as u32);
+        let s1 = e0 - e1;
+        let s2 = e1 - e2;
+        let mut r = s2.atan2(s1);
+        let mut s = (s1 * s1 + s2 * s2).sqrt();
+        if r < 0.0 {
+            r = 0.0;
+            s = s1;
+        } else if r > FRAC_PI_4 {
+            r = FRAC_PI_4;
+            s = (e0 - e2) / SQRT_2;
+        }
+        if s > 0.0 && best.is_none_or(|b| s > b.0) {
+            let i1 = heightmap.index(x1 as u32, y1 as u32);
+            let i2 = heightmap.index(x2 as u32, y2 as u32);
+            best = Some((s, r, i1, i2));
+        }
+    }
+
+    match best {
+        Some((_, r, i1, i2)) => {
+            let share = r / FRAC_PI_4;
+            vec![(i1, 1.0 - share), (i2, share)]
+        }
+        None => {
+            let (dx, dy) = constants::NEIGHBOURS_8[dir as usize];
+            vec![(heightmap.index((x as i32 + dx) as u32, (y as i32 + dy) as u32), 1.0)]
+        }
+    }
+}
+
+// land cells from the highest to the lowest, so every cell comes after the cells draining into it
+fn land_by_height(heightmap: &Grid<f32>, mask: &Grid<bool>) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..heightmap.len()).filter(|i| mask.data[*i]).collect();
+    order.sort_by(|a, b| heightmap.data[*b].total_cmp(&heightmap.data[*a]).then(a.cmp(b)));
+    order
+}
+
+/// Number of cells (each contributing one unit of rain) draining through every cell.
+pub fn flow_accumulation(heightmap: &Grid<f32>, mask: &Grid<bool>, directions: &Grid<i8>, method: FlowMethod) -> Grid<f32> {
+    let mut accumulation = Grid::new(heightmap.width, heightmap.height, 0.0);
+    for i in 0..accumulation.len() {
+        if mask.data[i] {
+            accumulation.data[i] = 1.0;
+        }
+    }
+
+    for i in land_by_height(heightmap, mask) {
+        let (x, y) = heightmap.coords(i);
+        let flow = accumulation.data[i];
+        for (receiver, share) in receivers(heightmap, directions, method, x, y) {
+            accumulation.data[receiver] += flow * share;
+        }
+    }
+    accumulation
+}
+
+/// Extracts rivers from cells whose accumulated flow passes the threshold. Rivers follow the
+/// steepest descent and end where they reach water (sea or lake), a pit, or a bigger river.
+/// Flow still runs through `lakes`, so a new river starts where a lake spills over.
+pub fn generate_rivers(heightmap: &Grid<f32>, mask: &Grid<bool>, lakes: &Grid<bool>, settings: &RiverSettings) -> Vec<River> {
+    let directions = flow_directions(heightmap, mask);
+    let accumulation = flow_accumulation(heightmap, mask, &directions, settings.method);
+    let width = heightmap.width;
+    let height = heightmap.height;
+
+    let downstream = |i: usize| -> Option<usize> {
+        let dir = directions.data[i];
+        if dir < 0 {
+            return None;
+        }
+        let (x, y) = heightmap.coords(i);
+        let (dx, dy) = constants::NEIGHBOURS_8[dir as usize];
+        Some(heightmap.index((x as i32 + dx) as u32, (y as i32 + dy) as u32))
+    };
+    let is_river = |i: usize| mask.data[i] && !lakes.data[i] && accumulation.data[i] >= settings.threshold;
+
+    // Strahler order, upstream cells are always handled first
+    let by_height = land_by_height(heightmap, mask);
+    let mut order = vec![0; heightmap.len()];
+    let mut max_in = vec![0; heightmap.len()];
+    let mut max_in_count = vec![0u8; heightmap.len()];
+    for &i in &by_height {
+        if !is_river(i) {
+            continue;
+        }
+        order[i] = match max_in_count[i] {
+            0 => 1,
+            1 => max_in[i],
+            _ => max_in[i] + 1,
+        };
+        if let Some(next) = downstream(i) {
+            if order[i] > max_in[next] {
+                max_in[next] = order[i];
+                max_in_count[next] = 1;
+            } else if order[i] == max_in[next] {
+                max_in_count[next] = max_in_count[next].saturating_add(1);
+            }
+        }
+    }
+
+    let max_accumulation = accumulation.data.iter().cloned().fold(1.0, f32::max);
+    let cell_point = |i: usize| {
+        let (x, y) = heightmap.coords(i);
+        utils::pixel_to_world(x as f32 + 0.5, y as f32 + 0.5, width, height)
+    };
+    let cell_width = |i: usize| {
+        (constants::RIVER_MAX_WIDTH * accumulation.data[i] / max_accumulation).max(constants::RIVER_MIN_WIDTH)
+    };
+
+    let mut visited = vec![false; heightmap.len()];
+    let mut rivers = Vec::new();
+    for &source in &by_height {
+        if !is_river(source) || max_in_count[source] != 0 || visited[source] {
+            continue;
+        }
+
+        let mut points: Vec<Vertex> = Vec::new();
+        let mut widths = Vec::new();
+        let mut current = source;
+        let mut last_order = 1;
+        loop {
+            points.push(cell_point(current));
+            widths.push(cell_width(current));
+            if visited[current] || !mask.data[current] || lakes.data[current] {
+                break;
+            }
+            visited[current] = true;
+            last_order = order[current];
+            match downstream(current) {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+
+        if points.len() < 2 {
+            continue;
+        }
+        let lines = points.windows(2).map(|p| Line::new(p[0], p[1])).collect();
+        rivers.push(River {
+            name: format!("River {}", rivers.len() + 1),
+            lines,
+            widths,
+            order: last_order,
+        });
+    }
+
+    rivers
+}
+
+/// Cells the rivers run through.
+pub fn river_mask(rivers: &[River], width: u32, height: u32) -> Grid<bool> {
+    let mut mask = Grid::new(width, height, false);
+    for river in rivers {
+        for line in &river.lines {
+            // river points sit on cell centres, so the ends of each segment are enough
+            for point in [line.start, line.end] {
+                let (px, py) = utils::world_to_pixel(point, width, height);
+                if mask.in_bounds(px as i32, py as i32) {
+                    mask.set(px as u32, py as u32, true);
+                }
+            }
+        }
+    }
+    mask
+}
@@ -0,0 +1,275 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::{
+    geometry::{self, Line, Vertex},
+    grid::Grid,
+    settlements::{Settlement, SettlementKind},
+    utils,
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct RoadSettings {
+    /// How many of its nearest neighbours each settlement is connected to.
+    pub(crate) connections: u32,
+    /// Extra cost per 100 m climbed or descended between neighbouring cells.
+    pub(crate) slope_weight: f32,
+    /// Cost of building a bridge onto a river.
+    pub(crate) bridge_cost: f32,
+    /// Cost of following an existing road compared to open ground, below one so roads merge.
+    pub(crate) reuse_factor: f32,
+}
+
+impl Default for RoadSettings {
+    fn default() -> Self {
+        RoadSettings {
+            connections: 2,
+            slope_weight: 5.0,
+            bridge_cost: 30.0,
+            reuse_factor: 0.4,
+        }
+    }
+}
+
+/// Ordered from the most to the least important, a road takes the class of the most
+/// important route that uses it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RoadClass {
+    Highway,
+    Road,
+    Track,
+}
+
+impl RoadClass {
+    pub fn name(&self) -> &str {
+        match self {
+            RoadClass::Highway => "highway",
+            RoadClass::Road => "road",
+            RoadClass::Track => "track",
+        }
+    }
+
+    // a route is as important as the smaller of the places it joins
+    fn between(a: SettlementKind, b: SettlementKind) -> Self {
+        match (a, b) {
+            (SettlementKind::City, SettlementKind::City) => RoadClass::Highway,
+            (SettlementKind::Village, _) | (_, SettlementKind::Village) => RoadClass::Track,
+            _ => RoadClass::Road,
+        }
+    }
+}
+
+/// A stretch of road between two junctions, settlements or dead ends.
+#[derive(Clone, Debug)]
+pub struct RoadEdge {
+    pub(crate) from: usize,
+    pub(crate) to: usize,
+    pub(crate) class: RoadClass,
+    pub(crate) lines: Vec<Line>,
+    /// Where the road crosses a river.
+    pub(crate) bridges: Vec<Vertex>,
+}
+
+/// Every route merged into one graph, with junctions wherever routes meet or split.
+#[derive(Clone, Debug, Default)]
+pub struct RoadNetwork {
+    pub(crate) nodes: Vec<Vertex>,
+    pub(crate) edges: Vec<RoadEdge>,
+}
+
+/// Connects every settlement to its nearest neighbours with A* over the terrain, shortest
+/// routes first. Later routes are drawn onto the roads already built, and where routes share
+/// cells they become one road in the network. Sea and lakes can't be crossed.
+pub fn build_roads(
+    settlements: &[Settlement],
+    mask: &Grid<bool>,
+    heightmap: Option<&Grid<f32>>,
+    water: &Grid<bool>,
+    rivers: &Grid<bool>,
+    settings: &RoadSettings,
+) -> RoadNetwork {
+    let width = mask.width;
+    let height = mask.height;
+    let heightmap = heightmap.filter(|h| h.width == width && h.height == height);
+
+    let cell_of = |v: Vertex| {
+        let (px, py) = utils::world_to_pixel(v, width, height);
+        mask.index((px as u32).min(width - 1), (py as u32).min(height - 1))
+    };
+    let cells: Vec<usize> = settlements.iter().map(|s| cell_of(s.position)).collect();
+
+    // each settlement with its nearest neighbours, and with its nearest neighbours at least
+    // as big as itself so that cities are joined to cities, shortest routes first
+    let mut pairs = Vec::new();
+    for a in 0..settlements.len() {
+        let mut nearest: Vec<(f32, usize)> = (0..settlements.len())
+            .filter(|b| *b != a)
+            .map(|b| (distance(settlements[a].position, settlements[b].position), b))
+            .collect();
+        nearest.sort_by(|x, y| x.0.total_cmp(&y.0));
+        let peers = nearest.iter().filter(|(_, b)| settlements[*b].kind <= settlements[a].kind);
+        for (d, b) in nearest.iter().take(settings.connections as usize).chain(peers.take(settings.connections as usize)) {
+            pairs.push((*d, a.min(*b), a.max(*b)));
+        }
+    }
+    pairs.sort_by(|x, y| x.0.total_cmp(&y.0).then((x.1, x.2).cmp(&(y.1, y.2))));
+    pairs.dedup_by(|x, y| x.1 == y.1 && x.2 == y.2);
+
+    let passable = |i: usize| mask.data[i] && !water.data[i];
+    let elevation = |i: usize| heightmap.map_or(0.0, |h| h.data[i].max(0.0));
+    let mut class: HashMap<usize, RoadClass> = HashMap::new();
+    let mut links: HashSet<(usize, usize)> = HashSet::new();
+    let mut search = geometry::PathSearch::new(mask.len());
+
+    for (_, a, b) in pairs {
+        let route_class = RoadClass::between(settlements[a].kind, settlements[b].kind);
+        let step_cost = |from: usize, to: usize| {
+            let mut cost = 1.0 + settings.slope_weight * (elevation(to) - elevation(from)).abs() / 100.0;
+            if class.contains_key(&to) {
+                cost *= settings.reuse_factor;
+            }
+            if rivers.data[to] && !rivers.data[from] {
+                cost += settings.bridge_cost;
+            }
+            cost
+        };
+        let heuristic_scale = settings.reuse_factor.min(1.0);
+        let Some(path) = search.a_star(mask, cells[a], cells[b], passable, step_cost, heuristic_scale) else {
+            continue;
+        };
+        for pair in path.windows(2) {
+            links.insert((pair[0].min(pair[1]), pair[0].max(pair[1])));
+        }
+        for cell in path {
+            let entry = class.entry(cell).or_insert(route_class);
+            *entry = (*entry).min(route_class);
+        }
+    }
+
+    let settlement_cells: HashSet<usize> = cells.into_iter().collect();
+    network_from_links(mask, &links, &class, &settlement_cells, rivers)
+}
+
+fn distance(a: Vertex, b: Vertex) -> f32 {
+    let dx = a.position[0] - b.position[0];
+    let dy = a.position[1] - b.position[1];
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// Turns the cell-to-cell links of every route into a graph whose nodes are settlements,
+/// junctions and dead ends, and whose edges are the roads between them.
+fn network_from_links(
+    grid: &Grid<bool>,
+    links: &HashSet<(usize, usize)>,
+    class: &HashMap<usize, RoadClass>,
+    settlement_cells: &HashSet<usize>,
+    rivers: &Grid<bool>,
+) -> RoadNetwork {
+    let mut neighbours: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (a, b) in links {
+        neighbours.entry(*a).or_default().push(*b);
+        neighbours.entry(*b).or_default().push(*a);
+    }
+    // sorted so the same links always give the same network
+    let mut cells: Vec<usize> = neighbours.keys().copied().collect();
+    cells.sort_unstable();
+    for list in neighbours.values_mut() {
+        list.sort_unstable();
+    }
+
+    let point = |i: usize| {
+        let (x, y) = grid.coords(i);
+        utils::pixel_to_world(x as f32 + 0.5, y as f32 + 0.5, grid.width, grid.height)
+    };
+    let mut network = RoadNetwork::default();
+    let mut node_of: HashMap<usize, usize> = HashMap::new();
+    for &cell in &cells {
+        if neighbours[&cell].len() != 2 || settlement_cells.contains(&cell) {
+            node_of.insert(cell, network.nodes.len());
+            network.nodes.push(point(cell));
+        }
+    }
+
+    let mut walked: HashSet<(usize, usize)> = HashSet::new();
+    for &start in &cells {
+        let Some(&from) = node_of.get(&start) else {
+            continue;
+        };
+        for &first in &neighbours[&start] {
+            if walked.contains(&(start.min(first), start.max(first))) {
+                continue;
+            }
+            let mut path = vec![start];
+            let mut previous = start;
+            let mut current = first;
+            loop {
+                walked.insert((previous.min(current), previous.max(current)));
+                path.push(current);
+                if node_of.contains_key(&current) {
+                    break;
+                }
+                // a cell that isn't a node has exactly two neighbours
+                let next = neighbours[&current].iter().copied().find(|n| *n != previous).unwrap_or(previous);
+                previous = current;
+                current = next;
+            }
+
+            // junctions carry the busiest road through them, so only the ends of a bare link count
+            let interior = &path[1..path.len() - 1];
+            let edge_class = if interior.is_empty() {
+                path.iter().filter_map(|c| class.get(c)).copied().max()
+            } else {
+                interior.iter().filter_map(|c| class.get(c)).copied().min()
+            };
+            let edge_class = edge_class.unwrap_or(RoadClass::Track);
+            let mut bridges = Vec::new();
+            for pair in path.windows(2) {
+                if rivers.data[pair[1]] && !rivers.data[pair[0]] {
+                    bridges.push(point(pair[1]));
+                }
+            }
+            network.edges.push(RoadEdge {
+                from,
+                to: node_of[&current],
+                class: edge_class,
+                lines: path.windows(2).map(|p| Line::new(point(p[0]), point(p[1]))).collect(),
+                bridges,
+            });
+        }
+    }
+    network
+}
+
+impl RoadNetwork {
+    /// The network as GeoJSON line strings in degrees of longitude and latitude.
+    pub fn to_geojson(&self, top_left: (f32, f32), bottom_right: (f32, f32)) -> String {
+        let mut json = String::from("{\"type\":\"FeatureCollection\",\"features\":[");
+        for (i, edge) in self.edges.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let mut points = vec![edge.lines[0].start];
+            points.extend(edge.lines.iter().map(|l| l.end));
+            let coordinates: Vec<String> = points
+                .iter()
+                .map(|p| {
+                    let (lon, lat) = utils::world_to_lon_lat(*p, top_left, bottom_right);
+                    format!("[{:.5},{:.5}]", lon, lat)
+                })
+                .collect();
+            let _ = write!(
+                json,
+                "{{\"type\":\"Feature\",\"properties\":{{\"class\":\"{}\",\"from\":{},\"to\":{},\"bridges\":{}}},\"geometry\":{{\"type\":\"LineString\",\"coordinates\":[{}]}}}}",
+                edge.class.name(),
+                edge.from,
+                edge.to,
+                edge.bridges.len(),
+                coordinates.join(",")
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+}
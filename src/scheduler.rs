@@ -0,0 +1,244 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{constants, geometry::Line, grid::Grid};
+
+/// Something that advances in discrete ticks on the simulation thread.
+pub trait Simulation: Send {
+    fn name(&self) -> &str;
+    /// Advances the simulation by one tick.
+    fn tick(&mut self);
+    fn is_finished(&self) -> bool;
+    /// Fraction of the work done, for simulations that have an end.
+    fn progress(&self) -> Option<f32>;
+    /// The state worth drawing right now.
+    fn frame(&self) -> SimulationFrame;
+    /// Work that runs as fast as it can whether the simulations are playing or not,
+    /// rather than a simulation to watch.
+    fn is_background(&self) -> bool {
+        false
+    }
+}
+
+/// Drawable state of a simulation, cheap to clone so the gui can grab it every frame.
+#[derive(Clone, Default)]
+pub struct SimulationFrame {
+    pub(crate) lines: Arc<Vec<Line>>,
+    pub(crate) heightmap: Option<Arc<Grid<f32>>>,
+}
+
+#[derive(Clone)]
+pub struct SimulationStatus {
+    pub(crate) name: String,
+    pub(crate) ticks: u64,
+    pub(crate) progress: Option<f32>,
+    pub(crate) finished: bool,
+    pub(crate) frame: SimulationFrame,
+    /// The generation of the world it was started from, for simulations tied to one.
+    pub(crate) generation: Option<u64>,
+}
+
+struct Entry {
+    simulation: Box<dyn Simulation>,
+    ticks: u64,
+    generation: Option<u64>,
+    /// Taken after the last tick, so that statuses can be built without asking again.
+    frame: SimulationFrame,
+}
+
+struct Shared {
+    entries: Vec<Entry>,
+    statuses: Vec<SimulationStatus>,
+    /// Bumped whenever `statuses` changes.
+    version: u64,
+    playing: bool,
+    steps_requested: u32,
+    ticks_per_second: f32,
+    clear_requested: bool,
+    /// Simulations tied to any other generation of the world are dropped.
+    generation: Option<u64>,
+    quit: bool,
+}
+
+/// Runs registered simulations on their own thread, with play, pause, single-step
+/// and speed controls for the gui.
+pub struct Scheduler {
+    shared: Arc<Mutex<Shared>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        let shared = Arc::new(Mutex::new(Shared {
+            entries: Vec::new(),
+            statuses: Vec::new(),
+            version: 0,
+            playing: false,
+            steps_requested: 0,
+            ticks_per_second: 30.0,
+            clear_requested: false,
+            generation: None,
+            quit: false,
+        }));
+        let worker_shared = Arc::clone(&shared);
+        let worker = thread::spawn(move || run_worker(worker_shared));
+        Scheduler {
+            shared,
+            worker: Some(worker),
+        }
+    }
+
+    /// Adds a simulation, tied to a generation of the world if it works on one, so that
+    /// `cancel_stale` can drop it once the world has moved on.
+    pub fn register(&self, simulation: Box<dyn Simulation>, generation: Option<u64>) {
+        let frame = simulation.frame();
+        let mut shared = self.shared.lock().unwrap();
+        shared.entries.push(Entry { simulation, ticks: 0, generation, frame });
+        update_statuses(&mut shared);
+    }
+
+    /// Drops the simulations tied to a generation of the world other than `generation`,
+    /// including any that are ticking right now.
+    pub fn cancel_stale(&self, generation: u64) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.generation = Some(generation);
+        retain_current(&mut shared.entries, Some(generation));
+        update_statuses(&mut shared);
+    }
+
+    pub fn play(&self) {
+        self.shared.lock().unwrap().playing = true;
+    }
+
+    pub fn pause(&self) {
+        self.shared.lock().unwrap().playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.shared.lock().unwrap().playing
+    }
+
+    /// Advances every simulation by a single tick while paused.
+    pub fn step(&self) {
+        self.shared.lock().unwrap().steps_requested += 1;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.shared.lock().unwrap().ticks_per_second
+    }
+
+    pub fn set_speed(&self, ticks_per_second: f32) {
+        self.shared.lock().unwrap().ticks_per_second = ticks_per_second.max(0.1);
+    }
+
+    /// Drops finished simulations.
+    pub fn clear_finished(&self) {
+        self.shared.lock().unwrap().clear_requested = true;
+    }
+
+    /// Latest status of every simulation and a version that changes whenever they do.
+    pub fn statuses(&self) -> (u64, Vec<SimulationStatus>) {
+        let shared = self.shared.lock().unwrap();
+        (shared.version, shared.statuses.clone())
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().quit = true;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn update_statuses(shared: &mut Shared) {
+    shared.statuses = shared
+        .entries
+        .iter()
+        .map(|e| SimulationStatus {
+            name: e.simulation.name().to_string(),
+            ticks: e.ticks,
+            progress: e.simulation.progress(),
+            finished: e.simulation.is_finished(),
+            frame: e.frame.clone(),
+            generation: e.generation,
+        })
+        .collect();
+    shared.version += 1;
+}
+
+fn retain_current(entries: &mut Vec<Entry>, generation: Option<u64>) {
+    entries.retain(|e| e.generation.is_none() || e.generation == generation);
+}
+
+fn run_worker(shared: Arc<Mutex<Shared>>) {
+    let mut last_tick = Instant::now();
+    loop {
+        // take the simulations out so the gui isn't blocked while they tick
+        let (mut entries, all, interval) = {
+            let mut s = shared.lock().unwrap();
+            if s.quit {
+                return;
+            }
+            if s.clear_requested {
+                s.clear_requested = false;
+                s.entries.retain(|e| !e.simulation.is_finished());
+                update_statuses(&mut s);
+            }
+
+            let interval = Duration::from_secs_f32(1.0 / s.ticks_per_second);
+            let due = s.playing && last_tick.elapsed() >= interval;
+            let background = s.entries.iter().any(|e| e.simulation.is_background() && !e.simulation.is_finished());
+            if !due && s.steps_requested == 0 && !background {
+                drop(s);
+                thread::sleep(Duration::from_millis(constants::SCHEDULER_IDLE_MS));
+                continue;
+            }
+            let stepping = !due && s.steps_requested > 0;
+            if stepping {
+                s.steps_requested -= 1;
+            }
+            (std::mem::take(&mut s.entries), due || stepping, interval)
+        };
+
+        // background work doesn't count towards the simulations' pace
+        let started = Instant::now();
+        if all {
+            last_tick = started;
+        }
+        // frames can be big, so are only taken of what moved and before locking
+        for entry in &mut entries {
+            if !entry.simulation.is_finished() && (all || entry.simulation.is_background()) {
+                entry.simulation.tick();
+                entry.ticks += 1;
+                entry.frame = entry.simulation.frame();
+            }
+        }
+
+        let mut s = shared.lock().unwrap();
+        // anything registered while ticking goes after the existing simulations
+        entries.append(&mut s.entries);
+        retain_current(&mut entries, s.generation);
+        s.entries = entries;
+        update_statuses(&mut s);
+        if s.entries.iter().all(|e| e.simulation.is_finished()) {
+            s.playing = false;
+        }
+        drop(s);
+
+        let spent = started.elapsed();
+        if all && spent < interval {
+            thread::sleep((interval - spent).min(Duration::from_millis(constants::SCHEDULER_IDLE_MS)));
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// Seed for one consumer of randomness, derived from the world seed and the consumer's
+/// stream name. Uses FNV-1a and splitmix64 rather than std's hasher, whose output
+/// may change between compiler versions.
+pub fn derive(world_seed: u64, stream: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in stream.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    splitmix64(world_seed ^ splitmix64(hash))
+}
+
+/// Random number generator for one stream of the world seed.
+pub fn rng(world_seed: u64, stream: &str) -> ChaCha8Rng {
+    from_seed(derive(world_seed, stream))
+}
+
+/// Random number generator for an already derived seed. ChaCha8 is used rather than
+/// rand's `StdRng`, whose algorithm may change between rand versions and platforms.
+pub fn from_seed(seed: u64) -> ChaCha8Rng {
+    ChaCha8Rng::seed_from_u64(seed)
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{grid::Grid, names, tectonics, terrain, worldgen};
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use rand::RngCore;
+
+    const SEED: u64 = 0x5eed;
+
+    #[derive(Debug, PartialEq)]
+    struct Generated {
+        land: Vec<bool>,
+        heightmap: Vec<f32>,
+        plate_id: Vec<u16>,
+        uplift: Vec<f32>,
+        names: Vec<String>,
+    }
+
+    /// Runs every seeded generator once for the same image and seed.
+    fn generate() -> Generated {
+        let image = RgbaImage::from_fn(64, 32, |x, y| if (x as i32 - 24).pow(2) + (y as i32 - 16).pow(2) < 120 { Rgba([0, 0, 0, 255]) } else { Rgba([0, 0, 0, 0]) });
+        let mask = Grid::from_alpha(&DynamicImage::ImageRgba8(image));
+
+        let land = worldgen::generate_land_mask(&worldgen::WorldGenSettings { width: 64, height: 32, ..Default::default() }, SEED);
+        let plates = tectonics::simulate_plates(64, 32, &Default::default(), SEED);
+        let heightmap = terrain::generate_heightmap(&mask, &Default::default(), &[], Some(&plates.uplift), SEED);
+        let names = names::generate_names(&names::default_cultures()[0], 10, SEED, "towns");
+        Generated {
+            land: land.data,
+            heightmap: heightmap.data,
+            plate_id: plates.plate_id.data,
+            uplift: plates.uplift.data,
+            names,
+        }
+    }
+
+    #[test]
+    fn generators_are_deterministic() {
+        assert_eq!(generate(), generate());
+    }
+
+    #[test]
+    fn streams_are_stable() {
+        // pinned so that a change of generator or seed derivation doesn't go unnoticed
+        assert_eq!(derive(SEED, "worldgen"), 11448451704028706464);
+        assert_eq!(rng(SEED, "worldgen").next_u64(), 1396924369339710098);
+    }
+}
@@ -0,0 +1,202 @@
+use rand::Rng;
+
+use crate::{
+    climate::{Climate, ClimateView},
+    constants,
+    geometry::Vertex,
+    grid::Grid,
+    seed, terrain, utils,
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct SettlementSettings {
+    pub(crate) cities: u32,
+    pub(crate) towns: u32,
+    pub(crate) villages: u32,
+    /// Closest, in cells, that a new city, town or village may be to any other settlement.
+    pub(crate) city_spacing: f32,
+    pub(crate) town_spacing: f32,
+    pub(crate) village_spacing: f32,
+    /// How much each part of the habitability score counts.
+    pub(crate) water_weight: f32,
+    pub(crate) coast_weight: f32,
+    pub(crate) temperature_weight: f32,
+    pub(crate) fertility_weight: f32,
+    pub(crate) elevation_weight: f32,
+}
+
+impl Default for SettlementSettings {
+    fn default() -> Self {
+        SettlementSettings {
+            cities: 8,
+            towns: 30,
+            villages: 120,
+            city_spacing: 60.0,
+            town_spacing: 25.0,
+            village_spacing: 10.0,
+            water_weight: 3.0,
+            coast_weight: 2.0,
+            temperature_weight: 2.0,
+            fertility_weight: 2.0,
+            elevation_weight: 1.0,
+        }
+    }
+}
+
+/// Ordered from the largest kind of settlement to the smallest.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SettlementKind {
+    City,
+    Town,
+    Village,
+}
+
+impl SettlementKind {
+    pub fn name(&self) -> &str {
+        match self {
+            SettlementKind::City => "City",
+            SettlementKind::Town => "Town",
+            SettlementKind::Village => "Village",
+        }
+    }
+
+    /// Population of a settlement of this kind on the best possible site.
+    fn base_population(&self) -> f32 {
+        match self {
+            SettlementKind::City => 100_000.0,
+            SettlementKind::Town => 10_000.0,
+            SettlementKind::Village => 1_000.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Settlement {
+    pub(crate) name: String,
+    pub(crate) kind: SettlementKind,
+    pub(crate) population: u32,
+    pub(crate) position: Vertex,
+    /// Habitability of the site, `0.0..=1.0`.
+    pub(crate) score: f32,
+}
+
+/// Habitability of every land cell from `0.0` to `1.0`, zero for water.
+/// Temperature and rainfall only count once a climate has been simulated.
+pub fn habitability(
+    mask: &Grid<bool>,
+    heightmap: Option<&Grid<f32>>,
+    fresh_water: &Grid<bool>,
+    climate: Option<&Climate>,
+    settings: &SettlementSettings,
+) -> Grid<f32> {
+    let width = mask.width;
+    let height = mask.height;
+    let heightmap = heightmap.filter(|h| h.width == width && h.height == height);
+    let climate = climate.filter(|c| c.width == width && c.height == height);
+
+    let coast = terrain::distance_to_coast(mask);
+    let water = terrain::distance_to_coast(fresh_water);
+    let temperature = climate.map(|c| c.annual_mean(ClimateView::Temperature));
+    let precipitation = climate.map(|c| c.annual_mean(ClimateView::Precipitation));
+
+    let total_weight = settings.water_weight
+        + settings.coast_weight
+        + settings.temperature_weight
+        + settings.fertility_weight
+        + settings.elevation_weight;
+    let mut score = Grid::new(width, height, 0.0);
+    if total_weight <= 0.0 {
+        return score;
+    }
+
+    for y in 0..height {
+        for x in 0..width {
+            let i = mask.index(x, y);
+            if !mask.data[i] || fresh_water.data[i] {
+                continue;
+            }
+
+            let water_score = (-water.data[i] / 5.0).exp();
+            let coast_score = (-coast.data[i] / 10.0).exp();
+            let temperature_score = match &temperature {
+                Some(t) => (-((t.data[i] - constants::IDEAL_TEMPERATURE) / constants::TEMPERATURE_TOLERANCE).powi(2)).exp(),
+                None => 1.0,
+            };
+
+            // flat, well watered ground grows the most food
+            let mut fertility_score = match &precipitation {
+                Some(p) => (p.data[i] / constants::IDEAL_PRECIPITATION).min(1.0),
+                None => 1.0,
+            };
+            let mut elevation_score = 1.0;
+            if let Some(heightmap) = heightmap {
+                let h = heightmap.data[i];
+                let mut slope: f32 = 0.0;
+                for (dx, dy) in constants::NEIGHBOURS_4 {
+                    let nx = x as i32 + dx;
+                    let ny = y as i32 + dy;
+                    if heightmap.in_bounds(nx, ny) {
+                        slope = slope.max((heightmap.get(nx as u32, ny as u32) - h).abs());
+                    }
+                }
+                fertility_score *= (-slope / 50.0).exp();
+                elevation_score = 1.0 - (h / 2000.0).clamp(0.0, 1.0);
+            }
+
+            let value = settings.water_weight * water_score
+                + settings.coast_weight * coast_score
+                + settings.temperature_weight * temperature_score
+                + settings.fertility_weight * fertility_score
+                + settings.elevation_weight * elevation_score;
+            score.data[i] = value / total_weight;
+        }
+    }
+    score
+}
+
+/// Places cities, then towns, then villages on the most habitable free sites, keeping each
+/// new settlement its kind's spacing away from every one placed before it.
+pub fn place_settlements(score: &Grid<f32>, settings: &SettlementSettings, world_seed: u64) -> Vec<Settlement> {
+    let mut rng = seed::rng(world_seed, "settlements");
+    let mut candidates: Vec<usize> = (0..score.len()).filter(|i| score.data[*i] > 0.0).collect();
+    // a little noise so that flat, equally good land doesn't fill up in scanline order
+    let jitter: Vec<f32> = (0..score.len()).map(|_| rng.gen_range(0.0..0.05)).collect();
+    candidates.sort_by(|a, b| (score.data[*b] + jitter[*b]).total_cmp(&(score.data[*a] + jitter[*a])));
+
+    let tiers = [
+        (SettlementKind::City, settings.cities, settings.city_spacing),
+        (SettlementKind::Town, settings.towns, settings.town_spacing),
+        (SettlementKind::Village, settings.villages, settings.village_spacing),
+    ];
+    let mut placed: Vec<(u32, u32)> = Vec::new();
+    let mut settlements = Vec::new();
+    for (kind, count, spacing) in tiers {
+        let mut remaining = count;
+        for &i in &candidates {
+            if remaining == 0 {
+                break;
+            }
+            let (x, y) = score.coords(i);
+            let too_close = placed.iter().any(|(px, py)| {
+                let dx = *px as f32 - x as f32;
+                let dy = *py as f32 - y as f32;
+                dx * dx + dy * dy < spacing * spacing
+            });
+            if too_close {
+                continue;
+            }
+
+            placed.push((x, y));
+            remaining -= 1;
+            let population = kind.base_population() * score.data[i].powi(2) * rng.gen_range(0.6..1.4);
+            settlements.push(Settlement {
+                name: format!("{} {}", kind.name(), count - remaining),
+                kind,
+                population: population.max(50.0) as u32,
+                position: utils::pixel_to_world(x as f32 + 0.5, y as f32 + 0.5, score.width, score.height),
+                score: score.data[i],
+            });
+        }
+    }
+    settlements
+}
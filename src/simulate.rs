@@ -1,39 +1,114 @@
-use crate::geometry::{Line, Vertex};
-
-pub fn simulate_ocean_flows(horiz: Line, collision: Vec<Line>) -> Vec<Line> {
-    let mut flow: Vec<Line> = Vec::new();
-    let steps = 1000;
-    let distance = 0.01;
-
-    let mut fl = horiz.clone();
-
-    let points :Vec<Vertex> = Vec::new();
-    for i in 0..steps {
-        let mut intersect = None;
-        for line in &collision {
-            if line.get_intersection(fl).is_some() {
-                intersect = Some((line,line.get_intersection(fl).unwrap()));
-                break;
-            }
+use std::sync::Arc;
+
+use crate::{
+    geometry::{Line, Vertex},
+    scheduler::{Simulation, SimulationFrame},
+};
+
+struct Current {
+    head: Vertex,
+    angle: f32,
+    done: bool,
+}
+
+/// Ocean currents that start at the western edge of the world and flow east,
+/// turning away from coastlines they run into. Each tick moves every current one step.
+pub struct OceanFlow {
+    currents: Vec<Current>,
+    collision: Vec<Line>,
+    flow: Vec<Line>,
+    steps: u32,
+    step: u32,
+    distance: f32,
+    top_left: (f32, f32),
+    bottom_right: (f32, f32),
+}
+
+impl OceanFlow {
+    pub fn new(count: u32, steps: u32, collision: Vec<Line>, top_left: (f32, f32), bottom_right: (f32, f32)) -> Self {
+        let mut currents = Vec::new();
+        let height = top_left.1 - bottom_right.1;
+        for i in 0..count {
+            let y = top_left.1 - height * (i as f32 + 0.5) / count as f32;
+            let fill = [top_left.0, y];
+            currents.push(Current {
+                head: Vertex { position: fill, tex_coords: fill },
+                angle: 0.0,
+                done: false,
+            });
+        }
+        OceanFlow {
+            currents,
+            collision,
+            flow: Vec::new(),
+            steps,
+            step: 0,
+            distance: 0.01,
+            top_left,
+            bottom_right,
         }
-        if intersect.is_some() {
-            let intersect = intersect.unwrap();
-            let line = intersect.0;
-            let point = intersect.1;
-
-            let angle_of_line = line.start.get_angle_to(&line.end);
-            let my_angle = fl.start.get_angle_to(&fl.end);
-
-            if my_angle < angle_of_line {
-                fl.end.rotate_around(&fl.start, distance);
-            } else if my_angle > angle_of_line {
-                fl.end.rotate_around(&fl.start, -distance);
+    }
+
+    fn in_bounds(&self, v: Vertex) -> bool {
+        let [x, y] = v.position;
+        x >= self.top_left.0 && x <= self.bottom_right.0 && y <= self.top_left.1 && y >= self.bottom_right.1
+    }
+}
+
+impl Simulation for OceanFlow {
+    fn name(&self) -> &str {
+        "Ocean Currents"
+    }
+
+    fn tick(&mut self) {
+        for i in 0..self.currents.len() {
+            let current = &self.currents[i];
+            if current.done {
+                continue;
+            }
+            let fl = Line::new_from_rise_run(current.head, current.angle.sin() * self.distance, current.angle.cos() * self.distance);
+
+            let mut blocking = None;
+            for line in &self.collision {
+                if let Some(point) = line.get_intersection(fl) {
+                    if fl.is_point_on_line(point.as_vector()) {
+                        blocking = Some(*line);
+                        break;
+                    }
+                }
+            }
+
+            let left_world = !self.in_bounds(fl.end);
+            let current = &mut self.currents[i];
+            if let Some(line) = blocking {
+                // turn along the coast and try again next tick
+                let angle_of_line = line.start.get_angle_to(&line.end);
+                if current.angle < angle_of_line {
+                    current.angle += self.distance * 10.0;
+                } else {
+                    current.angle -= self.distance * 10.0;
+                }
+            } else {
+                self.flow.push(fl);
+                current.head = fl.end;
+                current.done = left_world;
             }
-            
         }
-    } 
+        self.step += 1;
+    }
 
+    fn is_finished(&self) -> bool {
+        self.step >= self.steps || self.currents.iter().all(|c| c.done)
+    }
 
+    fn progress(&self) -> Option<f32> {
+        Some((self.step as f32 / self.steps.max(1) as f32).min(1.0))
+    }
 
-    return flow;
-}
\ No newline at end of file
+    fn frame(&self) -> SimulationFrame {
+        SimulationFrame {
+            lines: Arc::new(self.flow.clone()),
+            heightmap: None,
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::VecDeque;
+
+use rand::Rng;
+
+use crate::{
+    constants,
+    geometry::Line,
+    grid::Grid,
+    noise::Noise,
+    seed,
+    utils,
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct TectonicSettings {
+    pub(crate) plates: u32,
+    /// Share of the plates that carry continental crust.
+    pub(crate) continental_fraction: f32,
+    /// Height in metres of the tallest range a boundary can raise.
+    pub(crate) uplift: f32,
+    /// Size of the world when plates are used to make a land mask from scratch.
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+impl Default for TectonicSettings {
+    fn default() -> Self {
+        TectonicSettings {
+            plates: 12,
+            continental_fraction: 0.4,
+            uplift: 4000.0,
+            width: 1024,
+            height: 512,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Plate {
+    pub(crate) centre: (f32, f32),
+    /// Direction and speed of the plate, in cells per step.
+    pub(crate) motion: (f32, f32),
+    pub(crate) continental: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BoundaryKind {
+    /// Two continents pushing together, raising mountain ranges.
+    Collision,
+    /// Ocean sliding under a continent, a trench next to a coastal range.
+    Subduction,
+    /// Ocean sliding under ocean, a chain of volcanic islands.
+    IslandArc,
+    /// Plates pulling apart, rift valleys on land and ridges under the sea.
+    Rift,
+    /// Plates sliding past each other.
+    Transform,
+}
+
+impl BoundaryKind {
+    pub fn color(&self) -> [f32; 4] {
+        match self {
+            BoundaryKind::Collision => [0.6, 0.3, 0.1, 1.0],
+            BoundaryKind::Subduction => [0.9, 0.5, 0.0, 1.0],
+            BoundaryKind::IslandArc => [0.9, 0.9, 0.0, 1.0],
+            BoundaryKind::Rift => [0.8, 0.0, 0.8, 1.0],
+            BoundaryKind::Transform => [0.5, 0.5, 0.5, 1.0],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Boundary {
+    pub(crate) kind: BoundaryKind,
+    pub(crate) line: Line,
+}
+
+pub struct Tectonics {
+    pub(crate) plates: Vec<Plate>,
+    pub(crate) plate_id: Grid<u16>,
+    /// Elevation change caused by the plate boundaries, in metres.
+    pub(crate) uplift: Grid<f32>,
+    pub(crate) boundaries: Vec<Boundary>,
+    world_seed: u64,
+}
+
+/// Partitions a `width` by `height` world into drifting plates and works out what
+/// happens where they meet.
+pub fn simulate_plates(width: u32, height: u32, settings: &TectonicSettings, world_seed: u64) -> Tectonics {
+    let mut rng = seed::rng(world_seed, "tectonics");
+    let mut plates = Vec::new();
+    for i in 0..settings.plates.max(2) {
+        let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+        let speed: f32 = rng.gen_range(0.2..1.0);
+        // make sure there is at least one continent and one ocean
+        let continental = match i {
+            0 => true,
+            1 => false,
+            _ => rng.gen_range(0.0..1.0) < settings.continental_fraction,
+        };
+        plates.push(Plate {
+            centre: (rng.gen_range(0.0..width as f32), rng.gen_range(0.0..height as f32)),
+            motion: (angle.cos() * speed, angle.sin() * speed),
+            continental,
+        });
+    }
+
+    let plate_id = assign_plates(width, height, &plates, world_seed);
+
+    // every cell on a boundary gets the uplift of the strongest interaction it is part of
+    let mut stress: Grid<f32> = Grid::new(width, height, 0.0);
+    let mut boundary_cell = vec![false; plate_id.len()];
+    let mut boundaries = Vec::new();
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let a = plate_id.get(x as u32, y as u32) as usize;
+            for (dx, dy) in constants::NEIGHBOURS_4 {
+                if !plate_id.in_bounds(x + dx, y + dy) {
+                    continue;
+                }
+                let b = plate_id.get((x + dx) as u32, (y + dy) as u32) as usize;
+                if a == b {
+                    continue;
+                }
+
+                let (kind, value) = interact(&plates, a, b, (dx as f32, dy as f32));
+                let i = plate_id.index(x as u32, y as u32);
+                boundary_cell[i] = true;
+                if value.abs() > stress.data[i].abs() {
+                    stress.data[i] = value;
+                }
+
+                // each shared edge is seen from both sides, only record it once
+                if a < b {
+                    boundaries.push(Boundary { kind, line: shared_edge(x, y, dx, dy, width, height) });
+                }
+            }
+        }
+    }
+
+    let uplift = spread_stress(&plate_id, &stress, &boundary_cell, settings.uplift);
+
+    Tectonics { plates, plate_id, uplift, boundaries, world_seed }
+}
+
+// nearest plate centre, with the distances warped by noise so the boundaries meander
+fn assign_plates(width: u32, height: u32, plates: &[Plate], world_seed: u64) -> Grid<u16> {
+    let noise = Noise::new(seed::derive(world_seed, "tectonics-warp"));
+    let size = width.max(height) as f32;
+    let frequency = constants::PLATE_WARP_FREQUENCY / size;
+    let warp = constants::PLATE_WARP * size;
+
+    let mut plate_id = Grid::new(width, height, 0);
+    for y in 0..height {
+        for x in 0..width {
+            let fx = x as f32 * frequency;
+            let fy = y as f32 * frequency;
+            let wx = x as f32 + noise.fbm(fx, fy, 4, 2.0, 0.5) * warp - warp / 2.0;
+            let wy = y as f32 + noise.fbm(fx + 31.7, fy + 47.3, 4, 2.0, 0.5) * warp - warp / 2.0;
+
+            let mut best = 0;
+            let mut best_dist = f32::MAX;
+            for (i, plate) in plates.iter().enumerate() {
+                let d = (plate.centre.0 - wx).powi(2) + (plate.centre.1 - wy).powi(2);
+                if d < best_dist {
+                    best_dist = d;
+                    best = i;
+                }
+            }
+            plate_id.set(x, y, best as u16);
+        }
+    }
+    plate_id
+}
+
+// what plate `a` experiences where it meets plate `b`, `normal` points from a to b.
+// The value is the uplift on a's side as a fraction of the maximum.
+fn interact(plates: &[Plate], a: usize, b: usize, normal: (f32, f32)) -> (BoundaryKind, f32) {
+    let pa = &plates[a];
+    let pb = &plates[b];
+    let relative = (pa.motion.0 - pb.motion.0, pa.motion.1 - pb.motion.1);
+    // positive when the plates move towards each other, at most 2
+    let convergence = relative.0 * normal.0 + relative.1 * normal.1;
+    let strength = (convergence.abs() / 2.0).min(1.0);
+
+    if convergence.abs() < constants::TRANSFORM_THRESHOLD {
+        return (BoundaryKind::Transform, 0.0);
+    }
+
+    if convergence < 0.0 {
+        let value = if pa.continental { -0.3 } else { 0.3 };
+        return (BoundaryKind::Rift, value * strength);
+    }
+
+    match (pa.continental, pb.continental) {
+        (true, true) => (BoundaryKind::Collision, strength),
+        (true, false) => (BoundaryKind::Subduction, 0.7 * strength),
+        (false, true) => (BoundaryKind::Subduction, -0.5 * strength),
+        (false, false) => {
+            // the lower numbered plate rides over the other one
+            let value = if a < b { 0.9 } else { -0.4 };
+            (BoundaryKind::IslandArc, value * strength)
+        }
+    }
+}
+
+fn shared_edge(x: i32, y: i32, dx: i32, dy: i32, width: u32, height: u32) -> Line {
+    let (a, b) = match (dx, dy) {
+        (-1, 0) => ((x, y), (x, y + 1)),
+        (1, 0) => ((x + 1, y), (x + 1, y + 1)),
+        (0, -1) => ((x, y), (x + 1, y)),
+        _ => ((x, y + 1), (x + 1, y + 1)),
+    };
+    Line::new(
+        utils::pixel_to_world(a.0 as f32, a.1 as f32, width, height),
+        utils::pixel_to_world(b.0 as f32, b.1 as f32, width, height),
+    )
+}
+
+// carries the boundary stress into each plate, fading with the distance from the boundary
+fn spread_stress(plate_id: &Grid<u16>, stress: &Grid<f32>, boundary_cell: &[bool], uplift: f32) -> Grid<f32> {
+    let width = constants::BOUNDARY_WIDTH * plate_id.width.max(plate_id.height) as f32;
+    let mut source = vec![usize::MAX; plate_id.len()];
+    let mut distance = vec![0u32; plate_id.len()];
+    let mut queue = VecDeque::new();
+    for (i, is_boundary) in boundary_cell.iter().enumerate() {
+        if *is_boundary {
+            source[i] = i;
+            queue.push_back(i);
+        }
+    }
+
+    while let Some(i) = queue.pop_front() {
+        let (x, y) = plate_id.coords(i);
+        for (dx, dy) in constants::NEIGHBOURS_4 {
+            let nx = x as i32 + dx;
+            let ny = y as i32 + dy;
+            if !plate_id.in_bounds(nx, ny) {
+                continue;
+            }
+            let n = plate_id.index(nx as u32, ny as u32);
+            if source[n] != usize::MAX || plate_id.data[n] != plate_id.data[i] {
+                continue;
+            }
+            source[n] = source[i];
+            distance[n] = distance[i] + 1;
+            if (distance[n] as f32) < width * 3.0 {
+                queue.push_back(n);
+            }
+        }
+    }
+
+    let mut result = Grid::new(plate_id.width, plate_id.height, 0.0);
+    for i in 0..result.len() {
+        if source[i] == usize::MAX {
+            continue;
+        }
+        let falloff = (-(distance[i] as f32 / width).powi(2)).exp();
+        result.data[i] = stress.data[source[i]] * falloff * uplift;
+    }
+    result
+}
+
+impl Tectonics {
+    /// Elevation in metres made from the plates alone: continental crust sits above the sea,
+    /// oceanic crust below, with the boundary uplift and some noise on top.
+    pub fn elevation(&self) -> Grid<f32> {
+        let noise = Noise::new(seed::derive(self.world_seed, "tectonics-crust"));
+        let width = self.plate_id.width;
+        let height = self.plate_id.height;
+        let frequency = constants::NOISE_FREQUENCY / width.max(height) as f32;
+
+        let mut elevation = Grid::new(width, height, 0.0);
+        for y in 0..height {
+            for x in 0..width {
+                let i = elevation.index(x, y);
+                let plate = &self.plates[self.plate_id.data[i] as usize];
+                let base = if plate.continental {
+                    constants::CONTINENTAL_CRUST
+                } else {
+                    constants::OCEANIC_CRUST
+                };
+                let n = noise.fbm(x as f32 * frequency, y as f32 * frequency, 6, 2.0, 0.5) * 2.0 - 1.0;
+                elevation.data[i] = base + self.uplift.data[i] + n * constants::CRUST_NOISE;
+            }
+        }
+        elevation
+    }
+
+    /// Land wherever the plate elevation is above sea level.
+    pub fn land_mask(&self) -> Grid<bool> {
+        let elevation = self.elevation();
+        let mut mask = Grid::new(elevation.width, elevation.height, false);
+        for i in 0..mask.len() {
+            mask.data[i] = elevation.data[i] > 0.0;
+        }
+        mask
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use crate::{
+    constants,
+    geometry::{Line, Vertex},
+    grid::Grid,
+    lakes, navigation,
+    noise::Noise,
+    seed,
+    utils,
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct HeightmapSettings {
+    pub(crate) max_elevation: f32,
+    pub(crate) max_depth: f32,
+    pub(crate) roughness: f32,
+}
+
+impl Default for HeightmapSettings {
+    fn default() -> Self {
+        HeightmapSettings {
+            max_elevation: 4000.0,
+            max_depth: 5000.0,
+            roughness: 0.5,
+        }
+    }
+}
+
+/// Builds an elevation raster (metres, 0 is sea level) for the land mask.
+/// Land rises with the distance to the coast and the sea floor sinks the same way,
+/// both modulated by fractal noise. Ridge lines are given in world coordinates.
+/// `uplift` (metres, e.g. from plate tectonics) is added on top when it matches the mask's size.
+pub fn generate_heightmap(mask: &Grid<bool>, settings: &HeightmapSettings, ridges: &[Line], uplift: Option<&Grid<f32>>, world_seed: u64) -> Grid<f32> {
+    let distance = distance_to_coast(mask);
+    let noise = Noise::new(seed::derive(world_seed, "heightmap"));
+
+    let mut land_max: f32 = 1.0;
+    let mut sea_max: f32 = 1.0;
+    for i in 0..mask.len() {
+        if mask.data[i] {
+            land_max = land_max.max(distance.data[i]);
+        } else {
+            sea_max = sea_max.max(distance.data[i]);
+        }
+    }
+
+    let width = mask.width;
+    let height = mask.height;
+    let frequency = constants::NOISE_FREQUENCY / width.max(height) as f32;
+    let ridge_width = constants::RIDGE_WIDTH;
+    let uplift = uplift.filter(|u| u.width == width && u.height == height);
+
+    let mut heightmap = Grid::new(width, height, 0.0);
+    for y in 0..height {
+        for x in 0..width {
+            let i = mask.index(x, y);
+            let n = noise.fbm(x as f32 * frequency, y as f32 * frequency, 6, 2.0, 0.5);
+            let variation = 1.0 + (2.0 * n - 1.0) * settings.roughness;
+
+            if mask.data[i] {
+                let t = (distance.data[i] / land_max).sqrt();
+                let mut h = settings.max_elevation * t * variation;
+
+                if !ridges.is_empty() {
+                    let p = utils::pixel_to_world(x as f32, y as f32, width, height);
+                    let d = ridges
+                        .iter()
+                        .map(|r| distance_to_segment(p, r))
+                        .fold(f32::MAX, f32::min);
+                    let influence = (-(d / ridge_width).powi(2)).exp();
+                    h += settings.max_elevation * influence * (0.5 + 0.5 * n);
+                }
+
+                if let Some(uplift) = uplift {
+                    h += uplift.data[i];
+                }
+
+                heightmap.data[i] = h.clamp(constants::MIN_LAND_ELEVATION, settings.max_elevation);
+            } else {
+                let t = (distance.data[i] / sea_max).sqrt();
+                let mut d = settings.max_depth * t * variation;
+                if let Some(uplift) = uplift {
+                    d -= uplift.data[i];
+                }
+                heightmap.data[i] = -d.clamp(constants::MIN_LAND_ELEVATION, settings.max_depth);
+            }
+        }
+    }
+
+    heightmap
+}
+
+/// Chamfer distance (in pixels) from every cell to the nearest cell on the other side of the coast.
+pub fn distance_to_coast(mask: &Grid<bool>) -> Grid<f32> {
+    let width = mask.width as i32;
+    let height = mask.height as i32;
+    let mut dist = Grid::new(mask.width, mask.height, f32::MAX);
+
+    for y in 0..height {
+        for x in 0..width {
+            let own = mask.get(x as u32, y as u32);
+            for (dx, dy) in constants::NEIGHBOURS_4 {
+                let nx = x + dx;
+                let ny = y + dy;
+                if mask.in_bounds(nx, ny) && mask.get(nx as u32, ny as u32) != own {
+                    dist.set(x as u32, y as u32, 1.0);
+                    break;
+                }
+            }
+        }
+    }
+
+    let diagonal = std::f32::consts::SQRT_2;
+    let forward = [(-1, 0, 1.0), (0, -1, 1.0), (-1, -1, diagonal), (1, -1, diagonal)];
+    let backward = [(1, 0, 1.0), (0, 1, 1.0), (1, 1, diagonal), (-1, 1, diagonal)];
+
+    for y in 0..height {
+        for x in 0..width {
+            relax(&mut dist, x, y, &forward);
+        }
+    }
+    for y in (0..height).rev() {
+        for x in (0..width).rev() {
+            relax(&mut dist, x, y, &backward);
+        }
+    }
+
+    dist
+}
+
+fn relax(dist: &mut Grid<f32>, x: i32, y: i32, offsets: &[(i32, i32, f32)]) {
+    let mut best = dist.get(x as u32, y as u32);
+    for (dx, dy, cost) in offsets {
+        let nx = x + dx;
+        let ny = y + dy;
+        if dist.in_bounds(nx, ny) {
+            best = best.min(dist.get(nx as u32, ny as u32) + cost);
+        }
+    }
+    dist.set(x as u32, y as u32, best);
+}
+
+pub fn distance_to_segment(p: Vertex, line: &Line) -> f32 {
+    let p = p.as_pos();
+    let a = line.get_start().as_pos();
+    let b = line.get_end().as_pos();
+    let ab = b - a;
+    let len_sq = ab.length_sq();
+    if len_sq == 0.0 {
+        return p.distance(a);
+    }
+    let t = ((p - a).dot(ab) / len_sq).clamp(0.0, 1.0);
+    p.distance(a + ab * t)
+}
+
+/// Land wherever the heightmap rises above `sea_level`, so the coast is that iso-contour.
+pub fn sea_level_mask(heightmap: &Grid<f32>, sea_level: f32) -> Grid<bool> {
+    let mut mask = Grid::new(heightmap.width, heightmap.height, false);
+    for i in 0..mask.len() {
+        mask.data[i] = heightmap.data[i] > sea_level;
+    }
+    mask
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CoastStats {
+    /// Share of the world above sea level.
+    pub(crate) land_fraction: f32,
+    pub(crate) islands: usize,
+    /// Length of every coastline in kilometres.
+    pub(crate) coastline_length: f32,
+}
+
+pub fn coast_stats(mask: &Grid<bool>, coastline: &[Line], top_left: (f32, f32), bottom_right: (f32, f32)) -> CoastStats {
+    let land = mask.data.iter().filter(|l| **l).count();
+    let islands = lakes::components(mask, &|i| mask.data[i]).len();
+    let coastline_length = coastline
+        .iter()
+        .map(|l| navigation::great_circle_distance(l.start, l.end, top_left, bottom_right))
+        .sum();
+    CoastStats {
+        land_fraction: land as f32 / mask.len().max(1) as f32,
+        islands,
+        coastline_length,
+    }
+}
+
+/// A named island or sea.
+#[derive(Clone, Debug)]
+pub struct Area {
+    pub(crate) name: String,
+    pub(crate) cells: usize,
+    pub(crate) centre: Vertex,
+    /// Where its cells are in the land mask.
+    pub(crate) indices: Vec<usize>,
+}
+
+/// Connected stretches of land (or of sea) of at least `MIN_NAMED_AREA` cells, largest first.
+pub fn find_areas(mask: &Grid<bool>, land: bool) -> Vec<Area> {
+    let mut areas: Vec<Area> = lakes::components(mask, &|i| mask.data[i] == land)
+        .into_iter()
+        .filter(|cells| cells.len() >= constants::MIN_NAMED_AREA)
+        .map(|cells| {
+            let (mut sx, mut sy) = (0.0, 0.0);
+            for i in &cells {
+                let (x, y) = mask.coords(*i);
+                sx += x as f32 + 0.5;
+                sy += y as f32 + 0.5;
+            }
+            let n = cells.len() as f32;
+            Area {
+                name: String::new(),
+                cells: cells.len(),
+                centre: utils::pixel_to_world(sx / n, sy / n, mask.width, mask.height),
+                indices: cells,
+            }
+        })
+        .collect();
+    areas.sort_by_key(|a| std::cmp::Reverse(a.cells));
+    areas
+}
+
+/// Gives areas the names of the old areas they overlap, for when the coast is extracted
+/// again from the same world. An old name goes to the area with most of its cells, so an
+/// island split in two by rising seas keeps its name on the larger part.
+pub fn carry_names(old: &[Area], new: &mut [Area]) {
+    let mut owner = HashMap::new();
+    for (i, area) in old.iter().enumerate() {
+        for cell in &area.indices {
+            owner.insert(*cell, i);
+        }
+    }
+    // every pairing of an old and a new area that share cells, most shared first
+    let mut overlaps = Vec::new();
+    for (j, area) in new.iter().enumerate() {
+        let mut shared: HashMap<usize, usize> = HashMap::new();
+        for i in area.indices.iter().filter_map(|c| owner.get(c)) {
+            *shared.entry(*i).or_default() += 1;
+        }
+        overlaps.extend(shared.into_iter().map(|(i, count)| (count, i, j)));
+    }
+    overlaps.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut named = vec![false; new.len()];
+    let mut taken = vec![false; old.len()];
+    for (_, i, j) in overlaps {
+        if !named[j] && !taken[i] {
+            new[j].name = old[i].name.clone();
+            named[j] = true;
+            taken[i] = true;
+        }
+    }
+}
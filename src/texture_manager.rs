@@ -1,17 +1,91 @@
-use std::path::Path;
+use std::{path::Path, sync::Arc};
 
 use egui::{Context, ColorImage, TextureHandle};
-use glium::{Display, texture::SrgbTexture2d};
-use image::{DynamicImage, GenericImageView, ColorType, buffer::ConvertBuffer};
+use glium::{Display, texture::{MipmapsOption, SrgbTexture2d}};
+use image::{DynamicImage, ColorType, buffer::ConvertBuffer, RgbaImage, Rgba, imageops};
 
+use crate::{grid::Grid, climate::{Climate, ClimateView}, constants, geometry::Vertex};
 
+
+const LAND_COLOUR: Rgba<u8> = Rgba([120, 160, 90, 255]);
+
+/// An image too big to upload in one piece. It is kept as a pyramid of ever smaller
+/// copies, each cut into tiles that are uploaded with mipmaps only once they're seen.
 pub struct TextureData {
-    pub(crate) vertex_texture: SrgbTexture2d,
+    /// The full image first, then each halving of it down to one that fits in a tile.
+    /// Shared, so that exports can read it on another thread.
+    pub(crate) levels: Arc<Vec<RgbaImage>>,
+    /// A copy small enough for the gui to show.
     pub(crate) gui_texture: TextureHandle,
 }
 
 impl TextureData {
-    
+    pub fn width(&self) -> u32 {
+        self.levels[0].width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.levels[0].height()
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width() as f32 / self.height() as f32
+    }
+
+    /// The coarsest level whose pixels still show at least one screen pixel each, given
+    /// how many screen pixels a pixel of the full image covers.
+    pub fn level_for(&self, screen_pixels: f32) -> usize {
+        level_for(&self.levels, screen_pixels)
+    }
+
+    /// Tiles of a level overlapping a rectangle given in pixels of the full image.
+    pub fn tiles_in(&self, level: usize, min: (f32, f32), max: (f32, f32)) -> Vec<(u32, u32)> {
+        let image = &self.levels[level];
+        let tile = (constants::TEXTURE_TILE_SIZE << level) as f32;
+        let columns = image.width().div_ceil(constants::TEXTURE_TILE_SIZE);
+        let rows = image.height().div_ceil(constants::TEXTURE_TILE_SIZE);
+        let range = |low: f32, high: f32, count: u32| {
+            let first = (low / tile).floor().max(0.0) as u32;
+            let last = ((high / tile).ceil().max(0.0) as u32).min(count);
+            first..last
+        };
+        let mut tiles = Vec::new();
+        for ty in range(min.1, max.1, rows) {
+            for tx in range(min.0, max.0, columns) {
+                tiles.push((tx, ty));
+            }
+        }
+        tiles
+    }
+
+    /// Uploads one tile with its mipmaps, along with the two triangles that place it in
+    /// the world. Tiles carry a pixel of their neighbours around them so that filtering
+    /// doesn't leave seams.
+    pub fn upload_tile(&self, dis: &Display, level: usize, tx: u32, ty: u32) -> (SrgbTexture2d, Vec<Vertex>) {
+        let image = &self.levels[level];
+        let size = constants::TEXTURE_TILE_SIZE;
+        let (x0, y0) = (tx * size, ty * size);
+        let (x1, y1) = ((x0 + size).min(image.width()), (y0 + size).min(image.height()));
+        let (gx0, gy0) = (x0.saturating_sub(1), y0.saturating_sub(1));
+        let (gx1, gy1) = ((x1 + 1).min(image.width()), (y1 + 1).min(image.height()));
+
+        let pixels = imageops::crop_imm(image, gx0, gy0, gx1 - gx0, gy1 - gy0).to_image();
+        let dimensions = pixels.dimensions();
+        let raw = glium::texture::RawImage2d::from_raw_rgba_reversed(&pixels.into_raw(), dimensions);
+        let texture = SrgbTexture2d::with_mipmaps(dis, raw, MipmapsOption::AutoGeneratedMipmaps).unwrap();
+
+        // texture rows run bottom up
+        let corner = |x: u32, y: u32| {
+            let u = (x - gx0) as f32 / (gx1 - gx0) as f32;
+            let v = (gy1 - y) as f32 / (gy1 - gy0) as f32;
+            // by fraction of the level rather than pixel, as halving odd sizes rounds down
+            let aspect = self.aspect_ratio();
+            let position = [(x as f32 / image.width() as f32 * 2.0 - 1.0) * aspect, 1.0 - y as f32 / image.height() as f32 * 2.0];
+            Vertex { position, tex_coords: [u, v] }
+        };
+        let quad = vec![corner(x0, y0), corner(x0, y1), corner(x1, y1), corner(x0, y0), corner(x1, y1), corner(x1, y0)];
+        (texture, quad)
+    }
 }
 
 pub fn get_dynamic_image( path: &String) -> DynamicImage {
@@ -21,26 +95,156 @@ pub fn get_dynamic_image( path: &String) -> DynamicImage {
 }
 
 
-pub fn get_texture_data(dis: &Display, egui_ctx: &Context, image: &DynamicImage) -> TextureData {
-    let size = [image.width() as _, image.height() as _];
-    let image = image.to_rgba8();
-    let pixels = image.as_flat_samples();
+/// Builds the pyramid of an image, which becomes its full size level rather than being copied.
+pub fn get_texture_data(egui_ctx: &Context, image: RgbaImage) -> TextureData {
+    let mut levels = vec![image];
+    while levels.last().unwrap().width().max(levels.last().unwrap().height()) > constants::TEXTURE_TILE_SIZE {
+        let half = halve(levels.last().unwrap());
+        levels.push(half);
+    }
+
+    let preview = levels
+        .iter()
+        .find(|l| l.width().max(l.height()) <= constants::GUI_TEXTURE_SIZE)
+        .unwrap_or(levels.last().unwrap());
+    let size = [preview.width() as _, preview.height() as _];
+    let pixels = preview.as_flat_samples();
     let eg_texture = egui::ColorImage::from_rgba_unmultiplied(
         size,
         pixels.as_slice(),
     );
 
-    let image_dimensions = image.dimensions();
+    let handle = egui_ctx.load_texture("test", eg_texture, egui::TextureFilter::Nearest);
+    
+   // return texture;
+   TextureData { levels: Arc::new(levels), gui_texture: handle }
+}
 
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
+/// `TextureData::level_for`, for a pyramid on its own.
+pub fn level_for(levels: &[RgbaImage], screen_pixels: f32) -> usize {
+    if screen_pixels <= 0.0 {
+        return levels.len() - 1;
+    }
+    let level = (1.0 / screen_pixels).log2().floor().max(0.0) as usize;
+    level.min(levels.len() - 1)
+}
 
-    let texture = glium::texture::SrgbTexture2d::new(dis, image).unwrap();
+/// Half the size, each pixel the average of the four it replaces, weighted by their alpha
+/// so that transparent sea doesn't darken the coast.
+fn halve(image: &RgbaImage) -> RgbaImage {
+    let width = (image.width() / 2).max(1);
+    let height = (image.height() / 2).max(1);
+    let mut half = RgbaImage::new(width, height);
+    for (x, y, pixel) in half.enumerate_pixels_mut() {
+        let mut sum = [0u32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let source = image.get_pixel((x * 2 + dx).min(image.width() - 1), (y * 2 + dy).min(image.height() - 1)).0;
+            let alpha = source[3] as u32;
+            for c in 0..3 {
+                sum[c] += source[c] as u32 * alpha;
+            }
+            sum[3] += alpha;
+        }
+        // fully transparent pixels summed to nothing, so stay black
+        let alpha = sum[3].max(1);
+        *pixel = Rgba([(sum[0] / alpha) as u8, (sum[1] / alpha) as u8, (sum[2] / alpha) as u8, (sum[3] / 4) as u8]);
+    }
+    half
+}
 
-    //let eg_texture = ColorImage::from_rgba_unmultiplied(image_dimensions, rgba);
+/// Greyscale land over blue sea, for previewing a heightmap in the gui.
+pub fn get_heightmap_preview(egui_ctx: &Context, heightmap: &Grid<f32>) -> TextureHandle {
+    let (min, max) = heightmap.min_max();
+    let mut pixels = Vec::with_capacity(heightmap.len());
+    for h in &heightmap.data {
+        if *h > 0.0 {
+            let v = (h / max.max(1.0) * 255.0) as u8;
+            pixels.push(egui::Color32::from_rgb(v, v, v));
+        } else {
+            let v = (255.0 - h / min.min(-1.0) * 200.0) as u8;
+            pixels.push(egui::Color32::from_rgb(0, v / 3, v));
+        }
+    }
+    let image = ColorImage {
+        size: [heightmap.width as usize, heightmap.height as usize],
+        pixels,
+    };
+    egui_ctx.load_texture("heightmap", image, egui::TextureFilter::Linear)
+}
 
-    let handle = egui_ctx.load_texture("test", eg_texture, egui::TextureFilter::Nearest);
-    
-   // return texture;
-   TextureData { vertex_texture: texture, gui_texture: handle }
-}
\ No newline at end of file
+/// A base image for a generated land mask: land is opaque, the sea transparent.
+pub fn image_from_mask(mask: &Grid<bool>) -> DynamicImage {
+    let mut image = RgbaImage::new(mask.width, mask.height);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if mask.get(x, y) {
+            *pixel = LAND_COLOUR;
+        } else {
+            *pixel = Rgba([0, 0, 0, 0]);
+        }
+    }
+    DynamicImage::ImageRgba8(image)
+}
+
+/// The base image cut to a new land mask, with land that was sea in the base image
+/// filled in plainly.
+pub fn image_at_sea_level(base: &RgbaImage, mask: &Grid<bool>) -> RgbaImage {
+    let mut image = RgbaImage::new(mask.width, mask.height);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let original = *base.get_pixel(x, y);
+        *pixel = if !mask.get(x, y) {
+            Rgba([0, 0, 0, 0])
+        } else if original.0[3] != 0 {
+            original
+        } else {
+            LAND_COLOUR
+        };
+    }
+    image
+}
+
+/// Temperature (blue to red) or precipitation (brown to blue) for one month, sea ice in white.
+pub fn get_climate_preview(egui_ctx: &Context, climate: &Climate, month: usize, view: ClimateView) -> TextureHandle {
+    let mut pixels = Vec::with_capacity((climate.width * climate.height) as usize);
+    for y in 0..climate.height {
+        for x in 0..climate.width {
+            if climate.sea_ice(month, x, y) {
+                pixels.push(egui::Color32::WHITE);
+                continue;
+            }
+            let color = match view {
+                ClimateView::Temperature => {
+                    let t = ((climate.temperature(month, x, y) + 30.0) / 60.0).clamp(0.0, 1.0);
+                    egui::Color32::from_rgb((t * 255.0) as u8, 60, ((1.0 - t) * 255.0) as u8)
+                }
+                ClimateView::Precipitation => {
+                    let p = (climate.precipitation(month, x, y) / 300.0).clamp(0.0, 1.0);
+                    egui::Color32::from_rgb(((1.0 - p) * 160.0) as u8, (80.0 + p * 100.0) as u8, (p * 255.0) as u8)
+                }
+            };
+            pixels.push(color);
+        }
+    }
+    let image = ColorImage {
+        size: [climate.width as usize, climate.height as usize],
+        pixels,
+    };
+    egui_ctx.load_texture("climate", image, egui::TextureFilter::Linear)
+}
+
+/// The colour between the pixels around `(x, y)`, each channel `0.0..=1.0`. The image
+/// wraps around from its right edge to its left, as the globe does.
+pub fn sample_bilinear(image: &RgbaImage, x: f32, y: f32) -> [f32; 4] {
+    let y = y.clamp(0.0, (image.height() - 1) as f32);
+    let (left, y0) = (x.floor(), y.floor() as u32);
+    let x0 = (left as i64).rem_euclid(image.width() as i64) as u32;
+    let (x1, y1) = ((x0 + 1) % image.width(), (y0 + 1).min(image.height() - 1));
+    let (fx, fy) = (x - left, y - y0 as f32);
+    let mut color = [0.0; 4];
+    for (px, py, weight) in [(x0, y0, (1.0 - fx) * (1.0 - fy)), (x1, y0, fx * (1.0 - fy)), (x0, y1, (1.0 - fx) * fy), (x1, y1, fx * fy)] {
+        let pixel = image.get_pixel(px, py).0;
+        for c in 0..4 {
+            color[c] += pixel[c] as f32 / 255.0 * weight;
+        }
+    }
+    color
+}
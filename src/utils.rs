@@ -37,6 +37,27 @@ pub fn vertices_from_lines(thickness: f32, lines: &Vec<Line>) -> Vec<Vertex>{
     points
 }
 
+/// Like `vertices_from_lines`, but the thickness changes along the polyline.
+/// `widths` holds the thickness at every point, so it is one longer than `lines`.
+pub fn vertices_from_tapered_lines(lines: &[Line], widths: &[f32]) -> Vec<Vertex>{
+    let mut points = Vec::new();
+    for (i, cur_line) in lines.iter().enumerate() {
+        let start_width = widths[i];
+        let end_width = widths[(i + 1).min(widths.len() - 1)];
+        let top_left = normal_point_to_point(cur_line.get_start().as_vector(), cur_line.get_rise(), cur_line.get_run(), start_width / 2.0);
+        let bot_left = normal_point_to_point(cur_line.get_start().as_vector(), cur_line.get_rise(), cur_line.get_run(), -start_width / 2.0);
+        let top_right = normal_point_to_point(cur_line.get_end().as_vector(), cur_line.get_rise(), cur_line.get_run(), end_width / 2.0);
+        let bot_right = normal_point_to_point(cur_line.get_end().as_vector(), cur_line.get_rise(), cur_line.get_run(), -end_width / 2.0);
+        points.push(Vertex::from_vector(top_left));
+        points.push(Vertex::from_vector(bot_left));
+        points.push(Vertex::from_vector(bot_right));
+        points.push(Vertex::from_vector(bot_right));
+        points.push(Vertex::from_vector(top_right));
+        points.push(Vertex::from_vector(top_left));
+    }
+    points
+}
+
 pub fn vertices_from_line_points(lines: &Vec<Line>) -> Vec<Vertex>{
     let mut points = Vec::new();
     for line in lines {
@@ -60,4 +81,62 @@ pub fn world_point_to_screen_point(world: Vertex, width: u32, height: u32, zoom:
     let x = (((world.x + (-1.0 + 1.0 / zoom) + (normx * 2.0)) * zoom) / 2.0) * width as f32;
     let y = -((((world.y - (-1.0 + 1.0 / zoom) / aspect + (normy * 2.0 - 2.0) / aspect) * zoom) * aspect / 2.0) * height as f32);
     Vertex{position: [x,y], tex_coords: [x,y]}
-}
\ No newline at end of file
+}
+
+pub fn pixel_to_world(px: f32, py: f32, width: u32, height: u32) -> Vertex {
+    let aspect = width as f32 / height as f32;
+    let x = ((px / width as f32) * 2.0 - 1.0) * aspect;
+    let y = -(py / height as f32) * 2.0 + 1.0;
+    Vertex{position: [x,y], tex_coords: [x,y]}
+}
+
+/// Latitude in degrees of a pixel row, with the image spanning pole to pole.
+pub fn row_latitude(py: f32, height: u32) -> f32 {
+    90.0 - py / height as f32 * 180.0
+}
+
+/// The pixel a world point falls in, the inverse of `pixel_to_world`.
+pub fn world_to_pixel(world: Vertex, width: u32, height: u32) -> (f32, f32) {
+    let aspect = width as f32 / height as f32;
+    let px = (world.position[0] / aspect + 1.0) / 2.0 * width as f32;
+    let py = (1.0 - world.position[1]) / 2.0 * height as f32;
+    (px, py)
+}
+
+/// Longitude and latitude in degrees of a world point, with the world spanning the whole globe.
+pub fn world_to_lon_lat(world: Vertex, top_left: (f32, f32), bottom_right: (f32, f32)) -> (f32, f32) {
+    let u = (world.position[0] - top_left.0) / (bottom_right.0 - top_left.0);
+    let v = (world.position[1] - top_left.1) / (bottom_right.1 - top_left.1);
+    (u * 360.0 - 180.0, 90.0 - v * 180.0)
+}
+
+/// The world point at a longitude and latitude in degrees, the inverse of `world_to_lon_lat`.
+pub fn lon_lat_to_world(lon: f32, lat: f32, top_left: (f32, f32), bottom_right: (f32, f32)) -> Vertex {
+    let u = (lon + 180.0) / 360.0;
+    let v = (90.0 - lat) / 180.0;
+    let x = top_left.0 + u * (bottom_right.0 - top_left.0);
+    let y = top_left.1 + v * (bottom_right.1 - top_left.1);
+    Vertex { position: [x, y], tex_coords: [x, y] }
+}
+
+/// A latitude as degrees and minutes north or south, like `23°26'N`.
+pub fn format_latitude(lat: f32) -> String {
+    format_degrees(lat.abs(), if lat < 0.0 { 'S' } else { 'N' })
+}
+
+/// A longitude as degrees and minutes east or west.
+pub fn format_longitude(lon: f32) -> String {
+    format_degrees(lon.abs(), if lon < 0.0 { 'W' } else { 'E' })
+}
+
+fn format_degrees(degrees: f32, hemisphere: char) -> String {
+    let minutes = (degrees * 60.0).round() as u32;
+    if minutes == 0 {
+        // the equator and the prime meridian are in neither hemisphere
+        "0°".to_string()
+    } else if minutes.is_multiple_of(60) {
+        format!("{}°{}", minutes / 60, hemisphere)
+    } else {
+        format!("{}°{:02}'{}", minutes / 60, minutes % 60, hemisphere)
+    }
+}
@@ -0,0 +1,87 @@
+use rand::Rng;
+
+use crate::{constants, grid::Grid, noise::Noise, seed};
+
+#[derive(Copy, Clone, Debug)]
+pub struct WorldGenSettings {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) continents: u32,
+    /// Share of the world covered by land, `0.0..=1.0`.
+    pub(crate) land_fraction: f32,
+    /// How much the noise is pushed around by itself, 0 gives plain fBm.
+    pub(crate) warp: f32,
+}
+
+impl Default for WorldGenSettings {
+    fn default() -> Self {
+        WorldGenSettings {
+            width: 1024,
+            height: 512,
+            continents: 3,
+            land_fraction: 0.3,
+            warp: 0.5,
+        }
+    }
+}
+
+/// Makes a land mask from domain-warped fractal noise, pulled up around a number of
+/// continent centres and thresholded so that the requested share of it is land.
+pub fn generate_land_mask(settings: &WorldGenSettings, world_seed: u64) -> Grid<bool> {
+    let width = settings.width;
+    let height = settings.height;
+    let size = width.max(height) as f32;
+    let mut rng = seed::rng(world_seed, "worldgen");
+    let noise = Noise::new(seed::derive(world_seed, "worldgen-noise"));
+
+    // continent centres stay away from the edges of the map
+    let mut centres = Vec::new();
+    for _ in 0..settings.continents {
+        let x = rng.gen_range(0.15..0.85) * width as f32;
+        let y = rng.gen_range(0.2..0.8) * height as f32;
+        let radius = rng.gen_range(0.1..0.25) * size;
+        centres.push((x, y, radius));
+    }
+
+    let frequency = constants::WORLDGEN_FREQUENCY / size;
+    let mut field = Grid::new(width, height, 0.0);
+    for y in 0..height {
+        for x in 0..width {
+            let fx = x as f32 * frequency;
+            let fy = y as f32 * frequency;
+            let qx = noise.fbm(fx, fy, 4, 2.0, 0.5) * 2.0 - 1.0;
+            let qy = noise.fbm(fx + 5.2, fy + 1.3, 4, 2.0, 0.5) * 2.0 - 1.0;
+            let mut value = noise.fbm(fx + settings.warp * 4.0 * qx, fy + settings.warp * 4.0 * qy, 6, 2.0, 0.5);
+
+            if !centres.is_empty() {
+                let mut continent: f32 = 0.0;
+                for (cx, cy, radius) in &centres {
+                    let d = ((x as f32 - cx).powi(2) + (y as f32 - cy).powi(2)).sqrt() / radius;
+                    continent = continent.max((-d * d).exp());
+                }
+                value = value * 0.5 + continent * 0.5;
+            }
+
+            // fade out towards the map border so land doesn't get cut off
+            let ex = (x.min(width - 1 - x) as f32 / (width as f32 * 0.05)).min(1.0);
+            let ey = (y.min(height - 1 - y) as f32 / (height as f32 * 0.05)).min(1.0);
+            field.set(x, y, value * ex.min(ey));
+        }
+    }
+
+    // pick the threshold that gives the requested amount of land
+    let mut sorted = field.data.clone();
+    sorted.sort_by(|a, b| b.total_cmp(a));
+    let land_cells = (settings.land_fraction.clamp(0.0, 1.0) * sorted.len() as f32) as usize;
+    let threshold = if land_cells == 0 {
+        f32::MAX
+    } else {
+        sorted[land_cells.min(sorted.len()) - 1]
+    };
+
+    let mut mask = Grid::new(width, height, false);
+    for i in 0..mask.len() {
+        mask.data[i] = field.data[i] >= threshold;
+    }
+    mask
+}